@@ -0,0 +1,314 @@
+// compressor/decompressor for a sequence of u32 values using the adaptive range coder
+//
+// the stream has the same header as prime_codec, with mode ArithU32Values, followed by
+// the range coder's bytes. each value is coded as its kind, then the fields of that kind,
+// and every field has its own adaptive model, so the skewed distributions of the
+// prime power count and the exponents cost fractional bits:
+//   kind               3-bit bit tree, see ValueKind
+//   prime power count  count - 1 for Indices, count for BigCofactor (may be 0)
+//   exponents          exponent - 1 for each prime power
+//   index deltas       first prime index, then difference from the previous one,
+//                      with separate models since the first is much smaller
+//   cofactor           BigCofactor only, the part of the value the table cannot factor
+// the End kind ends the stream.
+
+use crate::encode_prime::{factors_to_int_as_prms, Factorization};
+use crate::prime_codec::{PrimeStreamErrcode, PrimeStreamHeader, PrimeStreamMode, PRIME_STREAM_HEADER_LEN, PRIME_STREAM_VERSION};
+use crate::prime_table::PrimeTable;
+use crate::primes::factorization_of;
+use crate::range_coder::{BitModel, RangeDecoder, RangeEncoder, UintModel};
+
+const KIND_TREE_BITS: u32 = 3;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub enum ValueKind {
+    Indices = 0,
+    BigCofactor = 1,
+    Zero = 2,
+    One = 3,
+    End = 4,
+}
+
+impl ValueKind {
+    pub fn from_u32(kind_code: u32) -> Option<Self> {
+        match kind_code {
+            0 => Some(ValueKind::Indices),
+            1 => Some(ValueKind::BigCofactor),
+            2 => Some(ValueKind::Zero),
+            3 => Some(ValueKind::One),
+            4 => Some(ValueKind::End),
+            _ => None
+        }
+    }
+}
+
+// one adaptive model per field, the compressor and decompressor must start with the same ones
+#[derive(Clone)]
+pub struct FactorFieldModels {
+    kind : Vec<BitModel>,
+    count : UintModel,
+    exponent : UintModel,
+    first_index : UintModel,
+    index_delta : UintModel,
+    cofactor : UintModel,
+}
+
+impl Default for FactorFieldModels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FactorFieldModels {
+    pub fn new() -> Self {
+        FactorFieldModels {
+            kind: vec![BitModel::default(); 1 << KIND_TREE_BITS],
+            count: UintModel::new(),
+            exponent: UintModel::new(),
+            first_index: UintModel::new(),
+            index_delta: UintModel::new(),
+            cofactor: UintModel::new(),
+        }
+    }
+
+    fn encode_prime_powers(&mut self, enc : &mut RangeEncoder, ixs : &[u32], count_offset : u32) {
+        if ixs.is_empty() {
+            self.count.encode(enc, 0);
+            return;
+        }
+        let iap = factors_to_int_as_prms(ixs);
+        self.count.encode(enc, iap.prm_powers.len() as u32 - count_offset);
+        for ppwr in iap.prm_powers.iter() {
+            self.exponent.encode(enc, ppwr.exp as u32 - 1);
+        }
+        let mut prev_index: u32 = 0;
+        for (k, ppwr) in iap.prm_powers.iter().enumerate() {
+            let model = if k == 0 { &mut self.first_index } else { &mut self.index_delta };
+            model.encode(enc, ppwr.prm_idx - prev_index);
+            prev_index = ppwr.prm_idx;
+        }
+    }
+
+    fn decode_prime_powers(&mut self, dec : &mut RangeDecoder, count_offset : u32) -> Result<Vec<u32>, PrimeStreamErrcode> {
+        let count = self.count.decode(dec)? as u64 + count_offset as u64;
+        if count > u32::BITS as u64 {
+            return Err(PrimeStreamErrcode::BadEncoding);
+        }
+        let mut exponents: Vec<u32> = vec![];
+        for _k in 0..count {
+            let exponent = self.exponent.decode(dec)? as u64 + 1;
+            if exponent > u32::BITS as u64 {
+                return Err(PrimeStreamErrcode::BadEncoding);
+            }
+            exponents.push(exponent as u32);
+        }
+        let mut ixs: Vec<u32> = vec![];
+        let mut prev_index: u32 = 0;
+        for (k, exponent) in exponents.into_iter().enumerate() {
+            let model = if k == 0 { &mut self.first_index } else { &mut self.index_delta };
+            prev_index = match prev_index.checked_add(model.decode(dec)?) {
+                Some(ix) => ix,
+                None => { return Err(PrimeStreamErrcode::BadPrimeIndex); }
+            };
+            for _j in 0..exponent {
+                ixs.push(prev_index);
+            }
+        }
+        Ok(ixs)
+    }
+
+    pub fn encode_factorization(&mut self, enc : &mut RangeEncoder, f : &Factorization<u32>) {
+        match f {
+            Factorization::Zero => enc.encode_bit_tree(&mut self.kind, ValueKind::Zero as u32, KIND_TREE_BITS),
+            Factorization::One => enc.encode_bit_tree(&mut self.kind, ValueKind::One as u32, KIND_TREE_BITS),
+            Factorization::Indices(ixs) => {
+                // the count is written less 1, same as encode_factors(), so there must be a prime
+                assert!(!ixs.is_empty());
+                enc.encode_bit_tree(&mut self.kind, ValueKind::Indices as u32, KIND_TREE_BITS);
+                self.encode_prime_powers(enc, ixs, 1);
+            }
+            Factorization::BigCofactor { ixs, cofactor } => {
+                enc.encode_bit_tree(&mut self.kind, ValueKind::BigCofactor as u32, KIND_TREE_BITS);
+                self.encode_prime_powers(enc, ixs, 0);
+                self.cofactor.encode(enc, *cofactor);
+            }
+        }
+    }
+
+    pub fn encode_end(&mut self, enc : &mut RangeEncoder) {
+        enc.encode_bit_tree(&mut self.kind, ValueKind::End as u32, KIND_TREE_BITS);
+    }
+
+    // next factorization, None at end of stream
+    pub fn decode_factorization(&mut self, dec : &mut RangeDecoder) -> Result<Option<Factorization<u32>>, PrimeStreamErrcode> {
+        let kind_code = dec.decode_bit_tree(&mut self.kind, KIND_TREE_BITS);
+        let f = match ValueKind::from_u32(kind_code) {
+            None => { return Err(PrimeStreamErrcode::BadEncoding); }
+            Some(ValueKind::End) => None,
+            Some(ValueKind::Zero) => Some(Factorization::Zero),
+            Some(ValueKind::One) => Some(Factorization::One),
+            Some(ValueKind::Indices) => Some(Factorization::Indices(self.decode_prime_powers(dec, 1)?)),
+            Some(ValueKind::BigCofactor) => {
+                let ixs = self.decode_prime_powers(dec, 0)?;
+                let cofactor = self.cofactor.decode(dec)?;
+                Some(Factorization::BigCofactor { ixs, cofactor })
+            }
+        };
+        dec.check_not_past_end()?;
+        Ok(f)
+    }
+}
+
+pub struct ArithCompressor<'a> {
+    prms : &'a PrimeTable,
+    encoder : RangeEncoder,
+    models : FactorFieldModels,
+}
+
+impl<'a> ArithCompressor<'a> {
+    // values are factored using prms
+    pub fn new(prms : &'a PrimeTable) -> Self {
+        ArithCompressor { prms, encoder: RangeEncoder::new(), models: FactorFieldModels::new() }
+    }
+
+    pub fn write_u32(&mut self, v : u32) {
+        let f = factorization_of(v, self.prms);
+        self.write_factorization(&f);
+    }
+
+    // for callers that factored the value themselves, such as without a prime table
+    // prime indexes must be ones that the decompressor's table contains
+    pub fn write_factorization(&mut self, f : &Factorization<u32>) {
+        self.models.encode_factorization(&mut self.encoder, f);
+    }
+
+    // end the stream and return it with its header
+    pub fn finish(mut self) -> Vec<u8> {
+        self.models.encode_end(&mut self.encoder);
+        let hdr = PrimeStreamHeader {
+            version: PRIME_STREAM_VERSION,
+            mode: PrimeStreamMode::ArithU32Values,
+            upper_bound: self.prms.upper_bound()
+        };
+        let mut out = hdr.to_bytes().to_vec();
+        out.extend(self.encoder.finish());
+        out
+    }
+}
+
+pub struct ArithDecompressor<'a> {
+    prms : &'a PrimeTable,
+    header : PrimeStreamHeader,
+    decoder : RangeDecoder<'a>,
+    models : FactorFieldModels,
+    ended : bool,
+}
+
+impl<'a> ArithDecompressor<'a> {
+    // check stream header, prms must be at least as big as the compressor's table
+    pub fn new(compressed : &'a [u8], prms : &'a PrimeTable) -> Result<Self, std::io::Error> {
+        if compressed.len() < PRIME_STREAM_HEADER_LEN {
+            return Err(PrimeStreamErrcode::Truncated.into());
+        }
+        let header = PrimeStreamHeader::from_bytes(&compressed[..PRIME_STREAM_HEADER_LEN])?;
+        if header.mode != PrimeStreamMode::ArithU32Values {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        if header.upper_bound > prms.upper_bound() {
+            return Err(PrimeStreamErrcode::TableTooSmall.into());
+        }
+        let decoder = RangeDecoder::new(&compressed[PRIME_STREAM_HEADER_LEN..]);
+        Ok(ArithDecompressor { prms, header, decoder, models: FactorFieldModels::new(), ended: false })
+    }
+
+    pub fn header(&self) -> &PrimeStreamHeader { &self.header }
+
+    // next factorization, None at end of stream
+    pub fn read_factorization(&mut self) -> Result<Option<Factorization<u32>>, std::io::Error> {
+        if self.ended {
+            return Ok(None);
+        }
+        let f = self.models.decode_factorization(&mut self.decoder)?;
+        self.ended = f.is_none();
+        Ok(f)
+    }
+
+    // next value, None at end of stream
+    pub fn read_u32(&mut self) -> Result<Option<u32>, std::io::Error> {
+        let (ixs, multiplier) = match self.read_factorization()? {
+            None => { return Ok(None); }
+            Some(Factorization::Zero) => { return Ok(Some(0)); }
+            Some(Factorization::One) => { return Ok(Some(1)); }
+            Some(Factorization::Indices(ixs)) => (ixs, 1),
+            Some(Factorization::BigCofactor { ixs, cofactor }) => (ixs, cofactor),
+        };
+        let mut v: u32 = multiplier;
+        for ix in ixs {
+            v = match self.prms.nth(ix as usize).and_then(|p| v.checked_mul(p)) {
+                Some(product) => product,
+                None => { return Err(PrimeStreamErrcode::BadPrimeIndex.into()); }
+            };
+        }
+        Ok(Some(v))
+    }
+}
+
+// compress a whole array of u32 values
+pub fn arith_compress_u32s(values : &[u32], prms : &PrimeTable) -> Vec<u8> {
+    let mut compressor = ArithCompressor::new(prms);
+    for v in values {
+        compressor.write_u32(*v);
+    }
+    compressor.finish()
+}
+
+// inverse of arith_compress_u32s()
+pub fn arith_decompress_u32s(compressed : &[u8], prms : &PrimeTable) -> Result<Vec<u32>, std::io::Error> {
+    let mut decompressor = ArithDecompressor::new(compressed, prms)?;
+    let mut values: Vec<u32> = vec![];
+    while let Some(v) = decompressor.read_u32()? {
+        values.push(v);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::prime_codec::compress_u32s;
+    use rand::Rng;
+
+    #[test]
+    pub fn test_arith_round_trip() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut values: Vec<u32> = (0..3000).collect();
+        values.extend([u32::MAX, 4294967291, 65521 * 65521, 1 << 31]);
+        let mut rng = rand::rng();
+        values.extend((0..3000).map(|_| rng.random::<u32>()));
+        let compressed = arith_compress_u32s(&values, &prms);
+        assert_eq!(arith_decompress_u32s(&compressed, &prms).unwrap(), values);
+        assert_eq!(arith_decompress_u32s(&arith_compress_u32s(&[], &prms), &prms).unwrap(), vec![]);
+
+        // adaptive models should beat the prefix codes on these
+        assert!(compressed.len() < compress_u32s(&values, &prms).unwrap().len());
+
+        // a table that is too small for some values still works, they just have big cofactors
+        let small_prms = PrimeTable::up_to(100);
+        let compressed = arith_compress_u32s(&values, &small_prms);
+        assert_eq!(arith_decompress_u32s(&compressed, &prms).unwrap(), values);
+    }
+
+    #[test]
+    pub fn test_arith_errors() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let values: Vec<u32> = (0..1000).collect();
+        let compressed = arith_compress_u32s(&values, &prms);
+        let error_kind = |bytes: &[u8], prms: &PrimeTable| arith_decompress_u32s(bytes, prms).unwrap_err().kind();
+        assert_eq!(error_kind(&compressed[..compressed.len() / 2], &prms), std::io::ErrorKind::InvalidData);
+        assert_eq!(error_kind(&compressed[..5], &prms), std::io::ErrorKind::InvalidData);
+        assert_eq!(error_kind(&compressed, &PrimeTable::up_to(1000)), std::io::ErrorKind::InvalidInput);
+        assert_eq!(error_kind(&compress_u32s(&values, &prms).unwrap(), &prms), std::io::ErrorKind::InvalidInput);
+    }
+}
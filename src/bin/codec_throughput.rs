@@ -0,0 +1,199 @@
+// throughput of the factor codec, in encoded bits per second
+//
+// encodes the prime indexes of SAMPLES random values up to LARGEST_UINT into one
+// buffer and decodes them again, with the codes in LENGTH_CODE, EXPONENT_CODE and
+// INDEX_DELTA_CODE. factoring is done up front and is not timed.
+// for comparison it also decodes the same buffer reading one bit per call, the way
+// the U32 and SmallInt encodings did before they read a word at a time, and copies
+// the encoded buffer one bit per call and a word at a time.
+// the bit-by-bit decode only knows the U32 and SmallInt codes, for other codes the
+// rates are given relative to the bit-by-bit copy instead.
+// this only reports timings, the numbers depend too much on the machine to pass or fail on.
+
+use bitstring::BitString;
+use rand::Rng;
+use compress_wi_primes::bit_stream::{BitReader, BitWriter};
+use compress_wi_primes::dyn_bit_string::DynBitString;
+use compress_wi_primes::encoding_uint_trait::{ContinueOffsets, DecodeError, BITSTRING_END};
+use compress_wi_primes::encode_prime::{factors_to_int_as_prms, try_read_factors_into, write_factors_with, FactorCodes, DEFAULT_FACTOR_CODES};
+use compress_wi_primes::get_env_var;
+use compress_wi_primes::prime_table::PrimeTable;
+use compress_wi_primes::primes::factor_uint;
+use compress_wi_primes::uint_code::{is_index_delta_code, UintCode};
+use compress_wi_primes::uint_width::UintWidth;
+use std::time::{Duration, SystemTime};
+
+// millions of bits per second
+fn mbits_per_sec( bits : usize, d : Duration ) -> f64 {
+    bits as f64 / d.as_secs_f64().max(1e-9) / 1e6
+}
+
+// a field with continue bits after the given offsets, read one bit per call
+fn read_continued_field_bit_by_bit( r : &mut BitReader, field_bits : u32, offsets : ContinueOffsets ) -> Result<u32, DecodeError> {
+    let mut v : u32 = 0;
+    for k in 0..field_bits {
+        v |= (r.try_read_bit()? as u32) << k;
+        if k + 1 < field_bits && offsets.contains(k) && r.try_read_bit()? == BITSTRING_END {
+            break;
+        }
+    }
+    Ok(v)
+}
+
+// a u32 in the U32 or SmallInt code, read one bit per call, None for any other code
+fn read_code_bit_by_bit( r : &mut BitReader, code : UintCode ) -> Option<Result<u32, DecodeError>> {
+    match code {
+        UintCode::SmallInt(offsets) => Some(read_continued_field_bit_by_bit(r, u32::LEN_BITS, offsets)),
+        UintCode::U32(offsets) => Some(read_continued_field_bit_by_bit(r, u32::LEN_BITS, offsets).and_then(|len_bitct| {
+            let mut v : u32 = 0;
+            for k in 0..=len_bitct {
+                v |= (r.try_read_bit()? as u32) << k;
+            }
+            Ok(v)
+        })),
+        _ => None,
+    }
+}
+
+// same as try_read_factors_into(), reading one bit per call
+fn read_factors_bit_by_bit( r : &mut BitReader, codes : &FactorCodes, factors : &mut Vec<u32> ) -> Option<Result<(), DecodeError>> {
+    factors.clear();
+    let l = match read_code_bit_by_bit(r, codes.length)? {
+        Ok(l) => l + 1,
+        Err(e) => { return Some(Err(e)); }
+    };
+    let mut exponents : Vec<u32> = Vec::with_capacity(l as usize);
+    for _k in 0..l {
+        match read_code_bit_by_bit(r, codes.exponent)? {
+            Ok(e) => exponents.push(e + 1),
+            Err(e) => { return Some(Err(e)); }
+        }
+    }
+    let mut prm_index : u32 = 0;
+    for e in exponents {
+        match read_code_bit_by_bit(r, codes.index_delta)? {
+            Ok(index_delta) => prm_index += index_delta,
+            Err(e) => { return Some(Err(e)); }
+        }
+        factors.extend(std::iter::repeat_n(prm_index, e as usize));
+    }
+    Some(Ok(()))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    use compress_wi_primes::get_env_var::get_env_var_u32_with_default;
+
+    let u32_env_var = |var_name: &str, default_value: u32| {
+        match get_env_var_u32_with_default(var_name, default_value) {
+            Ok(v) => v,
+            Err(e) => { get_env_var::env_var_usage(e, &var_name.to_string()); default_value }
+        }
+    };
+    let samples = u32_env_var("SAMPLES", 1000000);
+    println!("samples : {}", samples);
+    let largest_uint32 = u32_env_var("LARGEST_UINT", 1 << 24);
+    if largest_uint32 < 2 {
+        println!("ERROR: LARGEST_UINT : must be at least 2, the smallest value with a prime factor");
+        std::process::exit(1);
+    }
+    println!("largest value : {}", largest_uint32);
+
+    let code_env_var = |var_name: &str, default_code: UintCode| {
+        match get_env_var::get_env_var_parsed_with_default(var_name, default_code) {
+            Ok(code) => code,
+            Err(e) => { get_env_var::env_var_usage(e, &var_name.to_string()); default_code }
+        }
+    };
+    let factor_codes = FactorCodes {
+        length: code_env_var("LENGTH_CODE", DEFAULT_FACTOR_CODES.length),
+        exponent: code_env_var("EXPONENT_CODE", DEFAULT_FACTOR_CODES.exponent),
+        index_delta: code_env_var("INDEX_DELTA_CODE", DEFAULT_FACTOR_CODES.index_delta),
+    };
+    println!("factor codes : length {} exponent {} index delta {}",
+             factor_codes.length, factor_codes.exponent, factor_codes.index_delta);
+    if !is_index_delta_code(factor_codes.index_delta) {
+        println!("ERROR: INDEX_DELTA_CODE : {} would take as many bits as a prime index difference", factor_codes.index_delta);
+        std::process::exit(1);
+    }
+
+    let prms = PrimeTable::up_to(largest_uint32);
+    let mut rng = rand::rng();
+    // all the prime indexes in one vector, so the timing is not of chasing pointers
+    let mut all_ixs : Vec<u32> = Vec::new();
+    let mut ends : Vec<usize> = Vec::new();
+    for _k in 0..samples {
+        all_ixs.extend(factor_uint(rng.random_range(2..=largest_uint32), &prms).unwrap());
+        ends.push(all_ixs.len());
+    }
+    let factors = || ends.iter().scan(0, |start, end| {
+        let f = &all_ixs[*start..*end];
+        *start = *end;
+        Some(f)
+    });
+
+    // a length, and an exponent and index difference for each prime power
+    let field_count : usize = factors().map(|f| 1 + 2 * factors_to_int_as_prms(f).prm_powers.len()).sum();
+
+    let time_before_encode = SystemTime::now();
+    let mut bs = DynBitString::null();
+    let mut w = BitWriter::new(&mut bs);
+    for f in factors() {
+        write_factors_with(&mut w, f, &factor_codes);
+    }
+    let encode_time = SystemTime::now().duration_since(time_before_encode)?;
+    let total_bits = bs.len();
+    println!("encoded bits : {} ({:.3} per value, {} fields)", total_bits, total_bits as f64 / samples as f64, field_count);
+
+    let time_before_decode = SystemTime::now();
+    let mut r = BitReader::new(&bs);
+    let mut decoded : Vec<u32> = Vec::new();
+    for f in factors() {
+        try_read_factors_into(&mut r, &factor_codes, &mut decoded)?;
+        assert_eq!(decoded, f);
+    }
+    let decode_time = SystemTime::now().duration_since(time_before_decode)?;
+    assert!(r.is_at_end());
+
+    let time_before_bit_decode = SystemTime::now();
+    let mut r = BitReader::new(&bs);
+    let mut bit_decode_time = None;
+    for f in factors() {
+        match read_factors_bit_by_bit(&mut r, &factor_codes, &mut decoded) {
+            Some(result) => { result?; }
+            None => { break; }
+        }
+        assert_eq!(decoded, f);
+    }
+    if r.is_at_end() {
+        bit_decode_time = Some(SystemTime::now().duration_since(time_before_bit_decode)?);
+    }
+
+    let time_before_bit_copy = SystemTime::now();
+    let mut bit_copy = DynBitString::null();
+    for k in 0..bs.len() {
+        bit_copy.append(bs.get(k));
+    }
+    let bit_copy_time = SystemTime::now().duration_since(time_before_bit_copy)?;
+
+    let time_before_word_copy = SystemTime::now();
+    let mut word_copy = DynBitString::null();
+    word_copy.extend_from_bitslice(bs.slice(0..bs.len()));
+    let word_copy_time = SystemTime::now().duration_since(time_before_word_copy)?;
+    assert!(bit_copy == bs && word_copy == bs);
+
+    // rates are relative to the bit-by-bit decode, or the bit-by-bit copy when there is none
+    let (baseline_name, baseline_time) = match bit_decode_time {
+        Some(d) => ("bit-by-bit decode", d),
+        None => ("bit-by-bit copy", bit_copy_time),
+    };
+    let baseline_rate = mbits_per_sec(total_bits, baseline_time);
+    println!("{:>17} {:>10} {:>12} {:>20} {:>12}", "", "time", "Mbit/s", format!("x {}", baseline_name), "ns/field");
+    let bit_decode_row = bit_decode_time.map(|d| ("bit-by-bit decode", d));
+    for (name, d) in [("encode", encode_time), ("decode", decode_time)].into_iter().chain(bit_decode_row)
+                         .chain([("bit-by-bit copy", bit_copy_time), ("word copy", word_copy_time)]) {
+        let rate = mbits_per_sec(total_bits, d);
+        println!("{:>17} {:>10.3?} {:>12.1} {:>20.2} {:>12.1}", name, d, rate, rate / baseline_rate,
+                 d.as_secs_f64() * 1e9 / field_count as f64);
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// print each field of a bitstring of factor encodings, or of a compressed stream, see factor_disasm
+//
+// the bits come from one of
+//   BITS             any text form of bit_text::parse_bits(), such as b0110... or hex:37:0c5a...
+//   BITS_TEXT_FILE   a file holding the same, for long annotated dumps
+//   BITS_FILE        a file of raw bytes, as written by bit_io::StreamBitWriter
+//   STREAM_FILE      a file written by prime_codec::PrimeCompressor, header and all,
+//                    the bit offsets shown are from the end of the header
+// BIT_ORDER is lsb (the default) or msb, the order of the bits in each byte of
+// hex, base64 and BITS_FILE. the last few zero bits of BITS_FILE are taken to be
+// padding, BYTE_PADDED=true or false overrides that.
+// the codes must be the ones the bits were encoded with, FACTOR_CODES_FILE and
+// LENGTH_CODE, EXPONENT_CODE and INDEX_DELTA_CODE work as they do for factor_random.
+// exits with status 1 if the bits do not disassemble to the end.
+
+use bitstring::BitString;
+use compress_wi_primes::bit_text::parse_bits;
+use compress_wi_primes::dyn_bit_string::{BitOrder, DynBitString, BITS_PER_BYTE};
+use compress_wi_primes::encode_prime::{FactorCodes, DEFAULT_FACTOR_CODES};
+use compress_wi_primes::encoding_optimizer::load_factor_codes;
+use compress_wi_primes::factor_disasm::{disassemble_factorizations, disassemble_prime_stream, Disassembly};
+use compress_wi_primes::get_env_var;
+use compress_wi_primes::uint_code::UintCode;
+
+// a string environment variable, empty if not set
+fn env_var_string( var_name : &str ) -> String {
+    match get_env_var::get_env_var_parsed_with_default(var_name, String::new()) {
+        Ok(s) => s,
+        Err(e) => { get_env_var::env_var_usage(e, &var_name.to_string()); String::new() }
+    }
+}
+
+// the codes from FACTOR_CODES_FILE, LENGTH_CODE, EXPONENT_CODE and INDEX_DELTA_CODE
+fn factor_codes_from_env() -> Result<FactorCodes, Box<dyn std::error::Error + 'static>> {
+    // a missing FACTOR_CODES_FILE is an error here, the default codes would only
+    // make the bits look broken
+    let factor_codes_file = env_var_string("FACTOR_CODES_FILE");
+    let file_codes = if factor_codes_file.is_empty() {
+        DEFAULT_FACTOR_CODES
+    } else {
+        load_factor_codes(&factor_codes_file)?
+    };
+    let code_env_var = |var_name: &str, default_code: UintCode| {
+        match get_env_var::get_env_var_parsed_with_default(var_name, default_code) {
+            Ok(code) => code,
+            Err(e) => { get_env_var::env_var_usage(e, &var_name.to_string()); default_code }
+        }
+    };
+    let factor_codes = FactorCodes {
+        length: code_env_var("LENGTH_CODE", file_codes.length),
+        exponent: code_env_var("EXPONENT_CODE", file_codes.exponent),
+        index_delta: code_env_var("INDEX_DELTA_CODE", file_codes.index_delta),
+    };
+    println!("factor codes : length {} exponent {} index delta {}",
+             factor_codes.length, factor_codes.exponent, factor_codes.index_delta);
+    Ok(factor_codes)
+}
+
+// print the disassembly, and exit with status 1 if it has a problem
+fn report( d : &Disassembly, bs : &DynBitString ) {
+    print!("{}", d.format(bs));
+    match d.problem() {
+        Some(p) => {
+            println!("ERROR: encoding {} at bit {} : {}", d.encodings.len().saturating_sub(1), p.offset(), p);
+            std::process::exit(1);
+        }
+        None => {
+            println!("{} encodings", d.encodings.len());
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let stream_file = env_var_string("STREAM_FILE");
+    if !stream_file.is_empty() {
+        let factor_codes = factor_codes_from_env()?;
+        let sd = disassemble_prime_stream(&std::fs::read(&stream_file)?, &factor_codes)?;
+        println!("header : version {} mode {:?} prime table up to {}", sd.header.version, sd.header.mode, sd.header.upper_bound);
+        println!("bits : {}", sd.bits.len());
+        report(&sd.disassembly, &sd.bits);
+        return Ok(());
+    }
+
+    let order = match env_var_string("BIT_ORDER").as_str() {
+        "" | "lsb" => BitOrder::LsbFirst,
+        "msb" => BitOrder::MsbFirst,
+        _ => { get_env_var::env_var_usage(get_env_var::EnvVarFailure::CouldNotParseVar, &"BIT_ORDER".to_string()); BitOrder::LsbFirst }
+    };
+
+    let (bits_text, bits_text_file, bits_file) = (env_var_string("BITS"), env_var_string("BITS_TEXT_FILE"), env_var_string("BITS_FILE"));
+    let bs = if !bits_text.is_empty() {
+        parse_bits(&bits_text, order)?
+    } else if !bits_text_file.is_empty() {
+        parse_bits(&std::fs::read_to_string(&bits_text_file)?, order)?
+    } else if !bits_file.is_empty() {
+        let bytes = std::fs::read(&bits_file)?;
+        DynBitString::from_bytes_in(&bytes, bytes.len() * BITS_PER_BYTE, order).unwrap()
+    } else {
+        println!("ERROR: one of BITS, BITS_TEXT_FILE, BITS_FILE or STREAM_FILE must be set");
+        std::process::exit(1);
+    };
+    println!("bits : {}", bs.len());
+
+    let byte_padded_env_var_name = "BYTE_PADDED".to_string();
+    let byte_padded = match get_env_var::get_env_var_bool_with_default(byte_padded_env_var_name.as_str(), !bits_file.is_empty()) {
+        Ok(b) => b,
+        Err(e) => { get_env_var::env_var_usage(e, &byte_padded_env_var_name); false }
+    };
+
+    let factor_codes = factor_codes_from_env()?;
+    let d = disassemble_factorizations(&bs, &factor_codes, byte_padded);
+    report(&d, &bs);
+    Ok(())
+}
@@ -3,11 +3,19 @@
 
 use bitstring::BitString;
 use rand::RngCore;
-use compress_wi_primes::encode_prime::IntAsPrms;
+use compress_wi_primes::encode_prime::{FactorCodes, Factorization, IntAsPrms, DEFAULT_FACTOR_CODES};
+use compress_wi_primes::arith_codec::FactorFieldModels;
+use compress_wi_primes::range_coder::RangeEncoder;
 use compress_wi_primes::primes;
+use compress_wi_primes::prime_table::PrimeTable;
+use compress_wi_primes::prime_count::{factor_without_table, indices_to_primes_without_table};
 use compress_wi_primes::encode_prime;
+use compress_wi_primes::encode_hybrid::encode_hybrid;
+use compress_wi_primes::encoding_optimizer::*;
 use compress_wi_primes::get_env_var;
+use compress_wi_primes::uint_code::{candidate_codes, code_len, index_delta_candidate_codes, is_index_delta_code, UintCode};
 use compress_wi_primes::plot::{plot_histogram_u32, plot_histogram_f64};
+use compress_wi_primes::wheel::{Wheel, DEFAULT_WHEEL_MODULUS, WHEEL_MODULI};
 use std::time::SystemTime;
 
 //pub mod encode_prime;
@@ -30,6 +38,29 @@ fn hist_to_expected_value( hist : &[u32] ) -> f64 {
     expected_value
 }
 
+// sum of code_len() over a sample of values, None once any value could not be encoded
+fn add_code_lens( totals : &mut [Option<u128>], codes : &[UintCode], v : u32 ) {
+    for (total, code) in totals.iter_mut().zip(codes) {
+        *total = match (*total, code_len(*code, v)) {
+            (Some(t), Some(l)) => Some(t + l),
+            _ => None,
+        };
+    }
+}
+
+// print average bits per sample for each code, best first, and return the best code
+fn report_code_lens( field_name : &str, totals : &[Option<u128>], codes : &[UintCode], samples : u32 ) -> UintCode {
+    let mut ranked : Vec<(u128, UintCode)> = totals.iter().zip(codes)
+        .filter_map(|(total, code)| total.map(|t| (t, *code)))
+        .collect();
+    ranked.sort_by_key(|(total, _)| *total);
+    println!("average {} bits per sample for each code:", field_name);
+    for (total, code) in ranked.iter() {
+        println!("    {:>12} {:.3}", code.to_string(), *total as f64 / samples as f64);
+    }
+    ranked[0].1
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
     use compress_wi_primes::get_env_var::{get_env_var_u32, get_env_var_u32_with_default};
 
@@ -51,42 +82,157 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
         10000).unwrap();
     println!("samples : {}", samples);
 
-    let time_before_primes = SystemTime::now();
-
-    let prms : Vec<u32>;
-    let read_result = compress_wi_primes::primes::read_primes(largest_uint32);
-    match read_result {
-        Ok(read_prms) => {
-            prms = read_prms;
-        },
+    let wheel_modulus = get_env_var_u32_with_default(
+        "WHEEL_MODULUS",
+        DEFAULT_WHEEL_MODULUS).unwrap();
+    let wheel = match Wheel::new(wheel_modulus) {
+        Ok(w) => w,
         Err(_) => {
-            panic!("unable to read primes up to {}, generate them!", largest_uint32);
+            panic!("WHEEL_MODULUS must be one of {:?}", WHEEL_MODULI);
+        }
+    };
+    println!("wheel modulus : {}", wheel_modulus);
+
+    // without a prime table, prime indexes are computed with prime_count::pi()
+    // which is much slower, but nothing has to be generated first
+    let table_free_env_var_name = "PRIME_TABLE_FREE".to_string();
+    let table_free = match get_env_var::get_env_var_bool_with_default(table_free_env_var_name.as_str(), false) {
+        Ok(b) => b,
+        Err(e) => { get_env_var::env_var_usage(e, &table_free_env_var_name); false }
+    };
+    println!("prime table free : {}", table_free);
+
+    // codes saved by an earlier OPTIMIZE_FACTOR_CODES run, if any, see encoding_optimizer
+    let codes_file_env_var_name = "FACTOR_CODES_FILE".to_string();
+    let factor_codes_file = match get_env_var::get_env_var_parsed_with_default(codes_file_env_var_name.as_str(), String::new()) {
+        Ok(path) => path,
+        Err(e) => { get_env_var::env_var_usage(e, &codes_file_env_var_name); String::new() }
+    };
+    let file_codes = if factor_codes_file.is_empty() {
+        DEFAULT_FACTOR_CODES
+    } else {
+        load_factor_codes_or_default(&factor_codes_file)?
+    };
+
+    // which code to use for each field of the factor encoding, see uint_code for the names
+    // these override the ones in FACTOR_CODES_FILE
+    let code_env_var = |var_name: &str, default_code: UintCode| {
+        match get_env_var::get_env_var_parsed_with_default(var_name, default_code) {
+            Ok(code) => code,
+            Err(e) => { get_env_var::env_var_usage(e, &var_name.to_string()); default_code }
         }
     };
-    let time_after_read = SystemTime::now();
-    let duration_read_primes = time_after_read.duration_since(time_before_primes)?;
-    println!("time to read {} primes: {:?}", prms.len(), duration_read_primes);
+    let factor_codes = FactorCodes {
+        length: code_env_var("LENGTH_CODE", file_codes.length),
+        exponent: code_env_var("EXPONENT_CODE", file_codes.exponent),
+        index_delta: code_env_var("INDEX_DELTA_CODE", file_codes.index_delta),
+    };
+    println!("factor codes : length {} exponent {} index delta {}",
+             factor_codes.length, factor_codes.exponent, factor_codes.index_delta);
+    if !is_index_delta_code(factor_codes.index_delta) {
+        println!("ERROR: INDEX_DELTA_CODE : {} would take as many bits as a prime index difference", factor_codes.index_delta);
+        std::process::exit(1);
+    }
+
+    // the fields are encoded independently, so the best combination of codes
+    // is the best code for each field
+    let code_stats_env_var_name = "FACTOR_CODE_STATS".to_string();
+    let code_stats = match get_env_var::get_env_var_bool_with_default(code_stats_env_var_name.as_str(), false) {
+        Ok(b) => b,
+        Err(e) => { get_env_var::env_var_usage(e, &code_stats_env_var_name); false }
+    };
+    let codes = candidate_codes();
+    let index_codes = index_delta_candidate_codes();
+
+    // choose the continue offsets of the U32 and SmallInt fields of factor_codes
+    // for the sampled values, or for the little-endian u32 values in OPTIMIZE_CORPUS,
+    // and save them in FACTOR_CODES_FILE
+    let optimize_env_var_name = "OPTIMIZE_FACTOR_CODES".to_string();
+    let optimize_codes = match get_env_var::get_env_var_bool_with_default(optimize_env_var_name.as_str(), false) {
+        Ok(b) => b,
+        Err(e) => { get_env_var::env_var_usage(e, &optimize_env_var_name); false }
+    };
+    let corpus_env_var_name = "OPTIMIZE_CORPUS".to_string();
+    let corpus_file = match get_env_var::get_env_var_parsed_with_default(corpus_env_var_name.as_str(), String::new()) {
+        Ok(path) => path,
+        Err(e) => { get_env_var::env_var_usage(e, &corpus_env_var_name); String::new() }
+    };
+    let mut field_samples = FieldSamples::new();
+    let mut length_bits : Vec<Option<u128>> = vec![Some(0); codes.len()];
+    let mut exponent_bits : Vec<Option<u128>> = vec![Some(0); codes.len()];
+    let mut index_delta_bits : Vec<Option<u128>> = vec![Some(0); index_codes.len()];
+
+    let time_before_primes = SystemTime::now();
+
+    let prms : Option<PrimeTable> = if table_free {
+        None
+    } else {
+        let open_result = PrimeTable::open(largest_uint32);
+        let table = match open_result {
+            Ok(table) => table,
+            Err(e) => {
+                panic!("unable to read primes up to {} ({}), generate them!", largest_uint32, e);
+            }
+        };
+        let time_after_read = SystemTime::now();
+        let duration_read_primes = time_after_read.duration_since(time_before_primes)?;
+        println!("time to open {} primes (memory-mapped: {}): {:?}", table.len(), table.is_mapped(), duration_read_primes);
+        Some(table)
+    };
 
     // at this point, prms contains the primes we need to factor any u32
     // either we read it in from a file or we generated+wrote it to a file
     // so we only generate it if it isn't already saved
     // now we can experiment with it
 
+    let factor_it = |n: u32| match &prms {
+        Some(table) => primes::factor_with_wheel(n, table, &wheel),
+        None => factor_without_table(n),
+    };
+    let to_prime_factors = |ixs: &[u32]| match &prms {
+        Some(table) => primes::indices_to_prime_factors(ixs, table),
+        None => indices_to_primes_without_table(ixs),
+    };
+
     if let Ok(num_to_factor) = get_env_var_u32("NUM_TO_FACTOR") {
         println!("number to factor for debug: {}", num_to_factor);
-        let f = primes::factor(num_to_factor, &prms).unwrap();
+        let f = factor_it(num_to_factor).unwrap();
         println!("factor indexes of {} are {:?}", num_to_factor, f);
         let prmpwrs = encode_prime::factors_to_int_as_prms(&f);
         println!("prime powers of {} are {:?}", num_to_factor, prmpwrs);
-        let bs = encode_prime::encode_factors(&f);
+        let bs = encode_prime::encode_factors_with(&f, &factor_codes);
         println!("encoded value of {} bits is {:?}", bs.len(), bs);
         return Ok(());
     }
 
-    if get_env_var_u32("TEST_FACTORING_ALL").is_ok() {
+    if optimize_codes && !corpus_file.is_empty() {
+        let corpus_bytes = std::fs::read(&corpus_file)?;
+        for value_bytes in corpus_bytes.chunks(4) {
+            let mut le_bytes = [0u8; 4];
+            le_bytes[..value_bytes.len()].copy_from_slice(value_bytes);
+            let v = u32::from_le_bytes(le_bytes);
+            let f = match (v, factor_it(v)) {
+                (0, _) => Factorization::Zero,
+                (1, _) => Factorization::One,
+                (_, Ok(ixs)) => Factorization::Indices(ixs),
+                (_, Err(e)) => match &prms {
+                    Some(table) => primes::factorization_of(v, table),
+                    None => { return Err(format!("could not factor {} without a prime table: {:?}", v, e).into()); }
+                },
+            };
+            field_samples.add_factorization(&f);
+        }
+        println!("optimizing factor codes for {} values in {}", corpus_bytes.len().div_ceil(4), corpus_file);
+    }
+
+    // these two need the whole prime table
+    if get_env_var_u32("TEST_FACTORING_ALL").is_ok() && prms.is_none() {
+        println!("skipping TEST_FACTORING_ALL, it needs a prime table and PRIME_TABLE_FREE is set");
+    }
+    if let (Ok(_), Some(prms)) = (get_env_var_u32("TEST_FACTORING_ALL"), &prms) {
         println!("factoring all numbers up to {}", largest_uint32);
         let time_before_factoring = SystemTime::now();
-        primes::parallel_factor_all(largest_uint32, nthreads, &prms);
+        primes::parallel_factor_all(largest_uint32, nthreads, prms);
         let time_after_factoring = SystemTime::now();
         let duration_factoring = time_after_factoring.duration_since(time_before_factoring)?;
         println!("factored all numbers in {:?}", duration_factoring);
@@ -98,9 +244,12 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
         false) {
         Err(e) => { if e != get_env_var::EnvVarFailure::VarNotFound { get_env_var::env_var_usage(e, &pics_env_var_name); }},
         Ok(calc_compression_stats) => {
-            if calc_compression_stats {
+            if calc_compression_stats && prms.is_none() {
+                println!("skipping {}, it needs a prime table and PRIME_TABLE_FREE is set", pics_env_var_name);
+            }
+            if let (true, Some(prms)) = (calc_compression_stats, &prms) {
                 let mut prime_index_hist: Vec<f64> = vec![];
-                primes::prime_index_ratio_hist(0, prms.len(), &prms, &mut prime_index_hist);
+                primes::prime_index_ratio_hist(0, prms.len(), prms, &mut prime_index_hist);
                 println!("prime index compression histogram: {:?}", prime_index_hist);
                 plot_histogram_f64(
                     "index_compression.png",
@@ -115,9 +264,20 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
     // so we need to see prime-based compression work in this setting
 
     let mut compressions : u32 = 0;
+    let mut hybrid_bits : u64 = 0;   // total length of encode_hybrid() encodings
+    let mut bounded_bits : u64 = 0;  // total length of encode_factors_bounded() encodings
+    // where the bits of the encode_factors() encodings go, field by field
+    let mut length_field_bits : u64 = 0;
+    let mut exponent_field_bits : u64 = 0;
+    let mut index_delta_field_bits : u64 = 0;
+    let mut prime_power_count : u64 = 0;
+
+    // the same values range coded with adaptive models, see arith_codec
+    let mut arith_encoder = RangeEncoder::new();
+    let mut arith_models = FactorFieldModels::new();
 
     let interval_divisor = 10.0;
-    let mut rng = rand::thread_rng();
+    let mut rng = rand::rng();
 
     let mut histogrm_vs_u32 : Vec<u32> = vec![0; 100];
     let mut histogrm_fct_len : Vec<u32> = vec![0; 31]; // worst case is 2^31
@@ -129,36 +289,94 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
         let mut next_rand = rng.next_u32();
         if largest_uint32 != u32::MAX { next_rand %= largest_uint32 + 1 }
         if next_rand < 2 { next_rand = 2; }
-        let ixs  = primes::factor(next_rand, &prms).unwrap();
+        let ixs  = factor_it(next_rand).unwrap();
         histogrm_fct_len[ixs.len()] += 1;
 
         let prmpwrs : IntAsPrms = encode_prime::factors_to_int_as_prms(&ixs);
         histogrm_prmpwr_len[prmpwrs.prm_powers.len()] += 1;
+        if optimize_codes && corpus_file.is_empty() {
+            field_samples.add_factors(&ixs);
+        }
+        let mut prev_index = 0;
+        if code_stats {
+            add_code_lens(&mut length_bits, &codes, prmpwrs.prm_powers.len() as u32 - 1);
+        }
         for k in 0..prmpwrs.prm_powers.len() {
             histogrm_exponent[prmpwrs.prm_powers[k].exp as usize] += 1;
             let next_prime_index = prmpwrs.prm_powers[k].prm_idx;
             let log2_index = (next_prime_index as f64).log2() as u32;
             histogrm_log2_prime_index[log2_index as usize] += 1;
+            if code_stats {
+                add_code_lens(&mut exponent_bits, &codes, prmpwrs.prm_powers[k].exp as u32 - 1);
+                add_code_lens(&mut index_delta_bits, &index_codes, next_prime_index - prev_index);
+            }
+            prev_index = next_prime_index;
         }
-        let e = encode_prime::encode_factors(&ixs);
-        let e_str = encode_prime::format_factor_encoding_as_string(&ixs.as_slice());
+        let (e, breakdown) = encode_prime::encode_factors_breakdown_with(&ixs, &factor_codes);
+        let e_str = breakdown.format(e.slice(0..e.len()));
+        length_field_bits += breakdown.length_bits as u64;
+        exponent_field_bits += breakdown.exponents_total_bits() as u64;
+        index_delta_field_bits += breakdown.index_deltas_total_bits() as u64;
+        prime_power_count += breakdown.exponent_bits.len() as u64;
         if (e.len() as u32) < u32::BITS {
             //println!("COMPRESSED {} prime powers {:?} encoding {:?} len {}", next_rand, prmpwrs, e, e.len());
             compressions += 1;
         }
 
+        let f = Factorization::Indices(ixs.clone());
+        hybrid_bits += encode_hybrid(next_rand, &f).len() as u64;
+        bounded_bits += encode_prime::encode_factors_bounded(&ixs).len() as u64;
+        arith_models.encode_factorization(&mut arith_encoder, &f);
+
         let u32_szratio : f64 = e.len() as f64 / u32::BITS as f64;
         histogrm_vs_u32[(u32_szratio * interval_divisor) as usize] += 1;
 
-        let f = primes::indices_to_prime_factors(&ixs, &prms);
+        let f = to_prime_factors(&ixs);
         println!("int {} ratio {} ind {:?} fct {:?} prmpwr {:?} buf {:?} buflen {} encoding {}",
                  next_rand, u32_szratio, ixs, f, prmpwrs, e, e.len(), e_str);
     }
 
     println!("compressions: {}", compressions);
+    println!("average bits per sample : length {:.3} exponents {:.3} index deltas {:.3}",
+             length_field_bits as f64 / samples as f64,
+             exponent_field_bits as f64 / samples as f64,
+             index_delta_field_bits as f64 / samples as f64);
+    println!("average bits per prime power : exponent {:.3} index delta {:.3}",
+             exponent_field_bits as f64 / prime_power_count as f64,
+             index_delta_field_bits as f64 / prime_power_count as f64);
+    println!("average encode_hybrid length in bits: {}", hybrid_bits as f64 / samples as f64);
+    println!("average encode_factors_bounded length in bits: {}", bounded_bits as f64 / samples as f64);
+
+    if code_stats {
+        let best_codes = FactorCodes {
+            length: report_code_lens("length", &length_bits, &codes, samples),
+            exponent: report_code_lens("exponent", &exponent_bits, &codes, samples),
+            index_delta: report_code_lens("index delta", &index_delta_bits, &index_codes, samples),
+        };
+        println!("best factor codes : LENGTH_CODE={} EXPONENT_CODE={} INDEX_DELTA_CODE={}",
+                 best_codes.length, best_codes.exponent, best_codes.index_delta);
+    }
+
+    if optimize_codes {
+        let best_codes = optimize_factor_codes(&field_samples, &factor_codes);
+        println!("optimized factor codes : LENGTH_CODE={} EXPONENT_CODE={} INDEX_DELTA_CODE={}",
+                 best_codes.length, best_codes.exponent, best_codes.index_delta);
+        if let (Some(before), Some(after)) = (factor_fields_bits(&field_samples, &factor_codes),
+                                              factor_fields_bits(&field_samples, &best_codes)) {
+            println!("total factor field bits before {} after {}", before, after);
+        }
+        if !factor_codes_file.is_empty() {
+            save_factor_codes(&factor_codes_file, &best_codes)?;
+            println!("saved optimized factor codes in {}", factor_codes_file);
+        }
+    }
 
     println!("histogram of encode_factors compression ratio: {:?}", histogrm_vs_u32);
     println!("expected value of compression ratio: {}", hist_to_expected_value(&histogrm_vs_u32)/interval_divisor);
+    arith_models.encode_end(&mut arith_encoder);
+    let arith_bits_per_sample = (arith_encoder.finish().len() * 8) as f64 / samples as f64;
+    println!("average range coded bits per sample: {} compression ratio: {}",
+             arith_bits_per_sample, arith_bits_per_sample / u32::BITS as f64);
     plot_histogram_u32(
         "encode_factors_compression.png",
         "encode_factors compression ratio",
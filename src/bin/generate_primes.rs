@@ -1,9 +1,11 @@
 use compress_wi_primes::primes;
+use compress_wi_primes::prime_table::PrimeTable;
 use std::time::SystemTime;
 
 use compress_wi_primes::get_env_var::EnvVarFailure::VarNotFound;
 use compress_wi_primes::get_env_var::{get_env_var_u32_with_default,get_env_var_bool_with_default,env_var_usage};
 use compress_wi_primes::plot::plot_histogram_f64;
+use compress_wi_primes::prime_file::{PrimeFileLayout, DEFAULT_PRIME_FILE_LAYOUT};
 
 fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
 
@@ -22,30 +24,36 @@ fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
 
     let time_before_primes = SystemTime::now();
 
-    let mut prms : Vec<u32> = Vec::new();
-    let read_result = primes::read_primes(largest_uint32);
-    if let Ok(read_prms) = read_result {
+    let read_result = PrimeTable::read(largest_uint32);
+    let prms : PrimeTable = if let Ok(read_prms) = read_result {
         let time_after_read = SystemTime::now();
         let duration_read_primes = time_after_read.duration_since(time_before_primes)?;
         println!("time to read {} primes: {:?}", read_prms.len(), duration_read_primes);
-        prms = read_prms;
         println!("primes up through {} were already generated!", largest_uint32);
-    }
-    if prms.is_empty() {  // if primes were not read from file
-        prms = primes::parallel_calc_primes(nthreads, largest_uint32);
+        read_prms
+    } else {  // if primes were not read from file
+        let prms = PrimeTable::parallel_up_to(nthreads, largest_uint32);
 
         let time_after_threads = SystemTime::now();
         let duration_after_threads = time_after_threads.duration_since(time_before_primes)?;
         println!("time to compute primes: {:?}", duration_after_threads);
 
-        if let Err(e) = primes::write_primes(&prms, largest_uint32) {
+        let layout_code = get_env_var_u32_with_default(
+            "PRIME_FILE_LAYOUT",
+            DEFAULT_PRIME_FILE_LAYOUT as u32).unwrap();
+        let layout = match PrimeFileLayout::from_u32(layout_code) {
+            Some(l) => l,
+            None => { panic!("PRIME_FILE_LAYOUT must be 0 (u32 array) or 1 (mod-30 wheel bitmap)"); }
+        };
+        if let Err(e) = primes::write_primes_with_layout(prms.as_slice().unwrap(), largest_uint32, layout) {
             panic!("failed to write {} primes : {:?}", prms.len(), e);
         }
 
         let time_after_file = SystemTime::now();
         let duration_file_write = time_after_file.duration_since(time_after_threads)?;
         println!("time to write file: {:?}", duration_file_write);
-    }
+        prms
+    };
 
     let pics_env_var_name = "PRIME_INDEX_COMPRESSION_STATS".to_string();
     match get_env_var_bool_with_default(
@@ -0,0 +1,300 @@
+// bit-level adapters for io streams, so encodings can go to and come from files
+// and sockets without building the whole bitstring in memory first
+//
+// bits go to the stream in DynBitString::to_bytes() order, least significant bit
+// first within each byte, unless the adapter was made with_order() another BitOrder
+// to match some other tool. only whole bytes are written until finish(), which pads
+// the last byte with zero bits. the bit length is not written, so what is read back
+// must be self-delimiting, and a reader sees up to 7 padding bits at the end.
+//
+// unlike bit_stream::BitWriter and BitReader, which borrow a bitstring in memory,
+// these own a small buffer of bits and move whole bytes to and from the stream.
+
+use std::io::{Read, Write};
+use bitstring::BitString;
+use crate::bit_slice::BitSlice;
+use crate::bit_stream;
+use crate::dyn_bit_string::{BitOrder, DynBitString, BITS_PER_BYTE};
+use crate::encoding_uint_trait::{try_read_bit, try_read_bits_lsb_first, DecodeError};
+use crate::uint_code::{append_code, try_read_code, UintCode};
+use crate::uint_width::UintWidth;
+
+// buffered bits are written once there are this many
+const FLUSH_BITS: usize = 1 << 15;
+
+// bytes asked of the stream per read
+const READ_CHUNK_BYTES: usize = 4096;
+
+// bits to have buffered before trying to decode, doubled until the value fits
+const MIN_READ_AHEAD_BITS: usize = 256;
+
+pub struct StreamBitWriter<W: Write> {
+    stream: W,
+    bits: DynBitString,       // bits not written to stream yet
+    bits_written: usize,      // bits written to stream
+    order: BitOrder,          // of the bits in each byte of stream
+}
+
+pub struct StreamBitReader<R: Read> {
+    stream: R,
+    bits: DynBitString,       // bits read from stream and not dropped yet
+    cursor: usize,            // next bit to read in bits
+    bits_dropped: usize,      // bits dropped from the front of bits
+    stream_ended: bool,
+    order: BitOrder,          // of the bits in each byte of stream
+}
+
+impl<W: Write> StreamBitWriter<W> {
+    pub fn new(stream: W) -> Self {
+        Self::with_order(stream, BitOrder::LsbFirst)
+    }
+
+    pub fn with_order(stream: W, order: BitOrder) -> Self {
+        StreamBitWriter { stream, bits: DynBitString::null(), bits_written: 0, order }
+    }
+
+    pub fn write_bit(&mut self, b: bool) -> Result<(), std::io::Error> {
+        self.bits.append(b);
+        self.write_whole_bytes(FLUSH_BITS)
+    }
+
+    // the low nbits bits of value, nbits can be up to 64
+    pub fn write_uint(&mut self, value: u64, nbits: u32) -> Result<(), std::io::Error> {
+        self.bits.append_uint(value, nbits);
+        self.write_whole_bytes(FLUSH_BITS)
+    }
+
+    pub fn write_bits(&mut self, bs: &DynBitString) -> Result<(), std::io::Error> {
+        self.bits.extend_from_bitslice(BitSlice::new(bs));
+        self.write_whole_bytes(FLUSH_BITS)
+    }
+
+    pub fn write_code<T: UintWidth>(&mut self, code: UintCode, v: T) -> Result<(), std::io::Error> {
+        append_code(code, &mut self.bits, v);
+        self.write_whole_bytes(FLUSH_BITS)
+    }
+
+    // for encoders that write to a bit_stream::BitWriter, such as encode_prime::write_factors_with()
+    pub fn write_with<T>(&mut self, write: impl FnOnce(&mut bit_stream::BitWriter) -> T) -> Result<T, std::io::Error> {
+        let result = write(&mut bit_stream::BitWriter::new(&mut self.bits));
+        self.write_whole_bytes(FLUSH_BITS)?;
+        Ok(result)
+    }
+
+    // bits given to this writer so far, whether or not they reached the stream yet
+    pub fn len(&self) -> usize {
+        self.bits_written + self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // write out every whole byte of buffered bits, if there are at least min_bits of them
+    fn write_whole_bytes(&mut self, min_bits: usize) -> Result<(), std::io::Error> {
+        if self.bits.len() < min_bits {
+            return Ok(());
+        }
+        let whole_bits = self.bits.len() / BITS_PER_BYTE * BITS_PER_BYTE;
+        let mut bytes = self.bits.to_bytes_in(self.order);
+        bytes.truncate(whole_bits / BITS_PER_BYTE);
+        self.stream.write_all(&bytes)?;
+        self.bits = self.bits.slice(whole_bits..self.bits.len()).to_bit_string();
+        self.bits_written += whole_bits;
+        Ok(())
+    }
+
+    // pad the last byte with zero bits, write it out and return the underlying stream
+    // bits past the last whole byte are lost if this is not called
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        let padding = self.bits.len().next_multiple_of(BITS_PER_BYTE) - self.bits.len();
+        self.bits.append_uint(0, padding as u32);
+        self.write_whole_bytes(0)?;
+        self.stream.flush()?;
+        Ok(self.stream)
+    }
+}
+
+impl<R: Read> StreamBitReader<R> {
+    pub fn new(stream: R) -> Self {
+        Self::with_order(stream, BitOrder::LsbFirst)
+    }
+
+    pub fn with_order(stream: R, order: BitOrder) -> Self {
+        StreamBitReader { stream, bits: DynBitString::null(), cursor: 0, bits_dropped: 0, stream_ended: false, order }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, std::io::Error> {
+        self.read_with(try_read_bit)
+    }
+
+    // nbits bits as the low bits of the result, nbits can be up to 64
+    pub fn read_uint(&mut self, nbits: u32) -> Result<u64, std::io::Error> {
+        assert!(nbits <= u64::BITS);
+        self.read_with(|bs, cursor| try_read_bits_lsb_first(bs, cursor, nbits).map(|v| v as u64))
+    }
+
+    pub fn read_code<T: UintWidth>(&mut self, code: UintCode) -> Result<T, std::io::Error> {
+        self.read_with(|bs, cursor| try_read_code(code, bs, cursor))
+    }
+
+    // for the try_ decoders, which take the bitstring and a cursor, such as
+    // encode_prime::try_decode_factors_at_with(). the decoder must leave the cursor
+    // alone when it returns an error, as they all do, since it is called again with
+    // more bits whenever it runs out. running out at the end of the stream is an
+    // UnexpectedEof error, other DecodeErrors are InvalidData
+    pub fn read_with<T>(&mut self, mut read: impl FnMut(&DynBitString, &mut usize) -> Result<T, DecodeError>) -> Result<T, std::io::Error> {
+        let mut read_ahead = MIN_READ_AHEAD_BITS;
+        loop {
+            self.fill(read_ahead)?;
+            match read(&self.bits, &mut self.cursor) {
+                Err(DecodeError::Truncated) if !self.stream_ended => { read_ahead *= 2; }
+                r => { return r.map_err(std::io::Error::from); }
+            }
+        }
+    }
+
+    // bits read so far
+    pub fn position(&self) -> usize {
+        self.bits_dropped + self.cursor
+    }
+
+    // true once every bit of the stream has been read, including the padding
+    pub fn is_at_end(&mut self) -> Result<bool, std::io::Error> {
+        self.fill(1)?;
+        Ok(self.cursor == self.bits.len())
+    }
+
+    // read from the stream until there are min_bits past the cursor, or it ends
+    fn fill(&mut self, min_bits: usize) -> Result<(), std::io::Error> {
+        if self.stream_ended || self.bits.len() - self.cursor >= min_bits {
+            return Ok(());
+        }
+        // drop the bits already read, so the buffer does not grow with the stream
+        self.bits = self.bits.slice(self.cursor..self.bits.len()).to_bit_string();
+        self.bits_dropped += self.cursor;
+        self.cursor = 0;
+        let mut chunk = [0u8; READ_CHUNK_BYTES];
+        while !self.stream_ended && self.bits.len() < min_bits {
+            let byte_count = match self.stream.read(&mut chunk) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                r => r?
+            };
+            self.stream_ended = byte_count == 0;
+            self.bits.append_bytes_in(&chunk[..byte_count], self.order);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::encode_prime::{try_decode_factors_at_with, write_factors_with, DEFAULT_FACTOR_CODES};
+    use crate::encoding_u32::U32_DEFAULT_CONTINUE_OFFSETS;
+
+    // hands out one byte per read, like a slow socket
+    struct ByteAtATime<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl Read for ByteAtATime<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+            match self.bytes.split_first() {
+                Some((b, rest)) if !buf.is_empty() => {
+                    buf[0] = *b;
+                    self.bytes = rest;
+                    Ok(1)
+                }
+                _ => Ok(0)
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_stream_round_trip() {
+        let u32_code = UintCode::U32(U32_DEFAULT_CONTINUE_OFFSETS);
+        let factors: Vec<Vec<u32>> = (0..2000u32).map(|k| vec![k % 7, k % 7, k + 10, 3 * k + 20]).collect();
+        let mut expected = DynBitString::null();
+        let mut w = StreamBitWriter::new(Vec::new());
+        for (k, f) in factors.iter().enumerate() {
+            w.write_bit(k % 3 == 0).unwrap();
+            w.write_uint((k as u64).wrapping_mul(0x9e3779b97f4a7c15), 64).unwrap();
+            w.write_code(u32_code, k as u32).unwrap();
+            w.write_with(|bw| write_factors_with(bw, f, &DEFAULT_FACTOR_CODES)).unwrap();
+            expected.append(k % 3 == 0);
+            expected.append_uint((k as u64).wrapping_mul(0x9e3779b97f4a7c15), 64);
+            append_code(u32_code, &mut expected, k as u32);
+            write_factors_with(&mut bit_stream::BitWriter::new(&mut expected), f, &DEFAULT_FACTOR_CODES);
+        }
+        assert_eq!(w.len(), expected.len());
+        let bytes = w.finish().unwrap();
+        assert_eq!(bytes, expected.to_bytes());
+
+        let mut r = StreamBitReader::new(ByteAtATime { bytes: &bytes });
+        for (k, f) in factors.iter().enumerate() {
+            assert_eq!(r.read_bit().unwrap(), k % 3 == 0);
+            assert_eq!(r.read_uint(64).unwrap(), (k as u64).wrapping_mul(0x9e3779b97f4a7c15));
+            assert_eq!(r.read_code::<u32>(u32_code).unwrap(), k as u32);
+            let decoded: Vec<u32> = r.read_with(|bs, cursor| try_decode_factors_at_with(bs, cursor, &DEFAULT_FACTOR_CODES)).unwrap();
+            assert_eq!(&decoded, f);
+        }
+        assert_eq!(r.position(), expected.len());
+        // only the padding is left, too short for a 64-bit value
+        assert_eq!(r.read_uint(64).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        let padding = bytes.len() * BITS_PER_BYTE - expected.len();
+        assert_eq!(r.read_uint(padding as u32).unwrap(), 0);
+        assert!(r.is_at_end().unwrap());
+
+        // same bits in the other order
+        let mut w = StreamBitWriter::with_order(Vec::new(), BitOrder::MsbFirst);
+        w.write_bits(&expected).unwrap();
+        let msb_bytes = w.finish().unwrap();
+        assert_eq!(msb_bytes, expected.to_bytes_in(BitOrder::MsbFirst));
+        let mut r = StreamBitReader::with_order(ByteAtATime { bytes: &msb_bytes }, BitOrder::MsbFirst);
+        for k in 0..expected.len() {
+            assert_eq!(r.read_bit().unwrap(), expected.get(k));
+        }
+    }
+
+    #[test]
+    pub fn test_stream_edges() {
+        // writing nothing writes no bytes, and there is nothing to read back
+        let mut w = StreamBitWriter::new(Vec::new());
+        w.write_uint(0xff, 0).unwrap();
+        w.write_bits(&DynBitString::null()).unwrap();
+        assert!(w.is_empty());
+        let bytes = w.finish().unwrap();
+        assert!(bytes.is_empty());
+        let mut r = StreamBitReader::new(ByteAtATime { bytes: &bytes });
+        assert_eq!(r.read_uint(0).unwrap(), 0);
+        assert!(r.is_at_end().unwrap());
+        assert_eq!(r.read_bit().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(r.position(), 0);
+
+        // 64-bit values that start partway through a byte span 9 bytes of the stream,
+        // and a reader handed one byte at a time has to wait for all of them
+        let mut w = StreamBitWriter::new(Vec::new());
+        w.write_uint(0b101, 3).unwrap();
+        w.write_uint(u64::MAX - 1, 64).unwrap();
+        w.write_uint(0, 0).unwrap();
+        w.write_uint(0x0123_4567_89ab_cdef, 64).unwrap();
+        assert_eq!(w.len(), 131);
+        let bytes = w.finish().unwrap();
+        assert_eq!(bytes.len(), 17);
+        let mut r = StreamBitReader::new(ByteAtATime { bytes: &bytes });
+        assert_eq!(r.read_uint(3).unwrap(), 0b101);
+        assert_eq!(r.read_uint(64).unwrap(), u64::MAX - 1);
+        assert_eq!(r.read_uint(0).unwrap(), 0);
+        assert_eq!(r.read_uint(64).unwrap(), 0x0123_4567_89ab_cdef);
+        assert_eq!(r.position(), 131);
+
+        // reading past the end is UnexpectedEof and reads nothing, the padding is still there
+        assert_eq!(r.read_uint(6).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(r.position(), 131);
+        assert_eq!(r.read_uint(5).unwrap(), 0);
+        assert!(r.is_at_end().unwrap());
+        assert_eq!(r.read_bit().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(r.position(), 136);
+    }
+}
@@ -0,0 +1,240 @@
+// borrowed view of a range of bits in a DynBitString, without copying them
+//
+// counting, searching and the bitwise operations go a 64-bit word at a time,
+// so they cost len() / 64 word reads rather than len() calls to get().
+// rank1(i) is the number of 1 bits before bit i, select1(k) is the position of
+// the 1 bit with k 1 bits before it, so select1(rank1(i)) == Some(i) when bit i is 1.
+
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitXor, Range};
+use bitstring::BitString;
+use crate::dyn_bit_string::{DynBitString, BITS_PER_WORD};
+
+#[derive(Clone, Copy)]
+pub struct BitSlice<'a> {
+    bs : &'a DynBitString,
+    start : usize,
+    len : usize,
+}
+
+impl<'a> BitSlice<'a> {
+    // all of bs
+    pub fn new(bs : &'a DynBitString) -> Self {
+        BitSlice { bs, start: 0, len: bs.len() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, ndx : usize) -> bool {
+        assert!(ndx < self.len);
+        self.bs.get(self.start + ndx)
+    }
+
+    // bits of this slice in range, counting from the start of the slice
+    pub fn slice(&self, range : Range<usize>) -> BitSlice<'a> {
+        assert!(range.start <= range.end && range.end <= self.len);
+        BitSlice { bs: self.bs, start: self.start + range.start, len: range.end - range.start }
+    }
+
+    // nbits bits starting at pos, as the low bits of the result, nbits can be up to 64
+    pub fn read_uint(&self, pos : usize, nbits : u32) -> u64 {
+        assert!(pos + nbits as usize <= self.len);
+        self.bs.read_uint(self.start + pos, nbits)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + 'a {
+        let (bs, start) = (self.bs, self.start);
+        (start..start + self.len).map(move |k| bs.get(k))
+    }
+
+    // (bit offset, word of up to 64 bits starting there, bits in the word)
+    fn words(&self) -> impl Iterator<Item = (usize, u64, u32)> + '_ {
+        (0..self.len).step_by(BITS_PER_WORD).map(move |pos| {
+            let nbits = (self.len - pos).min(BITS_PER_WORD) as u32;
+            (pos, self.read_uint(pos, nbits), nbits)
+        })
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words().map(|(_, w, _)| w.count_ones() as usize).sum()
+    }
+
+    // number of 1 bits before bit i, i can be len()
+    pub fn rank1(&self, i : usize) -> usize {
+        self.slice(0..i).count_ones()
+    }
+
+    // position of the 1 bit with k 1 bits before it, None if there are not that many
+    pub fn select1(&self, k : usize) -> Option<usize> {
+        let mut ones_left = k;
+        for (pos, w, _) in self.words() {
+            let ones = w.count_ones() as usize;
+            if ones_left < ones {
+                // clear the lowest ones_left 1 bits, the one we want is then the lowest
+                let mut w = w;
+                for _j in 0..ones_left {
+                    w &= w - 1;
+                }
+                return Some(pos + w.trailing_zeros() as usize);
+            }
+            ones_left -= ones;
+        }
+        None
+    }
+
+    // position of the first 1 bit, None if there is none
+    pub fn find_first_set(&self) -> Option<usize> {
+        self.words().find(|(_, w, _)| *w != 0).map(|(pos, w, _)| pos + w.trailing_zeros() as usize)
+    }
+
+    // a copy of the bits, in a bitstring of their own
+    pub fn to_bit_string(&self) -> DynBitString {
+        let mut bs = DynBitString::null();
+        bs.extend_from_bitslice(*self);
+        bs
+    }
+
+    // bitwise op of two slices of the same length, a word at a time
+    fn zip_words(&self, other : &BitSlice, op : impl Fn(u64, u64) -> u64) -> DynBitString {
+        assert_eq!(self.len, other.len);
+        let mut bs = DynBitString::null();
+        for (pos, w, nbits) in self.words() {
+            bs.append_uint(op(w, other.read_uint(pos, nbits)), nbits);
+        }
+        bs
+    }
+}
+
+impl BitAnd for BitSlice<'_> {
+    type Output = DynBitString;
+
+    fn bitand(self, rhs : Self) -> DynBitString {
+        self.zip_words(&rhs, |a, b| a & b)
+    }
+}
+
+impl BitOr for BitSlice<'_> {
+    type Output = DynBitString;
+
+    fn bitor(self, rhs : Self) -> DynBitString {
+        self.zip_words(&rhs, |a, b| a | b)
+    }
+}
+
+impl BitXor for BitSlice<'_> {
+    type Output = DynBitString;
+
+    fn bitxor(self, rhs : Self) -> DynBitString {
+        self.zip_words(&rhs, |a, b| a ^ b)
+    }
+}
+
+impl PartialEq for BitSlice<'_> {
+    fn eq(&self, other : &Self) -> bool {
+        self.len == other.len && self.words().all(|(pos, w, nbits)| w == other.read_uint(pos, nbits))
+    }
+}
+
+// same format as DynBitString
+impl fmt::Debug for BitSlice<'_> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_bit_string())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    pub fn test_bit_slice() {
+        let mut rng = rand::rng();
+        let mut bs = DynBitString::null();
+        let mut other = DynBitString::null();
+        for k in 0..700 {
+            // sparse to start with, so select1() has some long gaps to cross
+            bs.append(k > 300 && rng.random::<bool>());
+            other.append(rng.random::<bool>());
+        }
+        let whole = BitSlice::new(&bs);
+        assert_eq!(whole.to_bit_string(), bs);
+        for (start, end) in [(0, 0), (0, 700), (3, 70), (64, 128), (250, 699), (301, 302), (37, 611)] {
+            let s = bs.slice(start..end);
+            let o = other.slice(start..end);
+            let bits: Vec<bool> = (start..end).map(|k| bs.get(k)).collect();
+            assert_eq!(s.len(), end - start);
+            assert_eq!(s.iter().collect::<Vec<bool>>(), bits);
+            assert_eq!(s.count_ones(), bits.iter().filter(|b| **b).count());
+            assert_eq!(s.find_first_set(), bits.iter().position(|b| *b));
+            let ones: Vec<usize> = (0..bits.len()).filter(|k| bits[*k]).collect();
+            for (k, pos) in ones.iter().enumerate() {
+                assert_eq!(s.rank1(*pos), k);
+                assert_eq!(s.select1(k), Some(*pos));
+            }
+            assert_eq!(s.rank1(s.len()), ones.len());
+            assert_eq!(s.select1(ones.len()), None);
+            let (and, or, xor) = (s & o, s | o, s ^ o);
+            for (k, b) in bits.iter().enumerate() {
+                assert_eq!(and.get(k), *b && o.get(k));
+                assert_eq!(or.get(k), *b || o.get(k));
+                assert_eq!(xor.get(k), *b != o.get(k));
+            }
+            // a slice of a slice is the same bits as slicing the bitstring
+            if s.len() > 10 {
+                assert_eq!(s.slice(5..s.len() - 5), bs.slice(start + 5..end - 5));
+            }
+        }
+        assert_eq!(DynBitString::null().slice(0..0).find_first_set(), None);
+    }
+
+    #[test]
+    pub fn test_bit_slice_edges() {
+        let mut bs = DynBitString::null();
+        bs.append_uint(0, 60);
+        bs.append_uint(0xfedc_ba98_7654_3210, 64);
+        bs.append_uint(0b11, 2);
+        let whole = BitSlice::new(&bs);
+        // reads that start in one word and end in the next
+        assert_eq!(whole.read_uint(60, 64), 0xfedc_ba98_7654_3210);
+        assert_eq!(whole.slice(60..126).read_uint(2, 64), 0xfedc_ba98_7654_3210 >> 2 | 0b11 << 62);
+        let s = whole.slice(61..126);
+        assert_eq!(s.count_ones(), 0xfedc_ba98_7654_3210u64.count_ones() as usize + 2);
+        assert_eq!(s.to_bit_string(), bs.slice(61..126).to_bit_string());
+
+        // zero length reads and slices, right up to the end
+        assert_eq!(whole.read_uint(bs.len(), 0), 0);
+        let end = whole.slice(bs.len()..bs.len());
+        assert!(end.is_empty());
+        assert_eq!(end.count_ones(), 0);
+        assert_eq!(end.rank1(0), 0);
+        assert_eq!(end.select1(0), None);
+        assert_eq!(end.iter().count(), 0);
+        assert_eq!(end.to_bit_string(), DynBitString::null());
+        assert_eq!(end & end, DynBitString::null());
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_get_past_end() {
+        let mut bs = DynBitString::null();
+        bs.append_uint(u64::MAX, 64);
+        BitSlice::new(&bs).slice(0..10).get(10);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_read_past_end() {
+        let mut bs = DynBitString::null();
+        bs.append_uint(u64::MAX, 64);
+        bs.append_uint(u64::MAX, 64);
+        // the bits after the slice are there, but not part of it
+        BitSlice::new(&bs).slice(10..70).read_uint(30, 31);
+    }
+}
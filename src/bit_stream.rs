@@ -0,0 +1,183 @@
+// borrowed views of a DynBitString for writing and reading encodings in place
+//
+// a BitWriter appends to a bitstring it does not own, so every encoder of a stream
+// can write into the same buffer instead of building its own and copying it over.
+// a BitReader reads from a bitstring it does not own, with its own cursor, so
+// decoding a long stream never copies it and takes time linear in its length.
+// the cursor only moves past an encoding that was read successfully.
+
+use bitstring::BitString;
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_uint_trait::{try_read_bit, DecodeError};
+use crate::uint_code::{append_code, try_read_code, UintCode};
+use crate::uint_width::UintWidth;
+
+pub struct BitWriter<'a> {
+    bs : &'a mut DynBitString,
+}
+
+pub struct BitReader<'a> {
+    bs : &'a DynBitString,
+    cursor : usize,
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(bs : &'a mut DynBitString) -> Self {
+        BitWriter { bs }
+    }
+
+    pub fn write_bit(&mut self, b : bool) {
+        self.bs.append(b);
+    }
+
+    pub fn write_code<T: UintWidth>(&mut self, code : UintCode, v : T) {
+        append_code(code, self.bs, v);
+    }
+
+    // the bitstring being appended to, for the append_ functions of each encoding
+    pub fn bitstr_mut(&mut self) -> &mut DynBitString {
+        self.bs
+    }
+
+    // bits in the bitstring, including any that were there before this writer
+    pub fn len(&self) -> usize {
+        self.bs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bs.len() == 0
+    }
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bs : &'a DynBitString) -> Self {
+        BitReader { bs, cursor: 0 }
+    }
+
+    // start reading at a bit offset other than 0
+    pub fn at(bs : &'a DynBitString, cursor : usize) -> Self {
+        BitReader { bs, cursor }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn bitstr(&self) -> &'a DynBitString {
+        self.bs
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bs.len().saturating_sub(self.cursor)
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn try_read_bit(&mut self) -> Result<bool, DecodeError> {
+        try_read_bit(self.bs, &mut self.cursor)
+    }
+
+    // panics if the bits do not hold a value of this code, see try_read_code()
+    pub fn read_code<T: UintWidth>(&mut self, code : UintCode) -> T {
+        self.try_read_code(code).unwrap()
+    }
+
+    pub fn try_read_code<T: UintWidth>(&mut self, code : UintCode) -> Result<T, DecodeError> {
+        try_read_code(code, self.bs, &mut self.cursor)
+    }
+
+    // for the read_ and try_read_ functions of each encoding,
+    // which take the bitstring and a cursor
+    pub fn with_cursor<R>(&mut self, read : impl FnOnce(&DynBitString, &mut usize) -> R) -> R {
+        read(self.bs, &mut self.cursor)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::encoding_elias::EliasDeltaEncoding;
+    use crate::encoding_golomb::RiceEncoding;
+    use crate::encoding_small_int::SmallIntEncoding;
+    use crate::encoding_u32::U32Encoding;
+    use crate::encoding_uint_trait::EncodingUint;
+
+    #[test]
+    pub fn test_shared_buffer() {
+        // different encodings write into one buffer, and are read back from it without copying
+        let u32_enc = U32Encoding::new();
+        let small_enc = SmallIntEncoding::new();
+        let delta_enc = EliasDeltaEncoding::new();
+        let rice_enc = RiceEncoding::<3>::new();
+        let mut bs = DynBitString::null();
+        let mut w = BitWriter::new(&mut bs);
+        for v in 0..1000u32 {
+            u32_enc.write_uint(&mut w, v * 7919);
+            small_enc.write_uint(&mut w, v % 32);
+            w.write_bit(v % 3 == 0);
+            delta_enc.write_uint(&mut w, v);
+            rice_enc.write_uint(&mut w, v % 50);
+        }
+        let bits_written = w.len();
+        assert_eq!(bits_written, bs.len());
+
+        let mut r = BitReader::new(&bs);
+        for v in 0..1000u32 {
+            assert_eq!(u32_enc.read_uint_from::<u32>(&mut r), v * 7919);
+            assert_eq!(small_enc.read_uint_from::<u32>(&mut r), v % 32);
+            assert_eq!(r.try_read_bit(), Ok(v % 3 == 0));
+            assert_eq!(delta_enc.read_uint_from::<u32>(&mut r), v);
+            assert_eq!(rice_enc.try_read_uint_from::<u32>(&mut r), Ok(v % 50));
+        }
+        assert!(r.is_at_end());
+        assert_eq!(r.try_read_bit(), Err(DecodeError::Truncated));
+        assert_eq!(u32_enc.try_read_uint_from::<u32>(&mut r), Err(DecodeError::Truncated));
+        assert_eq!(r.cursor(), bs.len());
+    }
+
+    #[test]
+    pub fn test_edges() {
+        use crate::encoding_uint_trait::{append_bits_lsb_first, try_read_bits_lsb_first};
+        let u32_code = UintCode::U32(crate::encoding_u32::U32_DEFAULT_CONTINUE_OFFSETS);
+
+        // a writer appends after the bits already there, and writing nothing changes nothing
+        let mut bs = DynBitString::null();
+        bs.append_uint(0x5555, 60);
+        let mut w = BitWriter::new(&mut bs);
+        assert_eq!(w.len(), 60);
+        assert!(!w.is_empty());
+        append_bits_lsb_first(w.bitstr_mut(), 0, 0);
+        assert_eq!(w.len(), 60);
+        // these cross the word boundary at bit 64
+        w.write_code(u32_code, u32::MAX);
+        w.write_code(u32_code, 0x1234_5678u32);
+        let end = w.len();
+
+        let mut r = BitReader::at(&bs, 60);
+        assert_eq!(r.with_cursor(|b, c| try_read_bits_lsb_first(b, c, 0)), Ok(0));
+        assert_eq!(r.cursor(), 60);
+        assert_eq!(r.read_code::<u32>(u32_code), u32::MAX);
+        assert_eq!(r.try_read_code::<u32>(u32_code), Ok(0x1234_5678));
+        assert_eq!(r.cursor(), end);
+        assert!(r.is_at_end());
+        // at the end only a zero length read works, and the cursor stays put
+        assert_eq!(r.with_cursor(|b, c| try_read_bits_lsb_first(b, c, 0)), Ok(0));
+        assert_eq!(r.with_cursor(|b, c| try_read_bits_lsb_first(b, c, 1)), Err(DecodeError::Truncated));
+        assert_eq!(r.try_read_code::<u32>(u32_code), Err(DecodeError::Truncated));
+        assert_eq!(r.cursor(), end);
+
+        // a reader made past the end has nothing left to read
+        let mut r = BitReader::at(&bs, end + 10);
+        assert_eq!(r.remaining(), 0);
+        assert_eq!(r.try_read_bit(), Err(DecodeError::Truncated));
+        assert_eq!(r.cursor(), end + 10);
+
+        let empty = DynBitString::null();
+        let mut r = BitReader::new(&empty);
+        assert!(r.is_at_end());
+        assert_eq!(r.try_read_bit(), Err(DecodeError::Truncated));
+        assert_eq!(r.cursor(), 0);
+    }
+}
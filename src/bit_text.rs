@@ -0,0 +1,244 @@
+// text forms of a DynBitString, so encodings can be pasted into bug reports and test vectors
+//
+//   Binary         b10110000111        the bits in order, first bit first, same as Display
+//   Grouped(4)     b1011_0000_111      the same, with _ after every n bits
+//   Annotated(16)  b                   n bits per line in groups of 8, each line
+//                   0: 10110000 111    starting with the offset of its first bit
+//   Hex            hex:11:0d07         DynBitString::to_bytes_in() bytes, after the bit length
+//   Base64         base64:11:DQc=      the same bytes in standard base64
+//
+// the bit length of hex and base64 is left out when the bits are a whole number of bytes.
+// the bit order only changes the bytes of hex and base64, binary forms list the bits in order.
+// parse_bits() reads any of these and ignores whitespace within a line, so they can be
+// indented or spaced out. an annotated line must start at the offset it says it does.
+
+use std::fmt;
+use bitstring::BitString;
+use crate::dyn_bit_string::{BitOrder, DynBitString, BITS_PER_BYTE};
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub enum BitTextFormat {
+    Binary,
+    Grouped(usize),      // bits per group
+    Annotated(usize),    // bits per line
+    Hex,
+    Base64,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ParseBitsErrcode {
+    UnknownFormat,   // does not start with b, hex: or base64:
+    BadDigit,        // character that is not a digit of the format
+    BadLength,       // bit length does not match the number of bytes
+    BadOffset,       // annotated line does not start where the previous one ended
+}
+
+impl fmt::Display for ParseBitsErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ParseBitsErrcode::UnknownFormat => "prefix for DynBitString not seen",
+            ParseBitsErrcode::BadDigit => "bad digit in DynBitString",
+            ParseBitsErrcode::BadLength => "bit length of DynBitString does not match its bytes",
+            ParseBitsErrcode::BadOffset => "line of DynBitString does not start at its offset",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::error::Error for ParseBitsErrcode {}
+
+const BASE64_DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn format_bits(bs: &DynBitString, format: BitTextFormat, order: BitOrder) -> String {
+    match format {
+        BitTextFormat::Binary => bs.to_string(),
+        BitTextFormat::Grouped(n) => {
+            let mut s = "b".to_string();
+            for k in 0..bs.len() {
+                if k > 0 && k % n.max(1) == 0 {
+                    s.push('_');
+                }
+                s.push(if bs.get(k) { '1' } else { '0' });
+            }
+            s
+        }
+        BitTextFormat::Annotated(n) => {
+            let n = n.max(1);
+            let offset_width = (bs.len().saturating_sub(1) / n * n).to_string().len();
+            let mut s = "b".to_string();
+            for k in 0..bs.len() {
+                if k % n == 0 {
+                    s += &format!("\n{:>width$}: ", k, width = offset_width);
+                } else if k % BITS_PER_BYTE == 0 {
+                    s.push(' ');
+                }
+                s.push(if bs.get(k) { '1' } else { '0' });
+            }
+            s
+        }
+        BitTextFormat::Hex => {
+            let digits: String = bs.to_bytes_in(order).iter().map(|b| format!("{:02x}", b)).collect();
+            format!("hex:{}{}", length_prefix(bs), digits)
+        }
+        BitTextFormat::Base64 => {
+            format!("base64:{}{}", length_prefix(bs), to_base64(&bs.to_bytes_in(order)))
+        }
+    }
+}
+
+// bit length and a colon, unless it is a whole number of bytes
+fn length_prefix(bs: &DynBitString) -> String {
+    if bs.len().is_multiple_of(BITS_PER_BYTE) { String::new() } else { format!("{}:", bs.len()) }
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |g, (k, b)| g | (*b as u32) << (16 - 8 * k));
+        for k in 0..4 {
+            if k <= chunk.len() {
+                s.push(BASE64_DIGITS[(group >> (18 - 6 * k) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+fn from_base64(digits: &str) -> Result<Vec<u8>, ParseBitsErrcode> {
+    let digits = digits.trim_end_matches('=');
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut group: u32 = 0;
+    let mut group_bits = 0;
+    for c in digits.bytes() {
+        let v = BASE64_DIGITS.iter().position(|d| *d == c).ok_or(ParseBitsErrcode::BadDigit)?;
+        group = (group << 6) | v as u32;
+        group_bits += 6;
+        if group_bits >= BITS_PER_BYTE {
+            group_bits -= BITS_PER_BYTE;
+            bytes.push((group >> group_bits) as u8);
+        }
+    }
+    // a lone last digit cannot finish a byte, and the bits after the last byte
+    // are padding to_base64() leaves zero
+    if digits.len() % 4 == 1 || group & ((1 << group_bits) - 1) != 0 {
+        return Err(ParseBitsErrcode::BadLength);
+    }
+    Ok(bytes)
+}
+
+// inverse of format_bits() for any of the formats, hex and base64 must be in the same order
+pub fn parse_bits(text: &str, order: BitOrder) -> Result<DynBitString, ParseBitsErrcode> {
+    let text: String = text.trim().to_string();
+    if let Some(rest) = text.strip_prefix("hex:") {
+        let (bit_len, digits) = split_length(rest)?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseBitsErrcode::BadDigit);
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(ParseBitsErrcode::BadLength);
+        }
+        let bytes = (0..digits.len()).step_by(2)
+            .map(|k| u8::from_str_radix(&digits[k..k + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        bytes_to_bits(&bytes, bit_len, order)
+    } else if let Some(rest) = text.strip_prefix("base64:") {
+        let (bit_len, digits) = split_length(rest)?;
+        bytes_to_bits(&from_base64(&digits)?, bit_len, order)
+    } else if let Some(rest) = text.strip_prefix('b') {
+        parse_binary_lines(rest)
+    } else {
+        Err(ParseBitsErrcode::UnknownFormat)
+    }
+}
+
+// optional bit length before a colon, then the digits with any whitespace removed
+fn split_length(s: &str) -> Result<(Option<usize>, String), ParseBitsErrcode> {
+    let (bit_len, digits) = match s.split_once(':') {
+        Some((len, digits)) => (Some(len.trim().parse::<usize>().map_err(|_| ParseBitsErrcode::BadLength)?), digits),
+        None => (None, s),
+    };
+    Ok((bit_len, digits.chars().filter(|c| !c.is_whitespace()).collect()))
+}
+
+fn bytes_to_bits(bytes: &[u8], bit_len: Option<usize>, order: BitOrder) -> Result<DynBitString, ParseBitsErrcode> {
+    let bit_len = bit_len.unwrap_or(bytes.len() * BITS_PER_BYTE);
+    DynBitString::from_bytes_in(bytes, bit_len, order).map_err(|_| ParseBitsErrcode::BadLength)
+}
+
+// binary digits, possibly grouped with _ or spaces, possibly on lines that start with an offset
+fn parse_binary_lines(s: &str) -> Result<DynBitString, ParseBitsErrcode> {
+    let mut bs = DynBitString::null();
+    for line in s.lines() {
+        let digits = match line.split_once(':') {
+            Some((offset, digits)) => {
+                if offset.trim().parse::<usize>() != Ok(bs.len()) {
+                    return Err(ParseBitsErrcode::BadOffset);
+                }
+                digits
+            }
+            None => line,
+        };
+        for c in digits.chars().filter(|c| *c != '_' && !c.is_whitespace()) {
+            match c {
+                '0' => bs.append(false),
+                '1' => bs.append(true),
+                _ => { return Err(ParseBitsErrcode::BadDigit); }
+            }
+        }
+    }
+    Ok(bs)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_formats() {
+        let bs = parse_bits("b10110000111", BitOrder::LsbFirst).unwrap();
+        let expected = [
+            (BitTextFormat::Binary, BitOrder::LsbFirst, "b10110000111"),
+            (BitTextFormat::Grouped(4), BitOrder::LsbFirst, "b1011_0000_111"),
+            (BitTextFormat::Annotated(8), BitOrder::LsbFirst, "b\n0: 10110000\n8: 111"),
+            (BitTextFormat::Hex, BitOrder::LsbFirst, "hex:11:0d07"),
+            (BitTextFormat::Hex, BitOrder::MsbFirst, "hex:11:b0e0"),
+            (BitTextFormat::Base64, BitOrder::LsbFirst, "base64:11:DQc="),
+            (BitTextFormat::Base64, BitOrder::MsbFirst, "base64:11:sOA="),
+        ];
+        for (format, order, text) in expected {
+            assert_eq!(format_bits(&bs, format, order), text);
+            assert_eq!(parse_bits(text, order), Ok(bs.clone()), "{}", text);
+        }
+        // pasted by hand
+        assert_eq!(parse_bits("  b 1011 0000\n   111 ", BitOrder::LsbFirst), Ok(bs.clone()));
+        assert_eq!(parse_bits("hex: 11 : 0d 07", BitOrder::LsbFirst), Ok(bs.clone()));
+
+        // every length of hex and base64, with and without padding bits
+        let mut bs = DynBitString::null();
+        for k in 0..50 {
+            for format in [BitTextFormat::Hex, BitTextFormat::Base64, BitTextFormat::Annotated(16), BitTextFormat::Grouped(3)] {
+                for order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+                    assert_eq!(parse_bits(&format_bits(&bs, format, order), order), Ok(bs.clone()), "{:?} {}", format, k);
+                }
+            }
+            bs.append(k % 3 == 1 || k % 7 == 0);
+        }
+
+        assert_eq!(parse_bits("", BitOrder::LsbFirst), Err(ParseBitsErrcode::UnknownFormat));
+        assert_eq!(parse_bits("b0120", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadDigit));
+        assert_eq!(parse_bits("hex:0g", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadDigit));
+        assert_eq!(parse_bits("hex:17:0d07", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("hex:0d0", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("base64:DQ*=", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadDigit));
+        assert_eq!(parse_bits("base64:A", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("base64:DQcAB", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("base64:DR==", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("base64:DQd=", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadLength));
+        assert_eq!(parse_bits("b\n0: 1011\n5: 0", BitOrder::LsbFirst), Err(ParseBitsErrcode::BadOffset));
+    }
+}
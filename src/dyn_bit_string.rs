@@ -1,13 +1,21 @@
 use std::fmt;
 //use std::num::NonZeroU32;
 use bitstring::BitString;
+use std::ops::Range;
 use std::str::FromStr;
+use crate::bit_slice::BitSlice;
+use crate::bit_text::parse_bits;
 
 pub struct DynBitString {
     cnt: usize,       // number of bits
-    b : Vec<u8>         // dynamically allocated byte array
+    b : Vec<u64>        // dynamically allocated word array
 }
 
+// bit k is bit k % 64 of word k / 64, so the words written out little-endian are
+// the same bytes as packing the bits least significant bit first.
+// bits past cnt are always zero, clip() clears them when shrinking,
+// so append_uint() can OR a whole word into place.
+
 //pub is_big_endian : bool = 0x12345678u32.to_be_bytes() == [ 0x12, 0x34, 0x56, 0x78 ];
 
 #[derive(PartialEq)]
@@ -17,27 +25,58 @@ pub enum DBSGetBitErr {
     StartingAtTooBig
 }
 
+#[derive(PartialEq)]
+#[derive(Debug)]
+pub enum DBSFromBytesErr {
+    TooFewBytes,      // bit length needs more bytes than were given
+    TooManyBytes      // bytes left over after the last one holding a bit
+}
+
+// order of the bits within each byte when a bitstring is serialized.
+// LsbFirst is this crate's own order, MsbFirst is the order most other bitstream
+// tools read and write, where the first bit is the high bit of the first byte.
+// the bitstring itself is the same either way, only its bytes differ.
+
+#[derive(PartialEq)]
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub enum BitOrder {
+    LsbFirst,
+    MsbFirst
+}
+
+impl BitOrder {
+    // a byte in this order from one in LsbFirst order, and back
+    pub fn convert_byte(&self, b: u8) -> u8 {
+        match self {
+            BitOrder::LsbFirst => b,
+            BitOrder::MsbFirst => b.reverse_bits(),
+        }
+    }
+}
+
 pub const BITS_PER_BYTE : usize = 8;
+pub const BITS_PER_WORD : usize = 64;
 
 impl BitString for DynBitString {
 
 
     fn get(&self, ndx: usize) -> bool {
         assert!(ndx < self.cnt);
-        let byte_index = ndx / BITS_PER_BYTE;
-        let bit_index_within_byte = ndx % BITS_PER_BYTE;
-        self.b[byte_index] & (1 << bit_index_within_byte) != 0
+        let word_index = ndx / BITS_PER_WORD;
+        let bit_index_within_word = ndx % BITS_PER_WORD;
+        self.b[word_index] & (1 << bit_index_within_word) != 0
     }
 
     fn set(&mut self, ndx: usize, bit: bool) {
         assert!(ndx < self.cnt);
-        let byte_index = ndx / BITS_PER_BYTE;
-        let bit_index_within_byte = ndx % BITS_PER_BYTE;
-        let bit_shift = 1 << bit_index_within_byte;
+        let word_index = ndx / BITS_PER_WORD;
+        let bit_index_within_word = ndx % BITS_PER_WORD;
+        let bit_shift = 1 << bit_index_within_word;
         if bit {
-            self.b[byte_index] |= bit_shift;
+            self.b[word_index] |= bit_shift;
         } else {
-            self.b[byte_index] &= !bit_shift;
+            self.b[word_index] &= !bit_shift;
         }
     }
 
@@ -51,44 +90,157 @@ impl BitString for DynBitString {
         #[allow(clippy::comparison_chain)]
         if newsz < self.cnt {
             // don't shrink vector but zero out bits from newsz to end
-            for k in newsz..self.cnt {
-                self.set(k, false);
+            let word_index = newsz / BITS_PER_WORD;
+            let bits_kept = newsz % BITS_PER_WORD;
+            self.b[word_index] &= (1 << bits_kept) - 1;
+            for w in self.b[word_index + 1..].iter_mut() {
+                *w = 0;
             }
             self.cnt = newsz;
         } else if newsz > self.cnt {
-            let old_bitcnt = self.cnt;
-            for _k in old_bitcnt..newsz {
-                self.append(false);
+            while self.cnt < newsz {
+                let nbits = (newsz - self.cnt).min(BITS_PER_WORD) as u32;
+                self.append_uint(0, nbits);
             }
         }
         // clip does NOTHING if new size is same as old size
     }
     fn append(&mut self, bit: bool) {
-        if self.cnt % BITS_PER_BYTE == 0 {
-            if self.b.len() * BITS_PER_BYTE == self.cnt {
-                self.b.push(0);  // allocate another 8 bits
-            } else {
-                assert!(self.b.len() * BITS_PER_BYTE > self.cnt);
+        self.append_uint(bit as u64, 1);
+    }
+
+    fn null() -> Self {
+        DynBitString { cnt: 0, b: Vec::new() }
+    }
+}
+
+// word-at-a-time access, bits are in the same order as append() and get(),
+// so bit k of value is bit len()+k of the bitstring
+
+impl DynBitString {
+    // append the low nbits bits of value, nbits can be up to 64
+    pub fn append_uint(&mut self, value: u64, nbits: u32) {
+        assert!(nbits <= u64::BITS);
+        if nbits == 0 {
+            return;
+        }
+        let value = if nbits == u64::BITS { value } else { value & ((1 << nbits) - 1) };
+        let word_index = self.cnt / BITS_PER_WORD;
+        let bits_used = (self.cnt % BITS_PER_WORD) as u32;
+        if bits_used == 0 {
+            self.set_word(word_index, value);
+        } else {
+            self.b[word_index] |= value << bits_used;
+            if bits_used + nbits > u64::BITS {
+                self.set_word(word_index + 1, value >> (u64::BITS - bits_used));
             }
         }
-        self.cnt += 1;
-        let byte_index = (self.cnt - 1) / BITS_PER_BYTE;
-        let mut last_byte = self.b[byte_index];
-        let bit_within_byte = (self.cnt - 1) % BITS_PER_BYTE;
-        let shifted_bit = 1 << bit_within_byte;
-        if bit {
-            last_byte |= shifted_bit;      // set the bit
+        self.cnt += nbits as usize;
+    }
+
+    // store a word that is all past the end of the bitstring, allocating it if needed
+    fn set_word(&mut self, word_index: usize, w: u64) {
+        if word_index == self.b.len() {
+            self.b.push(w);   // allocate another 64 bits
         } else {
-            last_byte &= !shifted_bit;     // clear the bit
+            self.b[word_index] = w;
         }
-        self.b[byte_index] = last_byte;  // and update last byte in array
     }
 
-    fn null() -> Self {
-        DynBitString { cnt: 0, b: Vec::new() }
+    // the nbits bits starting at pos, as the low bits of the result, nbits can be up to 64
+    pub fn read_uint(&self, pos: usize, nbits: u32) -> u64 {
+        assert!(nbits <= u64::BITS);
+        assert!(pos + nbits as usize <= self.cnt);
+        if nbits == 0 {
+            return 0;
+        }
+        let word_index = pos / BITS_PER_WORD;
+        let bit_index_within_word = (pos % BITS_PER_WORD) as u32;
+        let mut v = self.b[word_index] >> bit_index_within_word;
+        if bit_index_within_word + nbits > u64::BITS {
+            v |= self.b[word_index + 1] << (u64::BITS - bit_index_within_word);
+        }
+        if nbits == u64::BITS { v } else { v & ((1 << nbits) - 1) }
+    }
+
+    // append the bits of src, 64 bits at a time
+    pub fn extend_from_bitslice(&mut self, src: BitSlice) {
+        let mut pos = 0;
+        while pos < src.len() {
+            let nbits = (src.len() - pos).min(BITS_PER_WORD) as u32;
+            self.append_uint(src.read_uint(pos, nbits), nbits);
+            pos += nbits as usize;
+        }
+    }
+
+    // borrow the bits in range, without copying them
+    pub fn slice(&self, range: Range<usize>) -> BitSlice<'_> {
+        BitSlice::new(self).slice(range)
     }
 }
 
+// serialized form: bit k is bit k % 8 of byte k / 8, least significant bit first like
+// the bitstring itself, so an encoding is the same bytes whatever reads it.
+// the last byte is padded with zero bits, the bit length is not included,
+// so it must be stored alongside if the bitstring is not self-delimiting.
+
+impl DynBitString {
+    // len().div_ceil(8) bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let byte_cnt = self.cnt.div_ceil(BITS_PER_BYTE);
+        self.b.iter().flat_map(|w| w.to_le_bytes()).take(byte_cnt).collect()
+    }
+
+    // same as to_bytes() with the bits of each byte in the given order
+    pub fn to_bytes_in(&self, order: BitOrder) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+        if order != BitOrder::LsbFirst {
+            bytes.iter_mut().for_each(|b| *b = order.convert_byte(*b));
+        }
+        bytes
+    }
+
+    // inverse of to_bytes(), bytes must be exactly bit_len.div_ceil(8) long.
+    // any padding bits in the last byte are dropped
+    pub fn from_bytes(bytes: &[u8], bit_len: usize) -> Result<Self, DBSFromBytesErr> {
+        Self::from_bytes_in(bytes, bit_len, BitOrder::LsbFirst)
+    }
+
+    // inverse of to_bytes_in()
+    pub fn from_bytes_in(bytes: &[u8], bit_len: usize, order: BitOrder) -> Result<Self, DBSFromBytesErr> {
+        let byte_cnt = bit_len.div_ceil(BITS_PER_BYTE);
+        if bytes.len() < byte_cnt {
+            return Err(DBSFromBytesErr::TooFewBytes);
+        } else if bytes.len() > byte_cnt {
+            return Err(DBSFromBytesErr::TooManyBytes);
+        }
+        let mut bs = DynBitString::null();
+        bs.append_bytes_in(bytes, order);
+        bs.clip(bit_len);
+        Ok(bs)
+    }
+
+    // append all 8 bits of each byte, in to_bytes() order
+    pub fn append_bytes(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(BITS_PER_WORD / BITS_PER_BYTE);
+        for chunk in chunks.by_ref() {
+            self.append_uint(u64::from_le_bytes(chunk.try_into().unwrap()), u64::BITS);
+        }
+        for b in chunks.remainder() {
+            self.append_uint(*b as u64, BITS_PER_BYTE as u32);
+        }
+    }
+
+    // same as append_bytes() for bytes with their bits in the given order
+    pub fn append_bytes_in(&mut self, bytes: &[u8], order: BitOrder) {
+        if order == BitOrder::LsbFirst {
+            self.append_bytes(bytes);
+        } else {
+            let converted: Vec<u8> = bytes.iter().map(|b| order.convert_byte(*b)).collect();
+            self.append_bytes(&converted);
+        }
+    }
+}
 
 impl Clone for DynBitString {
     fn clone(&self) -> Self {
@@ -115,7 +267,7 @@ impl PartialEq for DynBitString {
 // support "{:?}" when printing DynBitString
 impl fmt::Debug for DynBitString {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "{}", self)
     }
 }
 
@@ -129,36 +281,19 @@ impl fmt::Display for DynBitString {
     }
 }
 
-// parse bitstring string same format as fmt::Debug above
+// parse bitstring string same format as fmt::Debug above,
+// or any of the other text forms in bit_text, with hex and base64 in LsbFirst order
 
 impl FromStr for DynBitString {
     type Err = String;
 
-    fn from_str(bitstr_binary_code : &str) -> Result<Self, Self::Err> {
-        let bstr : String = bitstr_binary_code.to_string();
-        if !bstr.starts_with('b') { Err("prefix for DynBitString not seen".to_string()) }
-        else {
-            let zeroes_and_ones  = bstr.strip_prefix('b').unwrap().as_bytes();
-            let mut bitstr = DynBitString::null();
-            for c in zeroes_and_ones {
-                let char  = *c as char;
-                if char == '1' {
-                    bitstr.append(true);
-                } else if char == '0' {
-                    bitstr.append(false);
-                } else {
-                    return Err("bits in DynBitString must be 0 or 1".to_string());
-                }
-            }
-            Ok(bitstr)
-        }
+    fn from_str(bitstr_text : &str) -> Result<Self, Self::Err> {
+        parse_bits(bitstr_text, BitOrder::LsbFirst).map_err(|e| e.to_string())
     }
 }
 
 pub fn append_bits(dest : &mut DynBitString, bits: & DynBitString) {
-    for k in 0..bits.len() {
-        dest.append(bits.get(k));
-    }
+    dest.extend_from_bitslice(BitSlice::new(bits));
 }
 
 pub fn get_bits(bs : &DynBitString, starting_at : u32, count : u32) -> Result<DynBitString, DBSGetBitErr> {
@@ -167,11 +302,7 @@ pub fn get_bits(bs : &DynBitString, starting_at : u32, count : u32) -> Result<Dy
     } else if starting_at + count > bs.len() as u32 {
         return Err(DBSGetBitErr::CountPastEnd);
     }
-    let mut substr = DynBitString::null();
-    for k in 0..count {
-        substr.append(bs.get((starting_at + k) as usize));
-    }
-    Ok(substr)
+    Ok(bs.slice(starting_at as usize..(starting_at + count) as usize).to_bit_string())
 }
 
 #[cfg(test)]
@@ -243,7 +374,7 @@ pub mod tests {
         let bs_result1 = DynBitString::from_str("");
         match bs_result1 {
             Err(_) => { },
-            Ok(_) => { assert!(false); }
+            Ok(_) => { panic!("expected an error"); }
         }
         let bs2 = DynBitString::from_str("b").unwrap();
         assert_eq!(bs2.len(), 0);
@@ -251,6 +382,8 @@ pub mod tests {
         assert!(bs3.len() == 1 && bs3.get(0));
         let bs4 = DynBitString::from_str("b01").unwrap();
         assert!(bs4.len() == 2 && !bs4.get(0) && bs4.get(1));
+        assert_eq!(DynBitString::from_str("hex:2:02").unwrap(), bs4);
+        assert!(DynBitString::from_str("b012").is_err());
     }
 
     #[test]
@@ -288,7 +421,7 @@ pub mod tests {
         bs.clip(15);
         assert_eq!(bs.len(), 15);
         // check that the buffer length is right
-        assert_eq!(bs.b.len(), 2);
+        assert_eq!(bs.b.len(), 1);
         // previously existing bits should be unchanged
         assert!(!bs.get(0));
         assert!(bs.get(1));
@@ -302,15 +435,80 @@ pub mod tests {
     pub fn test_get_bits() {
         let mut bs = DynBitString::null();
         bs.append(false); bs.append(true); bs.append(true);
-        match get_bits(&bs, 2, 2) {
-            Err(ecode) => { assert_eq!(ecode, DBSGetBitErr::CountPastEnd); },
-            Ok(_) => {}
-        }
-        match get_bits(&bs, 3, 2) {
-            Err(ecode) => { assert_eq!(ecode, DBSGetBitErr::StartingAtTooBig); },
-            Ok(_) => {}
-        }
+        if let Err(ecode) = get_bits(&bs, 2, 2) { assert_eq!(ecode, DBSGetBitErr::CountPastEnd); }
+        if let Err(ecode) = get_bits(&bs, 3, 2) { assert_eq!(ecode, DBSGetBitErr::StartingAtTooBig); }
         let substr = get_bits(&bs, 1, 2).unwrap();
         assert!(substr.get(0) && substr.get(1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_append_read_uint() {
+        use bitstring::BitString;
+
+        // same bits as appending one at a time, at every alignment and width
+        let mut bs = DynBitString::null();
+        let mut expected = DynBitString::null();
+        let mut fields: Vec<(usize, u64, u32)> = Vec::new();
+        let mut value: u64 = 0x9e3779b97f4a7c15;
+        for nbits in (0..=64).chain(0..=64).chain([64, 1, 63, 7, 9]) {
+            value = value.rotate_left(7) ^ 0x5851f42d4c957f2d;
+            fields.push((bs.len(), value, nbits));
+            bs.append_uint(value, nbits);
+            for k in 0..nbits {
+                expected.append(value & (1 << k) != 0);
+            }
+        }
+        assert_eq!(bs, expected);
+        assert_eq!(bs.b, expected.b);
+        for (pos, value, nbits) in fields {
+            let mask = if nbits == 64 { u64::MAX } else { (1 << nbits) - 1 };
+            assert_eq!(bs.read_uint(pos, nbits), value & mask);
+        }
+
+        // after shrinking, growing again must not bring back the old bits
+        bs.clip(3);
+        bs.clip(103);
+        assert_eq!(bs.read_uint(3, 64), 0);
+        assert_eq!(bs.read_uint(67, 36), 0);
+
+        let mut copy = DynBitString::null();
+        copy.append(true);
+        copy.extend_from_bitslice(expected.slice(5..expected.len()));
+        assert_eq!(copy.len(), expected.len() - 4);
+        for k in 5..expected.len() {
+            assert_eq!(copy.get(k - 4), expected.get(k));
+        }
+    }
+
+    #[test]
+    pub fn test_to_from_bytes() {
+        use bitstring::BitString;
+
+        let bs = DynBitString::from_str("b10110000111").unwrap();
+        // least significant bit first, last byte padded with zeros
+        assert_eq!(bs.to_bytes(), vec![0x0d, 0x07]);
+        assert_eq!(DynBitString::from_bytes(&[0x0d, 0x07], 11).unwrap(), bs);
+        // padding bits are dropped
+        assert_eq!(DynBitString::from_bytes(&[0x0d, 0xff], 11).unwrap(), bs);
+        assert_eq!(DynBitString::from_bytes(&[0x0d], 11), Err(DBSFromBytesErr::TooFewBytes));
+        assert_eq!(DynBitString::from_bytes(&[0x0d, 0x07, 0], 11), Err(DBSFromBytesErr::TooManyBytes));
+        assert_eq!(DynBitString::null().to_bytes(), Vec::<u8>::new());
+        assert_eq!(DynBitString::from_bytes(&[], 0).unwrap().len(), 0);
+        // first bit in the high bit of the first byte
+        assert_eq!(bs.to_bytes_in(BitOrder::MsbFirst), vec![0xb0, 0xe0]);
+        assert_eq!(DynBitString::from_bytes_in(&[0xb0, 0xe0], 11, BitOrder::MsbFirst).unwrap(), bs);
+
+        // across word boundaries
+        let bytes: Vec<u8> = (0..37).map(|k| (k * 73 + 5) as u8).collect();
+        for bit_len in [281usize, 288, 289, 295, 296] {
+            let bs = DynBitString::from_bytes(&bytes[..bit_len.div_ceil(8)], bit_len).unwrap();
+            assert_eq!(bs.len(), bit_len);
+            for k in 0..bit_len {
+                assert_eq!(bs.get(k), bytes[k / 8] & (1 << (k % 8)) != 0);
+            }
+            let round_trip = bs.to_bytes();
+            assert_eq!(round_trip.len(), bit_len.div_ceil(8));
+            assert_eq!(DynBitString::from_bytes(&round_trip, bit_len).unwrap(), bs);
+        }
+    }
+}
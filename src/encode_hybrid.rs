@@ -0,0 +1,173 @@
+// hybrid value encoding, so that no value ever takes more than 1 bit more than its raw width
+// a selector bit chooses between
+//   SELECT_FACTORS - encode_prime::encode_factorization_with() of the value's Factorization
+//   SELECT_RAW     - the T::BITS bits of the value itself, least significant bit first
+// the factor encoding is only used when it is shorter than T::BITS,
+// so the worst case is T::BITS + 1 bits (33 for a u32).
+// 0, 1 and prime factors that are not in the prime table are left to the reserved
+// length codes of the factor encoding, the raw form is only there to bound the length.
+// prime_codec writes every value of a stream this way.
+
+use bitstring::BitString;
+use crate::bit_stream::{BitReader, BitWriter};
+use crate::dyn_bit_string::DynBitString;
+use crate::encode_prime::{try_decode_factorization_at_with, try_decode_factorization_traced_at_with, write_factorization_with,
+                          DecodedField, FactorCodes, Factorization, FieldKind, DEFAULT_FACTOR_CODES};
+use crate::encoding_uint_trait::{append_bits_lsb_first, try_read_bit, try_read_bits_lsb_first, DecodeError};
+use crate::prime_table::PrimeTable;
+use crate::primes::{factorization_of, factorization_to_uint};
+use crate::uint_width::UintWidth;
+
+pub const SELECT_FACTORS: bool = false;
+pub const SELECT_RAW: bool = true;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum HybridValue<T> {
+    Factored(Factorization<T>),
+    Raw(T),
+}
+
+// encode v given its Factorization, see primes::factorization_of()
+pub fn encode_hybrid<T: UintWidth>(v: T, f: &Factorization<T>) -> DynBitString {
+    let mut bs = DynBitString::null();
+    write_hybrid_with(&mut BitWriter::new(&mut bs), v, f, &DEFAULT_FACTOR_CODES);
+    bs
+}
+
+pub fn write_hybrid_with<T: UintWidth>(w: &mut BitWriter, v: T, f: &Factorization<T>, codes: &FactorCodes) {
+    let start = w.len();
+    w.write_bit(SELECT_FACTORS);
+    write_factorization_with(w, f, codes);
+    if w.len() - start - 1 < T::BITS as usize {
+        return;
+    }
+    // too long, take it back
+    w.bitstr_mut().clip(start);
+    w.write_bit(SELECT_RAW);
+    append_bits_lsb_first(w.bitstr_mut(), v.to_u128(), T::BITS);
+}
+
+// inverse of encode_hybrid(), starting at the cursor and leaving it after the encoding
+pub fn decode_hybrid<T: UintWidth>(bs: &DynBitString, cursor: &mut usize) -> HybridValue<T> {
+    try_decode_hybrid(bs, cursor).unwrap()
+}
+
+// same as decode_hybrid() but safe for untrusted bits, see DecodeError
+pub fn try_decode_hybrid<T: UintWidth>(bs: &DynBitString, cursor: &mut usize) -> Result<HybridValue<T>, DecodeError> {
+    try_decode_hybrid_with(bs, cursor, &DEFAULT_FACTOR_CODES)
+}
+
+pub fn try_decode_hybrid_with<T: UintWidth>(bs: &DynBitString, cursor: &mut usize, codes: &FactorCodes) -> Result<HybridValue<T>, DecodeError> {
+    let mut c = *cursor;
+    let v = if try_read_bit(bs, &mut c)? == SELECT_FACTORS {
+        HybridValue::Factored(try_decode_factorization_at_with(bs, &mut c, codes)?)
+    } else {
+        HybridValue::Raw(T::from_u128(try_read_bits_lsb_first(bs, &mut c, T::BITS)?))
+    };
+    *cursor = c;
+    Ok(v)
+}
+
+// same as try_decode_hybrid_with(), also appending each field it reads to trace,
+// see encode_prime::try_decode_factorization_traced_at_with()
+pub fn try_decode_hybrid_traced_with<T: UintWidth>(bs: &DynBitString, cursor: &mut usize, codes: &FactorCodes,
+                                                   trace: &mut Vec<DecodedField>) -> Result<HybridValue<T>, DecodeError> {
+    let mut c = *cursor;
+    let selector = try_read_bit(bs, &mut c);
+    trace.push(DecodedField { offset: *cursor, bits: c - *cursor, kind: FieldKind::Selector, value: selector.as_ref().ok().map(|b| *b as u128) });
+    let v = if selector? == SELECT_FACTORS {
+        HybridValue::Factored(try_decode_factorization_traced_at_with(bs, &mut c, codes, trace)?)
+    } else {
+        let offset = c;
+        let raw = try_read_bits_lsb_first(bs, &mut c, T::BITS);
+        trace.push(DecodedField { offset, bits: c - offset, kind: FieldKind::Raw, value: raw.as_ref().ok().copied() });
+        HybridValue::Raw(T::from_u128(raw?))
+    };
+    *cursor = c;
+    Ok(v)
+}
+
+// same as try_decode_hybrid_with(), reading at the reader's cursor
+pub fn try_read_hybrid_with<T: UintWidth>(r: &mut BitReader, codes: &FactorCodes) -> Result<HybridValue<T>, DecodeError> {
+    r.with_cursor(|bs, cursor| try_decode_hybrid_with(bs, cursor, codes))
+}
+
+// factor v with prms and encode it
+pub fn encode_value<T: UintWidth>(v: T, prms: &PrimeTable) -> DynBitString {
+    encode_hybrid(v, &factorization_of(v, prms))
+}
+
+// decode a value written by encode_value(), prms must contain every prime that
+// the encoder's table did
+pub fn decode_value<T: UintWidth>(bs: &DynBitString, cursor: &mut usize, prms: &PrimeTable) -> T {
+    match decode_hybrid::<T>(bs, cursor) {
+        HybridValue::Raw(v) => v,
+        HybridValue::Factored(f) => factorization_to_uint(&f, prms),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::dyn_bit_string::{append_bits, get_bits};
+
+    #[test]
+    pub fn test_encode_value() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut bs = DynBitString::null();
+        let mut values: Vec<u32> = (0..5000).collect();
+        let mut rng = rand::rng();
+        values.extend((0..5000).map(|_| rng.random::<u32>()));
+        values.extend([u32::MAX, 4294967291, 1 << 31]);
+        for v in values.iter() {
+            let e = encode_value(*v, &prms);
+            assert!(e.len() <= 33);
+            append_bits(&mut bs, &e);
+        }
+        let mut cursor: usize = 0;
+        for v in values.iter() {
+            assert_eq!(decode_value::<u32>(&bs, &mut cursor, &prms), *v);
+        }
+        assert_eq!(cursor, bs.len());
+    }
+
+    #[test]
+    pub fn test_selector() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut cursor: usize = 0;
+        // 2^31 has a short factor encoding, 4294967291 is a big prime
+        assert_eq!(decode_hybrid::<u32>(&encode_value(1u32 << 31, &prms), &mut cursor), HybridValue::Factored(Factorization::Indices([0; 31].to_vec())));
+        cursor = 0;
+        assert_eq!(decode_hybrid::<u32>(&encode_value(4294967291u32, &prms), &mut cursor), HybridValue::Raw(4294967291));
+        assert_eq!(cursor, 33);
+        // 0 and 1 have reserved codes, and so does a small cofactor outside the table
+        cursor = 0;
+        assert_eq!(decode_hybrid::<u16>(&encode_value(0u16, &prms), &mut cursor), HybridValue::Factored(Factorization::Zero));
+        assert!(encode_value(1u32, &prms).len() < 33);
+        let small_prms = PrimeTable::up_to(100);
+        cursor = 0;
+        assert_eq!(decode_hybrid::<u32>(&encode_value(4 * 101u32, &small_prms), &mut cursor),
+                   HybridValue::Factored(Factorization::BigCofactor { ixs: vec![0, 0], cofactor: 101 }));
+        assert_eq!(encode_value(18446744073709551557u64, &prms).len(), 65);
+        cursor = 0;
+        assert_eq!(decode_value::<u64>(&encode_value(1u64 << 63, &prms), &mut cursor, &prms), 1 << 63);
+    }
+
+    #[test]
+    pub fn test_try_decode_hybrid() {
+        let prms = PrimeTable::up_to(1 << 16);
+        for v in [4294967291u32, 1 << 31, 0, 6] {
+            let e = encode_value(v, &prms);
+            for len in 0..e.len() {
+                let mut cursor: usize = 0;
+                assert_eq!(try_decode_hybrid::<u32>(&get_bits(&e, 0, len as u32).unwrap_or(DynBitString::null()), &mut cursor), Err(DecodeError::Truncated));
+                assert_eq!(cursor, 0);
+            }
+            let mut cursor: usize = 0;
+            assert!(try_decode_hybrid::<u32>(&e, &mut cursor).is_ok());
+            assert_eq!(cursor, e.len());
+        }
+    }
+}
@@ -5,21 +5,31 @@
 // or decode bitstring into factorization array
 // factorization array contains INDICES of primes not prime numbers
 // see primes::indices_to_prime_factors to convert indices to prime numbers
+// the prime indexes have the same unsigned integer type T as the integer that was
+// factored (see primes::factor_uint), and T sets the width of the encoding
 
+use std::fmt;
+use bitstring::BitString;
+use crate::bit_slice::BitSlice;
+use crate::bit_stream::{BitReader, BitWriter};
 use crate::dyn_bit_string::DynBitString;
-use crate::encoding_small_int::SmallIntEncoding;
-use crate::encoding_u32::U32Encoding;
-use crate::encoding_uint_trait::EncodingUint;
+use crate::encoding_bounded::{append_bounded_uint, try_read_bounded_uint};
+use crate::encoding_small_int::SMALL_INT_DEFAULT_CONTINUE_OFFSETS;
+use crate::encoding_u32::U32_DEFAULT_CONTINUE_OFFSETS;
+use crate::prime_count::{max_prime_index_up_to, prime_at_least};
+use crate::encoding_uint_trait::{try_uint_from_u128, DecodeError};
+use crate::uint_code::{append_code, try_read_code, UintCode};
+use crate::uint_width::UintWidth;
 
 #[derive(Debug)]
-pub struct PrmPwr {
+pub struct PrmPwr<T = u32> {
     pub exp : u8,          // exponent
-    pub prm_idx : u32      // prime number zero-based index
+    pub prm_idx : T        // prime number zero-based index
 }
 
 #[derive(Debug)]
-pub struct IntAsPrms {
-    pub prm_powers : Vec<PrmPwr>
+pub struct IntAsPrms<T = u32> {
+    pub prm_powers : Vec<PrmPwr<T>>
 }
 
 // input is a vector of non-decreasing prime number integers
@@ -27,7 +37,7 @@ pub struct IntAsPrms {
 // output is hopefully more compact representation of
 // factors as powers of primes
 
-pub fn factors_to_int_as_prms( prm_factors : &[u32] ) -> IntAsPrms {
+pub fn factors_to_int_as_prms<T: UintWidth>( prm_factors : &[T] ) -> IntAsPrms<T> {
     let mut iap = IntAsPrms { prm_powers: Vec::new() };
     let first_prmpwr = PrmPwr { exp: 0, prm_idx: prm_factors[0] };
     iap.prm_powers.push(first_prmpwr);
@@ -45,32 +55,173 @@ pub fn factors_to_int_as_prms( prm_factors : &[u32] ) -> IntAsPrms {
     iap
 }
 
-// encode a IntAsPrms structure as a bit string using
-// variable-length unsigned integer encoding
-// v - prime number factorization,
-//      each number must be prime number index
-//      sequence must be of non-zero length and non-decreasing
+// any unsigned integer in factorized form, see primes::factorization_of()
+// lets us encode 0 and 1, and numbers with prime factors that are not in the prime table
 
-pub fn encode_factors( v : &[u32] ) -> DynBitString {
-    assert!(!v.is_empty());
-    let iap = factors_to_int_as_prms(v);
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub enum Factorization<T = u32> {
+    Zero,
+    One,
+    Indices(Vec<T>),                          // non-decreasing prime indexes, same as encode_factors() takes
+    BigCofactor { ixs: Vec<T>, cofactor: T }, // ixs may be empty, cofactor is product of primes not in table
+}
 
+// the number of elements in an IntAsPrms structure is much smaller than 2^T::LEN_BITS
+// (at most 9 for a u32, since 2*3*5*...*29 > 2^32), so the largest 4 length codes
+// can never be used as lengths, and we reserve them for the other kinds of Factorization,
+// and for a stream of encodings to mark its end with, see prime_codec.
+// the decoders take the end of stream code to be an InvalidLength
 
-    // append SmallIntEncoding containing
-    // first encode the length of IntAsPrms
-    // followed by each exponent
+pub const ZERO_CODE_OFFSET: u128 = 0;
+pub const ONE_CODE_OFFSET: u128 = 1;
+pub const BIG_COFACTOR_CODE_OFFSET: u128 = 2;
+pub const END_OF_STREAM_CODE_OFFSET: u128 = 3;
 
-    let mut small_int_encoding = SmallIntEncoding::new();
+pub fn reserved_length_code<T: UintWidth>(offset: u128) -> T {
+    T::from_u128((1 << T::LEN_BITS) - 1 - offset)
+}
 
-    // the number of elements in the IntAsPrms structure
-    // is encoded by subtracting 1 first, since there is no
-    // reason to use a structure with zero elements
+// which unsigned integer code to use for each field of the factor encoding
+// SmallInt is only able to hold values below 2^T::LEN_BITS, which is fine for
+// the length and the exponents but not for the prime index differences.
+// the cofactor of a BigCofactor is not one of these fields, it is always written
+// with COFACTOR_CODE, since it is rare and there is nothing to tune it on
 
-    let l = iap.prm_powers.len();
-    assert!(l > 0);
-    small_int_encoding.append_uint32(l as u32 - 1);
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub struct FactorCodes {
+    pub length : UintCode,         // number of prime powers - 1, and the reserved codes
+    pub exponent : UintCode,       // exponent - 1
+    pub index_delta : UintCode,    // difference from the previous prime index
+}
+
+pub const COFACTOR_CODE: UintCode = UintCode::U32(U32_DEFAULT_CONTINUE_OFFSETS);
+
+pub const DEFAULT_FACTOR_CODES: FactorCodes = FactorCodes {
+    length: UintCode::SmallInt(SMALL_INT_DEFAULT_CONTINUE_OFFSETS),
+    exponent: UintCode::SmallInt(SMALL_INT_DEFAULT_CONTINUE_OFFSETS),
+    index_delta: UintCode::U32(U32_DEFAULT_CONTINUE_OFFSETS),
+};
 
-    for nxt_ppwr in iap.prm_powers.as_slice() {
+// where the bits of one factor encoding go, field by field, as recorded by the
+// encoder itself, see encode_factors_breakdown_with(). stats tools can add these
+// up per field, and format() shows the bits of each field
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Default)]
+pub struct EncodingBreakdown {
+    pub length_bits : u32,            // length code, and for a BigCofactor the prime power count after it
+    pub exponent_bits : Vec<u32>,     // one per prime power
+    pub index_delta_bits : Vec<u32>,  // one per prime power
+    pub cofactor_bits : u32,          // BigCofactor only
+}
+
+impl EncodingBreakdown {
+    pub fn exponents_total_bits(&self) -> u32 {
+        self.exponent_bits.iter().sum()
+    }
+
+    pub fn index_deltas_total_bits(&self) -> u32 {
+        self.index_delta_bits.iter().sum()
+    }
+
+    pub fn total_bits(&self) -> u32 {
+        self.length_bits + self.exponents_total_bits() + self.index_deltas_total_bits() + self.cofactor_bits
+    }
+
+    // the length, [ exponents ] and [ index deltas ] of the encoding at the start of bits,
+    // followed by the cofactor if there is one
+    pub fn format(&self, bits : BitSlice) -> String {
+        let mut pos : usize = 0;
+        let mut next_field = |field_bits : u32| {
+            let field = bits.slice(pos..pos + field_bits as usize).to_bit_string();
+            pos += field_bits as usize;
+            field.to_string()
+        };
+        let mut out_str = next_field(self.length_bits);
+        out_str += " [ ";
+        for b in self.exponent_bits.iter() {
+            out_str += &next_field(*b);
+            out_str += " ";
+        }
+        out_str += "] [ ";
+        for b in self.index_delta_bits.iter() {
+            out_str += &next_field(*b);
+            out_str += " ";
+        }
+        out_str += " ] ";
+        if self.cofactor_bits > 0 {
+            out_str += &next_field(self.cofactor_bits);
+        }
+        out_str
+    }
+}
+
+// one field of a factor or hybrid encoding as the decoder read it, the decoding side of
+// EncodingBreakdown, see try_decode_factorization_traced_at_with(). tools that need to
+// say where the bits went, or where they stop making sense, read this trace instead of
+// walking the fields themselves
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub enum FieldKind {
+    Selector,          // encode_hybrid's choice of factors or raw bits
+    Raw,               // encode_hybrid's raw value
+    Length,            // number of prime powers - 1, or a reserved code
+    PrimePowerCount,   // number of prime powers, after the big cofactor code
+    Exponent,          // exponent - 1
+    IndexDelta,        // difference from the previous prime index
+    Cofactor,
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FieldKind::Selector => "selector",
+            FieldKind::Raw => "raw",
+            FieldKind::Length => "length",
+            FieldKind::PrimePowerCount => "count",
+            FieldKind::Exponent => "exponent",
+            FieldKind::IndexDelta => "index delta",
+            FieldKind::Cofactor => "cofactor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub struct DecodedField {
+    pub offset : usize,          // of the first bit of the field
+    pub bits : usize,            // 0 if the field could not be read
+    pub kind : FieldKind,
+    pub value : Option<u128>,    // as read, None if the field could not be read
+}
+
+// read a field at the cursor with try_read_code(), and add it to the trace if there is one,
+// whether it could be read or not
+pub fn try_read_field<T: UintWidth>( code : UintCode, kind : FieldKind, bs : &DynBitString, cursor : &mut usize,
+                                     trace : Option<&mut Vec<DecodedField>> ) -> Result<T, DecodeError> {
+    let offset = *cursor;
+    let v = try_read_code::<T>(code, bs, cursor);
+    if let Some(t) = trace {
+        let bits = if v.is_ok() { *cursor - offset } else { 0 };
+        t.push(DecodedField { offset, bits, kind, value: v.as_ref().ok().map(|v| v.to_u128()) });
+    }
+    v
+}
+
+// append each exponent, followed by the prime index differences
+// and record their lengths if asked to
+fn encode_prime_powers<T: UintWidth>( bs : &mut DynBitString, prm_powers : &[PrmPwr<T>], codes : &FactorCodes,
+                                      mut breakdown : Option<&mut EncodingBreakdown> ) {
+    for nxt_ppwr in prm_powers {
         // there is no reason to include a prime
         // with an exponent of zero, which would just be
         // a factor 1 anyway
@@ -78,13 +229,15 @@ pub fn encode_factors( v : &[u32] ) -> DynBitString {
         // so we can subtract 2 from the exponent to improve
         // compression
         assert!(nxt_ppwr.exp > 0);
-        small_int_encoding.append_uint32((nxt_ppwr.exp - 1) as u32);
+        let len_before = bs.len();
+        append_code(codes.exponent, bs, T::from_u128((nxt_ppwr.exp - 1) as u128));
+        if let Some(b) = breakdown.as_deref_mut() {
+            b.exponent_bits.push((bs.len() - len_before) as u32);
+        }
     }
 
-    let encoding_so_far = small_int_encoding.get_bitstr_encoding();
-    let mut index_encoding = U32Encoding::from_bitstr_encoding(encoding_so_far);
-    let mut prev_index: u32 = 0;
-    for nxt_ppwr in iap.prm_powers.as_slice() {
+    let mut prev_index = T::ZERO;
+    for nxt_ppwr in prm_powers {
         // we encode the INDEX of the prime, because the
         // index of the prime will be significantly smaller than
         // the prime itself for large primes so this
@@ -93,57 +246,61 @@ pub fn encode_factors( v : &[u32] ) -> DynBitString {
         // encode the difference between this index and the last index
         // to further shrink the size of the encoding.
 
-        index_encoding.append_uint32(nxt_ppwr.prm_idx - prev_index);
+        let len_before = bs.len();
+        append_code(codes.index_delta, bs, nxt_ppwr.prm_idx - prev_index);
         prev_index = nxt_ppwr.prm_idx;
+        if let Some(b) = breakdown.as_deref_mut() {
+            b.index_delta_bits.push((bs.len() - len_before) as u32);
+        }
     }
-    index_encoding.get_bitstr_encoding()
 }
 
-// decode the bitstring into a factorization array
-// output array is non-decreasing and contains INDICES of prime numbers
+// encode a IntAsPrms structure as a bit string using
+// variable-length unsigned integer encoding
+// v - prime number factorization,
+//      each number must be prime number index
+//      sequence must be of non-zero length and non-decreasing
 
-pub fn decode_factors( bs : &DynBitString ) -> Vec<u32> {
-    let mut ppwrs : Vec<PrmPwr> = vec![];
-    let mut exponents : Vec<u32> = vec![];
-    let mut cursor : usize = 0;
-    let small_int_encoding = SmallIntEncoding::from_bitstr_encoding(bs.clone());
-    let mut prev_index : u32 = 0;
-    let l = small_int_encoding.read_uint32(&mut cursor) + 1;
-    ppwrs.reserve_exact(l as usize);
-    exponents.reserve_exact(l as usize);
-    for _k in 0..l {
-        let next_exponent = small_int_encoding.read_uint32(&mut cursor) + 1;
-        exponents.push(next_exponent);
-    }
-    let encoding_so_far = small_int_encoding.get_bitstr_encoding();
-    let index_encoding = U32Encoding::from_bitstr_encoding(encoding_so_far);
-    for k  in 0..l as usize {
-        let next_prm_index = index_encoding.read_uint32(&mut cursor) + prev_index;
-        prev_index = next_prm_index;
-        let nxt_prime_power = PrmPwr { exp: exponents[k] as u8, prm_idx: next_prm_index };
-        ppwrs.push(nxt_prime_power);
-    }
-    let mut factors : Vec<u32> = vec![];
-    for ppwr in ppwrs {
-        for _k in 0..ppwr.exp {
-            factors.push(ppwr.prm_idx);
-        }
-    }
-    factors
+pub fn encode_factors<T: UintWidth>( v : &[T] ) -> DynBitString {
+    encode_factors_with(v, &DEFAULT_FACTOR_CODES)
 }
 
-// format factorization encoding in a way that lets you see how
-// effective/ineffective the encoding is for the components
-// this implementation is closely tied to encode_factors()
+// same as encode_factors() with the codes of your choice,
+// decode it with the same codes
+
+pub fn encode_factors_with<T: UintWidth>( v : &[T], codes : &FactorCodes ) -> DynBitString {
+    let mut bs = DynBitString::null();
+    write_factors_with(&mut BitWriter::new(&mut bs), v, codes);
+    bs
+}
+
+// same as encode_factors_with(), also returning where its bits went
+pub fn encode_factors_breakdown_with<T: UintWidth>( v : &[T], codes : &FactorCodes ) -> (DynBitString, EncodingBreakdown) {
+    let mut bs = DynBitString::null();
+    let breakdown = write_factors_breakdown_with(&mut BitWriter::new(&mut bs), v, codes);
+    (bs, breakdown)
+}
+
+// same as encode_factors_with(), appending to the writer's bitstring instead of a new one
+pub fn write_factors_with<T: UintWidth>( w : &mut BitWriter, v : &[T], codes : &FactorCodes ) {
+    write_factors_recording(w, v, codes, None)
+}
 
-pub fn format_factor_encoding_as_string( v : &[u32] ) -> String {
+pub fn write_factors_breakdown_with<T: UintWidth>( w : &mut BitWriter, v : &[T], codes : &FactorCodes ) -> EncodingBreakdown {
+    let mut breakdown = EncodingBreakdown::default();
+    write_factors_recording(w, v, codes, Some(&mut breakdown));
+    breakdown
+}
+
+fn write_factors_recording<T: UintWidth>( w : &mut BitWriter, v : &[T], codes : &FactorCodes,
+                                          mut breakdown : Option<&mut EncodingBreakdown> ) {
+    assert!(!v.is_empty());
     let iap = factors_to_int_as_prms(v);
-    let mut out_str = "".to_string();
 
-    // append SmallIntEncoding containing
     // first encode the length of IntAsPrms
     // followed by each exponent
 
+    let bs = w.bitstr_mut();
 
     // the number of elements in the IntAsPrms structure
     // is encoded by subtracting 1 first, since there is no
@@ -151,48 +308,393 @@ pub fn format_factor_encoding_as_string( v : &[u32] ) -> String {
 
     let l = iap.prm_powers.len();
     assert!(l > 0);
-    let mut length_encoding = SmallIntEncoding::new();
-    length_encoding.append_uint32(l as u32 - 1);
-    let length_bs = length_encoding.get_bitstr_encoding();
-    out_str += length_bs.to_string().as_str();
-    out_str += " [ ";
+    let length_code = T::from_u128(l as u128 - 1);
+    assert!(length_code < reserved_length_code(END_OF_STREAM_CODE_OFFSET));
+    let len_before = bs.len();
+    append_code(codes.length, bs, length_code);
+    if let Some(b) = breakdown.as_deref_mut() {
+        b.length_bits = (bs.len() - len_before) as u32;
+    }
 
-    for nxt_ppwr in iap.prm_powers.as_slice() {
-        // there is no reason to include a prime
-        // with an exponent of zero, which would just be
-        // a factor 1 anyway
-        // if the exponent was 1 then we would not be here.
-        // so we can subtract 2 from the exponent to improve
-        // compression
-        assert!(nxt_ppwr.exp > 0);
-        let mut small_int_encoding = SmallIntEncoding::new();
-        small_int_encoding.append_uint32((nxt_ppwr.exp - 1) as u32);
-        out_str += small_int_encoding.get_bitstr_encoding().to_string().as_str();
-        out_str += " ";
+    encode_prime_powers(bs, &iap.prm_powers, codes, breakdown);
+}
+
+// encode any Factorization, Indices are encoded exactly the way encode_factors() does
+// the other kinds start with a reserved length code. a BigCofactor is followed by
+// the number of prime powers (not minus 1, since there may be none), the prime powers
+// the same way encode_factors() does them, and then the cofactor in a U32Encoding
+
+pub fn encode_factorization<T: UintWidth>( f : &Factorization<T> ) -> DynBitString {
+    encode_factorization_with(f, &DEFAULT_FACTOR_CODES)
+}
+
+pub fn encode_factorization_with<T: UintWidth>( f : &Factorization<T>, codes : &FactorCodes ) -> DynBitString {
+    let mut bs = DynBitString::null();
+    write_factorization_with(&mut BitWriter::new(&mut bs), f, codes);
+    bs
+}
+
+pub fn write_factorization_with<T: UintWidth>( w : &mut BitWriter, f : &Factorization<T>, codes : &FactorCodes ) {
+    write_factorization_recording(w, f, codes, None)
+}
+
+// same as write_factorization_with(), also returning where its bits went
+pub fn write_factorization_breakdown_with<T: UintWidth>( w : &mut BitWriter, f : &Factorization<T>, codes : &FactorCodes ) -> EncodingBreakdown {
+    let mut breakdown = EncodingBreakdown::default();
+    write_factorization_recording(w, f, codes, Some(&mut breakdown));
+    breakdown
+}
+
+fn write_factorization_recording<T: UintWidth>( w : &mut BitWriter, f : &Factorization<T>, codes : &FactorCodes,
+                                                mut breakdown : Option<&mut EncodingBreakdown> ) {
+    let len_before = w.len();
+    match f {
+        Factorization::Zero => {
+            w.write_code(codes.length, reserved_length_code::<T>(ZERO_CODE_OFFSET));
+        }
+        Factorization::One => {
+            w.write_code(codes.length, reserved_length_code::<T>(ONE_CODE_OFFSET));
+        }
+        Factorization::Indices(ixs) => {
+            write_factors_recording(w, ixs, codes, breakdown);
+            return;
+        }
+        Factorization::BigCofactor { ixs, cofactor } => {
+            w.write_code(codes.length, reserved_length_code::<T>(BIG_COFACTOR_CODE_OFFSET));
+            let prm_powers = if ixs.is_empty() { vec![] } else { factors_to_int_as_prms(ixs).prm_powers };
+            let length_code = T::from_u128(prm_powers.len() as u128);
+            assert!(length_code < reserved_length_code(BIG_COFACTOR_CODE_OFFSET));
+            w.write_code(codes.length, length_code);
+            if let Some(b) = breakdown.as_deref_mut() {
+                b.length_bits = (w.len() - len_before) as u32;
+            }
+            encode_prime_powers(w.bitstr_mut(), &prm_powers, codes, breakdown.as_deref_mut());
+            let cofactor_start = w.len();
+            w.write_code(COFACTOR_CODE, *cofactor);
+            if let Some(b) = breakdown {
+                b.cofactor_bits = (w.len() - cofactor_start) as u32;
+            }
+            return;
+        }
+    }
+    if let Some(b) = breakdown {
+        b.length_bits = (w.len() - len_before) as u32;
     }
-    out_str += "] [ ";
+}
 
-    let mut prev_index: u32 = 0;
-    for nxt_ppwr in iap.prm_powers.as_slice() {
-        let mut index_encoding = U32Encoding::new();
-        index_encoding.append_uint32(nxt_ppwr.prm_idx - prev_index);
-        prev_index = nxt_ppwr.prm_idx;
-        out_str += index_encoding.get_bitstr_encoding().to_string().as_str();
-        out_str += " ";
+// decode the bitstring into a factorization array
+// output array is non-decreasing and contains INDICES of prime numbers
+// T must be the same type that was passed to encode_factors()
+
+pub fn decode_factors<T: UintWidth>( bs : &DynBitString ) -> Vec<T> {
+    let mut cursor : usize = 0;
+    decode_factors_at(bs, &mut cursor)
+}
+
+// same as decode_factors() but start at the bit offset indicated by the cursor,
+// and leave the cursor pointing at the first bit after the encoding.
+// the encoding is self-delimiting, so many of them can be concatenated in one bitstring
+
+pub fn decode_factors_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Vec<T> {
+    decode_factors_at_with(bs, cursor, &DEFAULT_FACTOR_CODES)
+}
+
+// inverse of encode_factors_with()
+pub fn decode_factors_at_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes ) -> Vec<T> {
+    match try_decode_factors_at_with(bs, cursor, codes) {
+        Ok(factors) => factors,
+        Err(DecodeError::InvalidLength) => panic!("not a list of factors, use decode_factorization()"),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+// the try_ decoders return an error instead of panicking, so they are safe to use on
+// bits from outside. besides truncation, they check that the length code is not one
+// that encode_factors() never writes, and that exponents and prime indexes fit in T.
+// on error the cursor is left where it was.
+
+pub fn try_decode_factors<T: UintWidth>( bs : &DynBitString ) -> Result<Vec<T>, DecodeError> {
+    let mut cursor : usize = 0;
+    try_decode_factors_at(bs, &mut cursor)
+}
+
+pub fn try_decode_factors_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Result<Vec<T>, DecodeError> {
+    try_decode_factors_at_with(bs, cursor, &DEFAULT_FACTOR_CODES)
+}
+
+pub fn try_decode_factors_at_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes ) -> Result<Vec<T>, DecodeError> {
+    let mut factors : Vec<T> = vec![];
+    try_decode_factors_into_with(bs, cursor, codes, &mut factors)?;
+    Ok(factors)
+}
+
+// same as try_decode_factors_at_with(), but into a vector the caller can reuse
+// for every value, so decoding does not allocate. factors is cleared first.
+pub fn try_decode_factors_into_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes,
+                                                   factors : &mut Vec<T> ) -> Result<(), DecodeError> {
+    factors.clear();
+    let mut c = *cursor;
+    let length_code = try_read_code::<T>(codes.length, bs, &mut c)?;
+    if length_code >= reserved_length_code(END_OF_STREAM_CODE_OFFSET) {
+        return Err(DecodeError::InvalidLength);
+    }
+    try_decode_prime_powers_into(bs, &mut c, length_code.to_u128() as usize + 1, codes, None, factors)?;
+    *cursor = c;
+    Ok(())
+}
+
+// same as try_decode_factors_at_with(), reading at the reader's cursor
+pub fn try_read_factors_with<T: UintWidth>( r : &mut BitReader, codes : &FactorCodes ) -> Result<Vec<T>, DecodeError> {
+    r.with_cursor(|bs, cursor| try_decode_factors_at_with(bs, cursor, codes))
+}
+
+pub fn try_read_factors_into<T: UintWidth>( r : &mut BitReader, codes : &FactorCodes, factors : &mut Vec<T> ) -> Result<(), DecodeError> {
+    r.with_cursor(|bs, cursor| try_decode_factors_into_with(bs, cursor, codes, factors))
+}
+
+// read l exponents and l prime index differences written by encode_prime_powers()
+fn try_decode_prime_powers_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, l : usize, codes : &FactorCodes,
+                                             trace : Option<&mut Vec<DecodedField>> ) -> Result<Vec<T>, DecodeError> {
+    let mut factors : Vec<T> = vec![];
+    try_decode_prime_powers_into(bs, cursor, l, codes, trace, &mut factors)?;
+    Ok(factors)
+}
+
+// same as try_decode_prime_powers_at(), appending the prime indexes to factors
+// a T has fewer than T::BITS distinct prime factors, so more prime powers than that
+// is an invalid length, and the exponents fit in an array on the stack
+fn try_decode_prime_powers_into<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, l : usize, codes : &FactorCodes,
+                                               mut trace : Option<&mut Vec<DecodedField>>, factors : &mut Vec<T> ) -> Result<(), DecodeError> {
+    let mut exponents = [0u8; u128::BITS as usize];
+    if l > T::BITS as usize {
+        return Err(DecodeError::InvalidLength);
+    }
+    for exponent in exponents[..l].iter_mut() {
+        // p^e >= 2^e, so an exponent above T::BITS cannot be part of a T
+        let next_exponent = try_read_field::<T>(codes.exponent, FieldKind::Exponent, bs, cursor, trace.as_deref_mut())?.to_u128() + 1;
+        if next_exponent > T::BITS as u128 {
+            return Err(DecodeError::Overflow);
+        }
+        *exponent = next_exponent as u8;
+    }
+    let mut prev_index = T::ZERO;
+    for next_exponent in &exponents[..l] {
+        let index_delta = try_read_field::<T>(codes.index_delta, FieldKind::IndexDelta, bs, cursor, trace.as_deref_mut())?;
+        let next_prm_index = prev_index.to_u128().checked_add(index_delta.to_u128()).ok_or(DecodeError::Overflow)?;
+        let next_prm_index : T = try_uint_from_u128(next_prm_index)?;
+        prev_index = next_prm_index;
+        factors.extend(std::iter::repeat_n(next_prm_index, *next_exponent as usize));
+    }
+    Ok(())
+}
+
+// inverse of encode_factorization(), starting at the cursor and leaving it after the encoding
+
+pub fn decode_factorization_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Factorization<T> {
+    decode_factorization_at_with(bs, cursor, &DEFAULT_FACTOR_CODES)
+}
+
+pub fn decode_factorization_at_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes ) -> Factorization<T> {
+    try_decode_factorization_at_with(bs, cursor, codes).unwrap()
+}
+
+pub fn try_decode_factorization_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Result<Factorization<T>, DecodeError> {
+    try_decode_factorization_at_with(bs, cursor, &DEFAULT_FACTOR_CODES)
+}
+
+pub fn try_decode_factorization_at_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes ) -> Result<Factorization<T>, DecodeError> {
+    try_decode_factorization_recording(bs, cursor, codes, None)
+}
+
+// same as try_decode_factorization_at_with(), also appending each field it reads to trace.
+// on error the last field in trace is the one the decoder stopped at, either because it
+// could not be read, or because its value is not allowed there
+
+pub fn try_decode_factorization_traced_at_with<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes,
+                                                              trace : &mut Vec<DecodedField> ) -> Result<Factorization<T>, DecodeError> {
+    try_decode_factorization_recording(bs, cursor, codes, Some(trace))
+}
+
+fn try_decode_factorization_recording<T: UintWidth>( bs : &DynBitString, cursor : &mut usize, codes : &FactorCodes,
+                                                     mut trace : Option<&mut Vec<DecodedField>> ) -> Result<Factorization<T>, DecodeError> {
+    let mut c = *cursor;
+    let length_code = try_read_field::<T>(codes.length, FieldKind::Length, bs, &mut c, trace.as_deref_mut())?;
+    let f = if length_code == reserved_length_code(ZERO_CODE_OFFSET) {
+        Factorization::Zero
+    } else if length_code == reserved_length_code(ONE_CODE_OFFSET) {
+        Factorization::One
+    } else if length_code == reserved_length_code(BIG_COFACTOR_CODE_OFFSET) {
+        let l = try_read_field::<T>(codes.length, FieldKind::PrimePowerCount, bs, &mut c, trace.as_deref_mut())?;
+        if l >= reserved_length_code(BIG_COFACTOR_CODE_OFFSET) {
+            return Err(DecodeError::InvalidLength);
+        }
+        let ixs = try_decode_prime_powers_at(bs, &mut c, l.to_u128() as usize, codes, trace.as_deref_mut())?;
+        let cofactor = try_read_field::<T>(COFACTOR_CODE, FieldKind::Cofactor, bs, &mut c, trace)?;
+        Factorization::BigCofactor { ixs, cofactor }
+    } else if length_code >= reserved_length_code(END_OF_STREAM_CODE_OFFSET) {
+        // end of stream, or a code past the reserved ones that only a code wider than
+        // T::LEN_BITS, like EliasGamma, can hold
+        return Err(DecodeError::InvalidLength);
+    } else {
+        Factorization::Indices(try_decode_prime_powers_at(bs, &mut c, length_code.to_u128() as usize + 1, codes, trace)?)
+    };
+    *cursor = c;
+    Ok(f)
+}
+
+pub fn try_read_factorization_with<T: UintWidth>( r : &mut BitReader, codes : &FactorCodes ) -> Result<Factorization<T>, DecodeError> {
+    r.with_cursor(|bs, cursor| try_decode_factorization_at_with(bs, cursor, codes))
+}
+
+// magnitude-budget encoding of a factor list
+// the product of the factors must fit in T, so once some prime powers are known,
+// the ones that follow can only come from a smaller range. we keep an upper bound on
+// what is left of T::MAX (the budget) and code each prime index and exponent with
+// encoding_bounded::append_bounded_uint() over only the values that still fit:
+//   number of prime powers - 1, in DEFAULT_FACTOR_CODES.length
+//   then for each prime power p^e, smallest prime first
+//     prime index - (previous prime index + 1), up to the largest index whose prime
+//         leaves room for p^(prime powers left), since the primes after p are bigger
+//     e - 1, up to the largest e that leaves room for the prime powers after this one
+//   and the budget is divided by p^e
+// no prime table is needed, prime_count bounds p from its index and the index from p,
+// so the encoder and decoder always agree on the bounds.
+// there are no reserved codes, so this only encodes a non-empty list of prime indexes.
+
+// floor of the m'th root of x
+fn iroot(x : u128, m : u32) -> u128 {
+    if m == 1 {
+        return x;
+    }
+    let mut r = (x as f64).powf(1.0 / m as f64) as u128;
+    // the floating point estimate can be off by a little either way
+    while r > 0 && r.checked_pow(m).is_none_or(|rm| rm > x) {
+        r -= 1;
+    }
+    while (r + 1).checked_pow(m).is_some_and(|rm| rm <= x) {
+        r += 1;
+    }
+    r
+}
+
+// largest e with p_min^(e + powers_after) <= budget
+fn max_exponent_within_budget(budget : u128, p_min : u128, powers_after : u32) -> u32 {
+    let mut e = 0;
+    while p_min.checked_pow(e + 1 + powers_after).is_some_and(|pp| pp <= budget) {
+        e += 1;
+    }
+    e
+}
+
+pub fn encode_factors_bounded<T: UintWidth>( v : &[T] ) -> DynBitString {
+    assert!(!v.is_empty());
+    let iap = factors_to_int_as_prms(v);
+    let l = iap.prm_powers.len();
+    let mut bs = DynBitString::null();
+    append_code(DEFAULT_FACTOR_CODES.length, &mut bs, T::from_u128(l as u128 - 1));
+
+    let mut budget = T::from_u128(u128::MAX >> (u128::BITS - T::BITS)).to_u128();
+    let mut lowest_index : u128 = 0;
+    for (k, ppwr) in iap.prm_powers.iter().enumerate() {
+        let powers_left = (l - k) as u32;
+        let ix = ppwr.prm_idx.to_u128();
+        let max_index = max_prime_index_up_to(iroot(budget, powers_left)).unwrap();
+        assert!(lowest_index <= ix && ix <= max_index, "product of factors does not fit in {} bits", T::BITS);
+        append_bounded_uint(&mut bs, ix - lowest_index, max_index - lowest_index);
+
+        let p_min = prime_at_least(ix);
+        let max_exponent = max_exponent_within_budget(budget, p_min, powers_left - 1);
+        let exponent = ppwr.exp as u32;
+        assert!(exponent <= max_exponent, "product of factors does not fit in {} bits", T::BITS);
+        append_bounded_uint(&mut bs, (exponent - 1) as u128, (max_exponent - 1) as u128);
+
+        budget /= p_min.pow(exponent);
+        lowest_index = ix + 1;
+    }
+    bs
+}
+
+// inverse of encode_factors_bounded()
+pub fn decode_factors_bounded<T: UintWidth>( bs : &DynBitString ) -> Vec<T> {
+    let mut cursor : usize = 0;
+    decode_factors_bounded_at(bs, &mut cursor)
+}
+
+pub fn decode_factors_bounded_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Vec<T> {
+    match try_decode_factors_bounded_at(bs, cursor) {
+        Ok(factors) => factors,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+// same as decode_factors_bounded_at(), but safe for bits from outside like try_decode_factors().
+// the bounds leave no room for an index or exponent out of range, so besides truncation
+// the only error is a length with more prime powers than fit in a T, an InvalidLength.
+// on error the cursor is left where it was.
+
+pub fn try_decode_factors_bounded<T: UintWidth>( bs : &DynBitString ) -> Result<Vec<T>, DecodeError> {
+    let mut cursor : usize = 0;
+    try_decode_factors_bounded_at(bs, &mut cursor)
+}
+
+pub fn try_decode_factors_bounded_at<T: UintWidth>( bs : &DynBitString, cursor : &mut usize ) -> Result<Vec<T>, DecodeError> {
+    let mut c = *cursor;
+    let length_code = try_read_code::<T>(DEFAULT_FACTOR_CODES.length, bs, &mut c)?;
+    if length_code >= reserved_length_code(END_OF_STREAM_CODE_OFFSET) {
+        return Err(DecodeError::InvalidLength);
     }
-    out_str += " ] ";
+    let l = length_code.to_u128() as usize + 1;
+    let mut factors : Vec<T> = vec![];
+    let mut budget = T::from_u128(u128::MAX >> (u128::BITS - T::BITS)).to_u128();
+    let mut lowest_index : u128 = 0;
+    for k in 0..l {
+        let powers_left = (l - k) as u32;
+        let max_index = match max_prime_index_up_to(iroot(budget, powers_left)) {
+            Some(max_index) if lowest_index <= max_index => max_index,
+            _ => { return Err(DecodeError::InvalidLength); }
+        };
+        let ix = lowest_index + try_read_bounded_uint(bs, &mut c, max_index - lowest_index)?;
+
+        let p_min = prime_at_least(ix);
+        let max_exponent = max_exponent_within_budget(budget, p_min, powers_left - 1);
+        if max_exponent == 0 {
+            return Err(DecodeError::InvalidLength);
+        }
+        let exponent = try_read_bounded_uint(bs, &mut c, (max_exponent - 1) as u128)? as u32 + 1;
+
+        for _j in 0..exponent {
+            factors.push(T::from_u128(ix));
+        }
+        budget /= p_min.pow(exponent);
+        lowest_index = ix + 1;
+    }
+    *cursor = c;
+    Ok(factors)
+}
+
+// format factorization encoding in a way that lets you see how
+// effective/ineffective the encoding is for the components
+// see EncodingBreakdown::format()
+
+pub fn format_factor_encoding_as_string<T: UintWidth>( v : &[T] ) -> String {
+    format_factor_encoding_as_string_with(v, &DEFAULT_FACTOR_CODES)
+}
 
-    out_str
+pub fn format_factor_encoding_as_string_with<T: UintWidth>( v : &[T], codes : &FactorCodes ) -> String {
+    let (bs, breakdown) = encode_factors_breakdown_with(v, codes);
+    breakdown.format(BitSlice::new(&bs))
 }
 
 #[cfg(test)]
 pub mod tests {
 
     use super::*;
-    use bitstring::BitString;
+    use crate::dyn_bit_string;
+    use std::str::FromStr;
+    use crate::prime_table::PrimeTable;
 
     #[allow(dead_code)]
-    fn encode_it(n : u32, prms : & Vec<u32>) -> DynBitString {
+    fn encode_it(n : u32, prms : &PrimeTable) -> DynBitString {
         use crate::primes;
 
         let f = primes::factor(n, prms).unwrap();
@@ -201,7 +703,7 @@ pub mod tests {
 
     #[allow(dead_code)]
     fn encoded_int_as_str(bs : & DynBitString) -> String {
-        let ixs = decode_factors(bs);
+        let ixs = decode_factors::<u32>(bs);
         let iap = factors_to_int_as_prms(&ixs);
         let lenstr = iap.prm_powers.len().to_string();
         let mut bstr = lenstr.to_string();
@@ -250,7 +752,7 @@ pub mod tests {
     pub fn test_encode_factors() {
         use crate::primes;
 
-        let prms: Vec<u32> = primes::gen_primes_up_to(1 << 16);
+        let prms = PrimeTable::up_to(1 << 16);
         let mut two_to_the_k: u32 = 2;
         for _k in 1..15 {
             two_to_the_k *= 2;
@@ -258,7 +760,7 @@ pub mod tests {
                 let next_to_factor = two_to_the_k + j - 1;
                 let bs = encode_it(next_to_factor, &prms);
                 let f = primes::factor(next_to_factor, &prms);
-                let encoded_str = format_factor_encoding_as_string(&f.unwrap().as_slice());
+                let encoded_str = format_factor_encoding_as_string(f.unwrap().as_slice());
                 // FIXME: get rid of println statements
                 println!("bitstring for {} len {} bitstring {} binary {:?} formatted {}",
                          next_to_factor, bs.len(), encoded_int_as_str(&bs), bs, encoded_str);
@@ -267,26 +769,212 @@ pub mod tests {
     }
     #[test]
     pub fn test_decode_factors() {
-        use crate::primes::gen_primes_up_to;
-
-        let prms : Vec<u32> = gen_primes_up_to(1 << 16);
+        let prms = PrimeTable::up_to(1 << 16);
 
         for k in 1<<1..1<<15 {
             let bs = encode_it(k, &prms);
-            let idx_out = decode_factors(&bs);
+            let idx_out = decode_factors::<u32>(&bs);
             let mut f_out: Vec<u32> = vec![];
             for idx in idx_out {
-                f_out.push(prms[idx as usize]);
+                f_out.push(prms.nth(idx as usize).unwrap());
             }
             assert_eq!(prod(f_out), k);
         }
     }
 
     #[test]
-    pub fn test_int_as_prm_to_string() {
-        use crate::primes;
+    pub fn test_try_decode_factors() {
+        use crate::dyn_bit_string::get_bits;
+
+        // 2^3 * 3 * 7919
+        let ixs: Vec<u32> = vec![0, 0, 0, 1, 999];
+        let bs = encode_factors(&ixs);
+        assert_eq!(try_decode_factors::<u32>(&bs), Ok(ixs.clone()));
+        // a reused vector is cleared first
+        let mut factors: Vec<u32> = vec![5, 5];
+        let mut cursor: usize = 0;
+        assert_eq!(try_decode_factors_into_with(&bs, &mut cursor, &DEFAULT_FACTOR_CODES, &mut factors), Ok(()));
+        assert_eq!((factors, cursor), (ixs.clone(), bs.len()));
+        for len in 0..bs.len() {
+            let prefix = get_bits(&bs, 0, len as u32).unwrap_or(DynBitString::null());
+            let mut cursor: usize = 0;
+            assert_eq!(try_decode_factors_at::<u32>(&prefix, &mut cursor), Err(DecodeError::Truncated), "{} bits", len);
+            assert_eq!(cursor, 0);
+        }
+
+        // reserved length codes are not lists of factors
+        let bs = encode_factorization::<u32>(&Factorization::One);
+        assert_eq!(try_decode_factors::<u32>(&bs), Err(DecodeError::InvalidLength));
+        assert_eq!(try_decode_factorization_at::<u32>(&bs, &mut 0), Ok(Factorization::One));
+
+        // an exponent above 32 or prime indexes adding up past u16::MAX cannot be decoded
+        let codes = FactorCodes { exponent: UintCode::EliasGamma, ..DEFAULT_FACTOR_CODES };
+        let mut bs = DynBitString::null();
+        append_code(codes.length, &mut bs, 0u32);
+        append_code(codes.exponent, &mut bs, 40u32);
+        append_code(codes.index_delta, &mut bs, 1u32);
+        assert_eq!(try_decode_factors_at_with::<u32>(&bs, &mut 0, &codes), Err(DecodeError::Overflow));
+
+        // a length code past the reserved ones, which a code without a width limit can hold
+        let codes = FactorCodes { length: UintCode::EliasGamma, ..DEFAULT_FACTOR_CODES };
+        let mut bs = DynBitString::null();
+        append_code(codes.length, &mut bs, u64::MAX);
+        assert_eq!(try_decode_factors_at_with::<u64>(&bs, &mut 0, &codes), Err(DecodeError::InvalidLength));
+        let mut cursor : usize = 0;
+        assert_eq!(try_decode_factorization_at_with::<u64>(&bs, &mut cursor, &codes), Err(DecodeError::InvalidLength));
+        assert_eq!(cursor, 0);
+        let mut trace : Vec<DecodedField> = vec![];
+        assert_eq!(try_decode_factorization_traced_at_with::<u64>(&bs, &mut cursor, &codes, &mut trace), Err(DecodeError::InvalidLength));
+        assert_eq!(trace.last().map(|t| (t.kind, t.bits)), Some((FieldKind::Length, bs.len())));
+        let mut bs = DynBitString::null();
+        append_code(DEFAULT_FACTOR_CODES.length, &mut bs, 1u16);
+        append_code(DEFAULT_FACTOR_CODES.exponent, &mut bs, 0u16);
+        append_code(DEFAULT_FACTOR_CODES.exponent, &mut bs, 0u16);
+        append_code(DEFAULT_FACTOR_CODES.index_delta, &mut bs, 60000u16);
+        append_code(DEFAULT_FACTOR_CODES.index_delta, &mut bs, 10000u16);
+        assert_eq!(try_decode_factors::<u16>(&bs), Err(DecodeError::Overflow));
+    }
+
+    #[test]
+    pub fn test_encode_factorization() {
+        use crate::primes::{factorization_of, factorization_to_uint};
+
+        let prms = PrimeTable::up_to(1 << 8);
+        let mut bs = DynBitString::null();
+        let values: Vec<u32> = [0, 1, 2, 30, 257, 257 * 2 * 2, 65537, 65521 * 65537, 4294967291, u32::MAX].to_vec();
+        for v in values.iter() {
+            dyn_bit_string::append_bits(&mut bs, &encode_factorization(&factorization_of(*v, &prms)));
+        }
+        let mut cursor : usize = 0;
+        for v in values.iter() {
+            let f = decode_factorization_at::<u32>(&bs, &mut cursor);
+            assert_eq!(f, factorization_of(*v, &prms));
+            assert_eq!(factorization_to_uint(&f, &prms), *v);
+        }
+        assert_eq!(cursor, bs.len());
+        assert_eq!(factorization_of(257u32 * 4, &prms), Factorization::BigCofactor { ixs: [0, 0].to_vec(), cofactor: 257 });
+        assert_eq!(factorization_of(30u32, &prms), Factorization::Indices([0, 1, 2].to_vec()));
+
+        // a factor list encodes the same either way
+        assert_eq!(encode_factorization(&Factorization::Indices([0u32, 0, 5].to_vec())), encode_factors(&[0u32, 0, 5]));
+        assert_eq!(encode_factorization::<u32>(&Factorization::Zero).len(), 7);
+        assert_eq!(factorization_to_uint(&factorization_of(0u128, &prms), &prms), 0);
+        assert_eq!(factorization_to_uint(&factorization_of(u128::MAX, &prms), &prms), u128::MAX);
+    }
 
-        let prms : Vec<u32> = primes::gen_primes_up_to(1 << 8);
+    #[test]
+    pub fn test_factor_codes() {
+        use crate::primes::factorization_of;
+
+        let prms = PrimeTable::up_to(1 << 10);
+        let codes_to_try = [
+            DEFAULT_FACTOR_CODES,
+            FactorCodes { length: UintCode::EliasGamma, exponent: UintCode::Fibonacci, index_delta: UintCode::EliasDelta },
+            FactorCodes { length: UintCode::Rice(1), exponent: UintCode::EliasOmega, index_delta: UintCode::ExpGolomb(3) },
+        ];
+        let values: Vec<u32> = (0..2000).chain([1 << 31, 4294967291, 1021 * 1031 * 2]).collect();
+        for codes in codes_to_try.iter() {
+            let mut bs = DynBitString::null();
+            for v in values.iter() {
+                dyn_bit_string::append_bits(&mut bs, &encode_factorization_with(&factorization_of(*v, &prms), codes));
+            }
+            let mut cursor : usize = 0;
+            for v in values.iter() {
+                assert_eq!(decode_factorization_at_with::<u32>(&bs, &mut cursor, codes), factorization_of(*v, &prms), "{:?}", codes);
+            }
+            assert_eq!(cursor, bs.len());
+        }
+        let ixs = [0u32, 0, 0, 4, 100];
+        let codes = codes_to_try[1];
+        let mut cursor : usize = 0;
+        assert_eq!(decode_factors_at_with::<u32>(&encode_factors_with(&ixs, &codes), &mut cursor, &codes), ixs.to_vec());
+        // 3 prime powers, exponents 3 1 1, deltas 0 4 96
+        assert_eq!(encode_factors_with(&ixs, &codes).len(), 3 + (4 + 2 + 2) + (1 + 5 + 11));
+        assert_eq!(format_factor_encoding_as_string_with(&ixs, &codes), "b011 [ b0011 b11 b11 ] [ b1 b01101 b00111100001  ] ");
+        let (bs, breakdown) = encode_factors_breakdown_with(&ixs, &codes);
+        assert_eq!(breakdown, EncodingBreakdown { length_bits: 3, exponent_bits: vec![4, 2, 2], index_delta_bits: vec![1, 5, 11], cofactor_bits: 0 });
+        assert_eq!(bs, encode_factors_with(&ixs, &codes));
+
+        // the breakdown accounts for every bit of every kind of factorization
+        for v in [0u32, 1, 2, 1021 * 1031 * 2, 4294967291, 3 * 1031 * 1033] {
+            let f = factorization_of(v, &prms);
+            let mut bs = DynBitString::null();
+            let breakdown = write_factorization_breakdown_with(&mut BitWriter::new(&mut bs), &f, &codes);
+            assert_eq!(breakdown.total_bits() as usize, bs.len(), "{}", v);
+            assert_eq!(bs, encode_factorization_with(&f, &codes));
+            assert_eq!(breakdown.cofactor_bits > 0, matches!(f, Factorization::BigCofactor { .. }));
+            // and the decoder's trace reads the same fields back
+            let mut trace : Vec<DecodedField> = vec![];
+            let mut cursor : usize = 0;
+            assert_eq!(try_decode_factorization_traced_at_with::<u32>(&bs, &mut cursor, &codes, &mut trace), Ok(f));
+            let field_bits = |kind : FieldKind| -> Vec<u32> { trace.iter().filter(|t| t.kind == kind).map(|t| t.bits as u32).collect() };
+            assert_eq!(field_bits(FieldKind::Exponent), breakdown.exponent_bits);
+            assert_eq!(field_bits(FieldKind::IndexDelta), breakdown.index_delta_bits);
+            assert_eq!(field_bits(FieldKind::Cofactor).iter().sum::<u32>(), breakdown.cofactor_bits);
+            assert_eq!(trace.iter().map(|t| t.bits).sum::<usize>(), cursor);
+        }
+    }
+
+    #[test]
+    pub fn test_encode_factors_bounded() {
+        use crate::primes::{factor, factor_uint};
+        use rand::Rng;
+
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut rng = rand::rng();
+        let mut values: Vec<u32> = (2..20000).collect();
+        values.extend((0..5000).map(|_| rng.random::<u32>().max(2)));
+        values.extend([u32::MAX, 1 << 31, 3u32.pow(20), 2 * 3 * 5 * 7 * 11 * 13 * 17 * 19 * 23]);
+        let mut bs = DynBitString::null();
+        let mut unbounded_len : usize = 0;
+        let mut factored : Vec<Vec<u32>> = vec![];
+        for v in values.iter() {
+            if let Ok(ixs) = factor(*v, &prms) {
+                dyn_bit_string::append_bits(&mut bs, &encode_factors_bounded(&ixs));
+                unbounded_len += encode_factors(&ixs).len();
+                factored.push(ixs);
+            }
+        }
+        let mut cursor : usize = 0;
+        for ixs in factored.iter() {
+            assert_eq!(&decode_factors_bounded_at::<u32>(&bs, &mut cursor), ixs);
+        }
+        assert_eq!(cursor, bs.len());
+        assert!(bs.len() < unbounded_len, "bounded {} unbounded {}", bs.len(), unbounded_len);
+
+        // 2^31: length 2 bits, index 0 of up to 28 bits takes 4 bits for its bit length,
+        // exponent - 1 = 30 of at most 30 takes 3 bits for its bit length
+        // and 4 for its 15 possible values with that bit length
+        assert_eq!(encode_factors_bounded(&[0u32; 31]).len(), 2 + 4 + (3 + 4));
+
+        for _k in 0..200 {
+            let v = rng.random::<u64>().max(2);
+            if let Ok(ixs) = factor_uint(v, &prms) {
+                assert_eq!(decode_factors_bounded::<u64>(&encode_factors_bounded(&ixs)), ixs);
+            }
+        }
+
+        // truncated bits and more prime powers than fit in a u32 are errors, not panics
+        let bs = encode_factors_bounded(&[0u32, 0, 0, 1, 999]);
+        for len in 0..bs.len() {
+            let prefix = dyn_bit_string::get_bits(&bs, 0, len as u32).unwrap_or(DynBitString::null());
+            let mut cursor : usize = 0;
+            assert_eq!(try_decode_factors_bounded_at::<u32>(&prefix, &mut cursor), Err(DecodeError::Truncated), "{} bits", len);
+            assert_eq!(cursor, 0);
+        }
+        let mut bs = DynBitString::null();
+        append_code(DEFAULT_FACTOR_CODES.length, &mut bs, 30u32);
+        dyn_bit_string::append_bits(&mut bs, &DynBitString::from_str("b0000000000000000000000000000000000000000").unwrap());
+        assert_eq!(try_decode_factors_bounded::<u32>(&bs), Err(DecodeError::InvalidLength));
+
+        assert_eq!(iroot(u128::MAX, 2), u64::MAX as u128);
+        assert_eq!(iroot(1000, 3), 10);
+        assert_eq!(iroot(999, 3), 9);
+    }
+
+    #[test]
+    pub fn test_int_as_prm_to_string() {
+        let prms = PrimeTable::up_to(1 << 8);
         let bs = encode_it(30, &prms);
         println!("encoded_int_to_string {}", encoded_int_as_str(&bs));
     }
@@ -0,0 +1,184 @@
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_uint_trait::{append_bits_lsb_first, low_bits_mask, try_read_bit, try_read_bits_lsb_first, DecodeError};
+
+// codes for a value v known to be in 0..=max, where both encoder and decoder know max
+// so no codespace is spent on values that cannot occur
+//   truncated binary - n possible values take floor(log2 n) or ceil(log2 n) bits,
+//       the shorter codes go to the smallest values
+//   bounded uint - bit length of v in truncated binary over 0..=bit length of max,
+//       then the bits of v below its leading 1, in truncated binary when v has the
+//       same bit length as max, since then it can be no bigger than max.
+//       like U32Encoding, small values get short codes, but nothing is spent on
+//       bit lengths or values above max.
+// bits are written least significant bit first, same as U32Encoding.
+// max = 0 takes no bits at all.
+
+fn bit_length(v: u128) -> u32 {
+    u128::BITS - v.leading_zeros()
+}
+
+// v in 0..n
+pub fn append_truncated_binary(bs: &mut DynBitString, v: u128, n: u128) {
+    assert!(v < n);
+    if n == 1 {
+        return;
+    }
+    let k = bit_length(n - 1) - 1;   // n > 2^k, and n <= 2^(k+1)
+    let short_codes = low_bits_mask(k + 1) - (n - 1);  // 2^(k+1) - n values take k bits
+    let (code, bits) = if v < short_codes { (v, k) } else { (v + short_codes, k + 1) };
+    // the first k bits are the same for both, so the decoder can tell them apart after k bits
+    let code = if bits == k { code } else { (code >> 1) | ((code & 1) << k) };
+    append_bits_lsb_first(bs, code, bits);
+}
+
+// inverse of append_truncated_binary()
+pub fn read_truncated_binary(bs: &DynBitString, bitstring_cursor: &mut usize, n: u128) -> u128 {
+    try_read_truncated_binary(bs, bitstring_cursor, n).unwrap()
+}
+
+// same as read_truncated_binary() but safe for untrusted bits, see DecodeError.
+// every code of n values decodes to one of them, so the bits can only be truncated.
+// on error the cursor is left where it was.
+pub fn try_read_truncated_binary(bs: &DynBitString, bitstring_cursor: &mut usize, n: u128) -> Result<u128, DecodeError> {
+    assert!(n > 0);
+    if n == 1 {
+        return Ok(0);
+    }
+    let k = bit_length(n - 1) - 1;
+    let short_codes = low_bits_mask(k + 1) - (n - 1);
+    let mut cursor = *bitstring_cursor;
+    let mut v = try_read_bits_lsb_first(bs, &mut cursor, k)?;
+    if v >= short_codes {
+        let low_bit = try_read_bit(bs, &mut cursor)? as u128;
+        v = ((v << 1) | low_bit) - short_codes;
+    }
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+// v in 0..=max
+pub fn append_bounded_uint(bs: &mut DynBitString, v: u128, max: u128) {
+    assert!(v <= max);
+    let max_len = bit_length(max);
+    let v_len = bit_length(v);
+    append_truncated_binary(bs, v_len as u128, max_len as u128 + 1);
+    if v_len < 2 {
+        return;   // 0 and 1 are their bit length
+    }
+    let leading_one = 1 << (v_len - 1);
+    if v_len < max_len {
+        append_bits_lsb_first(bs, v - leading_one, v_len - 1);
+    } else {
+        append_truncated_binary(bs, v - leading_one, max - leading_one + 1);
+    }
+}
+
+// inverse of append_bounded_uint()
+pub fn read_bounded_uint(bs: &DynBitString, bitstring_cursor: &mut usize, max: u128) -> u128 {
+    try_read_bounded_uint(bs, bitstring_cursor, max).unwrap()
+}
+
+// same as read_bounded_uint() but safe for untrusted bits, like try_read_truncated_binary()
+pub fn try_read_bounded_uint(bs: &DynBitString, bitstring_cursor: &mut usize, max: u128) -> Result<u128, DecodeError> {
+    let max_len = bit_length(max);
+    let mut cursor = *bitstring_cursor;
+    let v_len = try_read_truncated_binary(bs, &mut cursor, max_len as u128 + 1)? as u32;
+    let v = if v_len < 2 {
+        v_len as u128
+    } else {
+        let leading_one: u128 = 1 << (v_len - 1);
+        if v_len < max_len {
+            leading_one | try_read_bits_lsb_first(bs, &mut cursor, v_len - 1)?
+        } else {
+            leading_one + try_read_truncated_binary(bs, &mut cursor, max - leading_one + 1)?
+        }
+    };
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use bitstring::BitString;
+
+    #[test]
+    pub fn test_truncated_binary() {
+        // n = 5: 0, 1, 2 take 2 bits, 3 and 4 take 3 bits
+        let expected_lens = [2, 2, 2, 3, 3];
+        let mut bs = DynBitString::null();
+        for (v, expected_len) in expected_lens.iter().enumerate() {
+            let len_before = bs.len();
+            append_truncated_binary(&mut bs, v as u128, 5);
+            assert_eq!(bs.len() - len_before, *expected_len);
+        }
+        for n in [1u128, 2, 3, 7, 8, 9, 1000, 1 << 40] {
+            for v in [0, 1, n / 3, n / 2, n.saturating_sub(2), n - 1].into_iter().filter(|v| *v < n) {
+                append_truncated_binary(&mut bs, v, n);
+            }
+        }
+        append_truncated_binary(&mut bs, u128::MAX - 1, u128::MAX);
+        let mut cursor: usize = 0;
+        for v in 0..5 {
+            assert_eq!(read_truncated_binary(&bs, &mut cursor, 5), v);
+        }
+        for n in [1u128, 2, 3, 7, 8, 9, 1000, 1 << 40] {
+            for v in [0, 1, n / 3, n / 2, n.saturating_sub(2), n - 1].into_iter().filter(|v| *v < n) {
+                assert_eq!(read_truncated_binary(&bs, &mut cursor, n), v, "n = {}", n);
+            }
+        }
+        assert_eq!(read_truncated_binary(&bs, &mut cursor, u128::MAX), u128::MAX - 1);
+        assert_eq!(cursor, bs.len());
+    }
+
+    #[test]
+    pub fn test_bounded_uint() {
+        let mut bs = DynBitString::null();
+        append_bounded_uint(&mut bs, 0, 0);
+        assert_eq!(bs.len(), 0);
+        let maxes = [1u128, 2, 5, 16, 17, 1000, u32::MAX as u128, u128::MAX];
+        for max in maxes {
+            for v in (0..=max.min(300)).chain([max / 2, max - 1, max]) {
+                append_bounded_uint(&mut bs, v, max);
+            }
+        }
+        let mut cursor: usize = 0;
+        for max in maxes {
+            for v in (0..=max.min(300)).chain([max / 2, max - 1, max]) {
+                assert_eq!(read_bounded_uint(&bs, &mut cursor, max), v, "max = {}", max);
+            }
+        }
+        assert_eq!(cursor, bs.len());
+
+        // with max = 5, 4 and 5 are the only values of bit length 3, so they take 1 bit after it
+        let mut bs = DynBitString::null();
+        append_bounded_uint(&mut bs, 5, 5);
+        assert_eq!(bs.len(), 2 + 1);
+    }
+
+    #[test]
+    pub fn test_try_read_truncated() {
+        use crate::dyn_bit_string::get_bits;
+
+        let mut bs = DynBitString::null();
+        append_bounded_uint(&mut bs, 1000, 5000);
+        append_truncated_binary(&mut bs, 4, 5);
+        let len = bs.len();
+        // every shorter prefix is truncated, and the cursor stays put
+        for prefix_len in 0..len {
+            let prefix = get_bits(&bs, 0, prefix_len as u32).unwrap_or(DynBitString::null());
+            let mut cursor: usize = 0;
+            let result = try_read_bounded_uint(&prefix, &mut cursor, 5000)
+                .and_then(|_| try_read_truncated_binary(&prefix, &mut cursor, 5));
+            assert_eq!(result, Err(DecodeError::Truncated), "{} bits", prefix_len);
+        }
+        let mut cursor: usize = 0;
+        assert_eq!(try_read_bounded_uint(&bs, &mut cursor, 5000), Ok(1000));
+        assert_eq!(try_read_truncated_binary(&bs, &mut cursor, 5), Ok(4));
+        assert_eq!(cursor, len);
+        // the cursor is left where it was on error
+        let mut cursor = len - 1;
+        assert_eq!(try_read_truncated_binary(&bs, &mut cursor, 5), Err(DecodeError::Truncated));
+        assert_eq!(cursor, len - 1);
+    }
+}
@@ -0,0 +1,235 @@
+use bitstring::BitString;
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_uint_trait::*;
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
+
+// Elias gamma, delta and omega codes
+// these encode positive integers, so we encode N = v + 1 to handle v = 0.
+// bits of N are written most significant bit first, as in the textbook definitions:
+//   gamma - bit length of N - 1 as zeros, then the bits of N
+//           (so the leading 1 of N ends the zeros)
+//   delta - bit length of N in gamma code, then the bits of N after its leading 1
+//   omega - groups of bits, each the bits of the next group's bit length - 1,
+//           starting from 2 and ending with the bits of N, then a 0
+// gamma takes 2 * log2(N) + 1 bits, delta and omega are shorter for big N.
+// none of these depend on T, except that the value read must fit in it.
+// the try_ readers return Overflow instead of panicking when it does not.
+
+pub struct EliasGammaEncoding {
+    pub encoding : UintEncoding
+}
+
+pub struct EliasDeltaEncoding {
+    pub encoding : UintEncoding
+}
+
+pub struct EliasOmegaEncoding {
+    pub encoding : UintEncoding
+}
+
+pub fn append_elias_gamma<T: UintWidth>(bs: &mut DynBitString, v_in: T) {
+    let (n_low, n_bits) = sum_with_bit_length(v_in.to_u128(), 1);
+    append_unary(bs, (n_bits - 1) as u128, false);
+    append_bits_msb_first(bs, n_low, n_bits - 1);
+}
+
+pub fn read_elias_gamma<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    try_read_elias_gamma(bs, bitstring_cursor).unwrap()
+}
+
+pub fn try_read_elias_gamma<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    let mut cursor = *bitstring_cursor;
+    let zeros = try_read_unary(bs, &mut cursor, false)?;
+    if zeros > u128::BITS as u128 {
+        return Err(DecodeError::Overflow);
+    }
+    let rest = try_read_bits_msb_first(bs, &mut cursor, zeros as u32)?;
+    // N = 2^zeros + rest, so v = 2^zeros - 1 + rest
+    let v = low_bits_mask(zeros as u32).checked_add(rest).ok_or(DecodeError::Overflow)?;
+    let v = try_uint_from_u128(v)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+pub fn append_elias_delta<T: UintWidth>(bs: &mut DynBitString, v_in: T) {
+    let (n_low, n_bits) = sum_with_bit_length(v_in.to_u128(), 1);
+    append_elias_gamma(bs, n_bits - 1);
+    append_bits_msb_first(bs, n_low, n_bits - 1);
+}
+
+pub fn read_elias_delta<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    try_read_elias_delta(bs, bitstring_cursor).unwrap()
+}
+
+pub fn try_read_elias_delta<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    let mut cursor = *bitstring_cursor;
+    let rest_bits = try_read_elias_gamma::<u32>(bs, &mut cursor)?;
+    let rest = try_read_bits_msb_first(bs, &mut cursor, rest_bits)?;
+    let v = low_bits_mask(rest_bits).checked_add(rest).ok_or(DecodeError::Overflow)?;
+    let v = try_uint_from_u128(v)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+pub fn append_elias_omega<T: UintWidth>(bs: &mut DynBitString, v_in: T) {
+    // groups are built from N down to 2, then written in reverse
+    let mut groups: Vec<(u128, u32)> = vec![];
+    let mut group = sum_with_bit_length(v_in.to_u128(), 1);
+    while group.1 > 1 {
+        groups.push(group);
+        group = sum_with_bit_length((group.1 - 1) as u128, 0);
+    }
+    for (n_low, n_bits) in groups.iter().rev() {
+        bs.append(true);
+        append_bits_msb_first(bs, *n_low, n_bits - 1);
+    }
+    bs.append(false);
+}
+
+pub fn read_elias_omega<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    try_read_elias_omega(bs, bitstring_cursor).unwrap()
+}
+
+pub fn try_read_elias_omega<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    // n_minus_1 is N - 1, and each group holds N bits after its leading 1
+    let mut cursor = *bitstring_cursor;
+    let mut n_minus_1: u128 = 0;
+    while try_read_bit(bs, &mut cursor)? {
+        if n_minus_1 >= u128::BITS as u128 {
+            return Err(DecodeError::Overflow);
+        }
+        let group_bits = n_minus_1 as u32 + 1;
+        let rest = try_read_bits_msb_first(bs, &mut cursor, group_bits)?;
+        n_minus_1 = low_bits_mask(group_bits).checked_add(rest).ok_or(DecodeError::Overflow)?;
+    }
+    let v = try_uint_from_u128(n_minus_1)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+impl EncodingUint for EliasGammaEncoding {
+    fn new() -> Self {
+        EliasGammaEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        EliasGammaEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::EliasGamma
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+impl EncodingUint for EliasDeltaEncoding {
+    fn new() -> Self {
+        EliasDeltaEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        EliasDeltaEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::EliasDelta
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+impl EncodingUint for EliasOmegaEncoding {
+    fn new() -> Self {
+        EliasOmegaEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        EliasOmegaEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::EliasOmega
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // textbook codes are for N = v + 1, and written most significant bit first
+    #[test]
+    pub fn test_textbook_codes() {
+        let test_cases = vec![
+            // N, gamma, delta, omega
+            (1u32, "b1", "b1", "b0"),
+            (2, "b010", "b0100", "b100"),
+            (3, "b011", "b0101", "b110"),
+            (4, "b00100", "b01100", "b101000"),
+            (10, "b0001010", "b00100010", "b1110100"),
+            (17, "b000010001", "b001010001", "b10100100010"),
+        ];
+        for (n, gamma, delta, omega) in test_cases {
+            let mut g = EliasGammaEncoding::new();
+            g.append_uint(n - 1);
+            assert_eq!(g.get_bitstr_encoding(), DynBitString::from_str(gamma).unwrap(), "gamma {}", n);
+            let mut d = EliasDeltaEncoding::new();
+            d.append_uint(n - 1);
+            assert_eq!(d.get_bitstr_encoding(), DynBitString::from_str(delta).unwrap(), "delta {}", n);
+            let mut o = EliasOmegaEncoding::new();
+            o.append_uint(n - 1);
+            assert_eq!(o.get_bitstr_encoding(), DynBitString::from_str(omega).unwrap(), "omega {}", n);
+        }
+    }
+
+    fn round_trip<E: EncodingUint>() {
+        let mut e = E::new();
+        let mut values: Vec<u32> = (0..3000).collect();
+        values.extend([u32::MAX, u32::MAX - 1, 1 << 31, (1 << 31) - 1]);
+        for v in values.iter() {
+            e.append_uint(*v);
+        }
+        e.append_uint(u64::MAX);
+        e.append_uint(u128::MAX);
+        e.append_uint(u128::MAX - 1);
+        e.append_uint(0u128);
+        let mut cursor: usize = 0;
+        for v in values.iter() {
+            assert_eq!(e.read_uint::<u32>(&mut cursor), *v);
+        }
+        assert_eq!(e.read_uint::<u64>(&mut cursor), u64::MAX);
+        assert_eq!(e.read_uint::<u128>(&mut cursor), u128::MAX);
+        assert_eq!(e.read_uint::<u128>(&mut cursor), u128::MAX - 1);
+        assert_eq!(e.read_uint::<u128>(&mut cursor), 0);
+        assert_eq!(cursor, e.get_bitstr_encoding().len());
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        round_trip::<EliasGammaEncoding>();
+        round_trip::<EliasDeltaEncoding>();
+        round_trip::<EliasOmegaEncoding>();
+    }
+}
@@ -0,0 +1,195 @@
+use bitstring::BitString;
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_uint_trait::{try_read_bit, try_uint_from_u128, DecodeError, EncodingUint, UintEncoding};
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
+
+// Fibonacci code of N = v + 1
+// N is written as a sum of non-consecutive Fibonacci numbers 1, 2, 3, 5, 8, ... (Zeckendorf),
+// one bit per Fibonacci number starting from 1, up to the largest one used, then an extra 1.
+// since no two consecutive Fibonacci numbers are used, "11" only occurs at the end.
+// N takes about 1.44 * log2(N) + 2 bits, and a flipped bit only corrupts a value or two.
+
+pub struct FibonacciEncoding {
+    pub encoding : UintEncoding
+}
+
+// Fibonacci numbers 1, 2, 3, 5, ... that fit in a u128
+fn fibonacci_numbers() -> Vec<u128> {
+    let mut fibs: Vec<u128> = vec![1, 2];
+    while let Some(next_fib) = fibs[fibs.len() - 2].checked_add(fibs[fibs.len() - 1]) {
+        fibs.push(next_fib);
+    }
+    fibs
+}
+
+pub fn append_fibonacci<T: UintWidth>(bs: &mut DynBitString, v_in: T) {
+    // we work with r = N - 1 = v, since N may not fit in a u128
+    // a Fibonacci number f fits in what is left of N when f <= r + 1
+    let fibs = fibonacci_numbers();
+    let mut r = v_in.to_u128();
+    let largest = fibs.iter().rposition(|f| f - 1 <= r).unwrap();
+    let mut bits = vec![false; largest + 1];
+    for k in (0..=largest).rev() {
+        if fibs[k] - 1 <= r {
+            bits[k] = true;
+            if fibs[k] - 1 == r {
+                break;   // nothing left of N
+            }
+            r -= fibs[k];
+        }
+    }
+    for b in bits {
+        bs.append(b);
+    }
+    bs.append(true);
+}
+
+pub fn read_fibonacci<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    try_read_fibonacci(bs, bitstring_cursor).unwrap()
+}
+
+// Overflow once N needs a Fibonacci number that does not fit in a u128
+pub fn try_read_fibonacci<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    let fibs = fibonacci_numbers();
+    let mut cursor = *bitstring_cursor;
+    let mut r: u128 = 0;     // N - 1 once the first Fibonacci number is added
+    let mut first = true;
+    let mut prev_bit = false;
+    let mut k: usize = 0;
+    loop {
+        let b = try_read_bit(bs, &mut cursor)?;
+        if b && prev_bit {
+            break;
+        }
+        if b {
+            let fib = *fibs.get(k).ok_or(DecodeError::Overflow)?;
+            if first {
+                r = fib - 1;
+                first = false;
+            } else {
+                r = r.checked_add(fib).ok_or(DecodeError::Overflow)?;
+            }
+        }
+        prev_bit = b;
+        k += 1;
+    }
+    let v = try_uint_from_u128(r)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+impl EncodingUint for FibonacciEncoding {
+    fn new() -> Self {
+        FibonacciEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        FibonacciEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::Fibonacci
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_fibonacci() {
+        // N = v + 1
+        let test_cases = vec![
+            (1u32, "b11"),
+            (2, "b011"),
+            (3, "b0011"),
+            (4, "b1011"),
+            (11, "b001011"),
+            (12, "b101011"),
+        ];
+        for (n, expected) in test_cases {
+            let mut e = FibonacciEncoding::new();
+            e.append_uint(n - 1);
+            assert_eq!(e.get_bitstr_encoding(), DynBitString::from_str(expected).unwrap(), "N = {}", n);
+        }
+
+        let mut e = FibonacciEncoding::new();
+        let values: Vec<u32> = (0..3000).chain([u32::MAX, 1 << 31]).collect();
+        for v in values.iter() {
+            e.append_uint(*v);
+        }
+        e.append_uint(u128::MAX);
+        e.append_uint(0u16);
+        let mut cursor: usize = 0;
+        for v in values.iter() {
+            assert_eq!(e.read_uint::<u32>(&mut cursor), *v);
+        }
+        assert_eq!(e.read_uint::<u128>(&mut cursor), u128::MAX);
+        assert_eq!(e.read_uint::<u16>(&mut cursor), 0);
+        assert_eq!(cursor, e.get_bitstr_encoding().len());
+    }
+
+    #[test]
+    pub fn test_fibonacci_edges() {
+        // 0 is the shortest code, in every width
+        for code in [encode(0u16), encode(0u128)] {
+            assert_eq!(code, DynBitString::from_str("b11").unwrap());
+        }
+        let mut cursor: usize = 0;
+        assert_eq!(try_read_fibonacci::<u16>(&encode(0u16), &mut cursor), Ok(0));
+
+        // the largest value of each width
+        let mut bs = DynBitString::null();
+        append_fibonacci(&mut bs, u16::MAX);
+        append_fibonacci(&mut bs, u32::MAX);
+        append_fibonacci(&mut bs, u64::MAX);
+        append_fibonacci(&mut bs, u128::MAX);
+        let mut cursor: usize = 0;
+        assert_eq!(try_read_fibonacci::<u16>(&bs, &mut cursor), Ok(u16::MAX));
+        assert_eq!(try_read_fibonacci::<u32>(&bs, &mut cursor), Ok(u32::MAX));
+        assert_eq!(try_read_fibonacci::<u64>(&bs, &mut cursor), Ok(u64::MAX));
+        assert_eq!(try_read_fibonacci::<u128>(&bs, &mut cursor), Ok(u128::MAX));
+        assert_eq!(cursor, bs.len());
+
+        // N is the largest Fibonacci number in a u128, only its own bit and the end bit are set
+        let fibs = fibonacci_numbers();
+        let largest = fibs[fibs.len() - 1];
+        let bs = encode(largest - 1);
+        assert_eq!(bs.len(), fibs.len() + 1);
+        assert_eq!((0..bs.len()).filter(|k| bs.get(*k)).count(), 2);
+        let mut cursor: usize = 0;
+        assert_eq!(try_read_fibonacci::<u128>(&bs, &mut cursor), Ok(largest - 1));
+
+        // one Fibonacci number further does not fit in a u128
+        let mut bs = DynBitString::null();
+        for _k in 0..fibs.len() {
+            bs.append(false);
+        }
+        bs.append(true);
+        bs.append(true);
+        let mut cursor: usize = 0;
+        assert_eq!(try_read_fibonacci::<u128>(&bs, &mut cursor), Err(DecodeError::Overflow));
+        assert_eq!(cursor, 0);
+
+        // a value too big for the width it is read as, and a code with no end
+        assert_eq!(try_read_fibonacci::<u64>(&encode(u128::MAX), &mut cursor), Err(DecodeError::Overflow));
+        assert_eq!(try_read_fibonacci::<u32>(&DynBitString::from_str("b0101").unwrap(), &mut cursor), Err(DecodeError::Truncated));
+        assert_eq!(cursor, 0);
+    }
+
+    fn encode<T: UintWidth>(v: T) -> DynBitString {
+        let mut bs = DynBitString::null();
+        append_fibonacci(&mut bs, v);
+        bs
+    }
+}
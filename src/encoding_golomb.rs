@@ -0,0 +1,196 @@
+use bitstring::BitString;
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_uint_trait::*;
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
+
+// Golomb-Rice and exponential Golomb codes, both with a parameter K
+// bits are written most significant bit first, as in the textbook definitions.
+//   Rice with parameter K - v >> K in unary as ones ended by a zero, then the low K bits of v
+//     best when values are geometrically distributed around 2^K, but the unary part
+//     grows linearly with v, so this is a poor choice for values much bigger than that.
+//   exponential Golomb of order K - bit length of v + 2^K minus K + 1 as zeros,
+//     then the bits of v + 2^K. order 0 is the same as Elias gamma.
+
+pub struct RiceEncoding<const K: u32> {
+    pub encoding : UintEncoding
+}
+
+pub struct ExpGolombEncoding<const K: u32> {
+    pub encoding : UintEncoding
+}
+
+pub fn append_rice<T: UintWidth>(bs: &mut DynBitString, v_in: T, k: u32) {
+    assert!(k < u128::BITS);
+    let v = v_in.to_u128();
+    append_unary(bs, v >> k, true);
+    append_bits_msb_first(bs, v, k);
+}
+
+pub fn read_rice<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, k: u32) -> T {
+    assert!(k < u128::BITS);
+    try_read_rice(bs, bitstring_cursor, k).unwrap()
+}
+
+pub fn try_read_rice<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, k: u32) -> Result<T, DecodeError> {
+    assert!(k < u128::BITS);
+    let mut cursor = *bitstring_cursor;
+    let quotient = try_read_unary(bs, &mut cursor, true)?;
+    if quotient > u128::MAX >> k {
+        return Err(DecodeError::Overflow);
+    }
+    let remainder = try_read_bits_msb_first(bs, &mut cursor, k)?;
+    let v = try_uint_from_u128((quotient << k) | remainder)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+// length of the Rice code for v, without building it, since it can be very long
+pub fn rice_len<T: UintWidth>(v_in: T, k: u32) -> u128 {
+    (v_in.to_u128() >> k) + 1 + k as u128
+}
+
+pub fn append_exp_golomb<T: UintWidth>(bs: &mut DynBitString, v_in: T, k: u32) {
+    assert!(k < u128::BITS);
+    let (w_low, w_bits) = sum_with_bit_length(v_in.to_u128(), 1 << k);
+    append_unary(bs, (w_bits - 1 - k) as u128, false);
+    append_bits_msb_first(bs, w_low, w_bits - 1);
+}
+
+pub fn read_exp_golomb<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, k: u32) -> T {
+    assert!(k < u128::BITS);
+    try_read_exp_golomb(bs, bitstring_cursor, k).unwrap()
+}
+
+pub fn try_read_exp_golomb<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, k: u32) -> Result<T, DecodeError> {
+    assert!(k < u128::BITS);
+    let mut cursor = *bitstring_cursor;
+    let zeros = try_read_unary(bs, &mut cursor, false)?;
+    if zeros + k as u128 > u128::BITS as u128 {
+        return Err(DecodeError::Overflow);
+    }
+    let rest = try_read_bits_msb_first(bs, &mut cursor, zeros as u32 + k)?;
+    // w = 2^(zeros + k) + rest, so v = 2^(zeros + k) - 2^k + rest
+    let v = (low_bits_mask(zeros as u32 + k) - low_bits_mask(k)).checked_add(rest).ok_or(DecodeError::Overflow)?;
+    let v = try_uint_from_u128(v)?;
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+impl<const K: u32> EncodingUint for RiceEncoding<K> {
+    fn new() -> Self {
+        RiceEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        RiceEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::Rice(K)
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+impl<const K: u32> EncodingUint for ExpGolombEncoding<K> {
+    fn new() -> Self {
+        ExpGolombEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+    }
+
+    fn from_bitstr_encoding(bs : DynBitString) -> Self {
+        ExpGolombEncoding { encoding: UintEncoding { bstr: bs }}
+    }
+
+    fn uint_code(&self) -> UintCode {
+        UintCode::ExpGolomb(K)
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::encoding_elias::EliasGammaEncoding;
+
+    #[test]
+    pub fn test_rice() {
+        let test_cases = vec![
+            (0u32, "b000"),
+            (3, "b011"),
+            (4, "b1000"),
+            (9, "b11001"),
+        ];
+        for (v, expected) in test_cases {
+            let mut e = RiceEncoding::<2>::new();
+            e.append_uint(v);
+            assert_eq!(e.get_bitstr_encoding(), DynBitString::from_str(expected).unwrap(), "v = {}", v);
+            assert_eq!(rice_len(v, 2), expected.len() as u128 - 1);
+        }
+        let mut e = RiceEncoding::<4>::new();
+        for v in 0..1000u32 {
+            e.append_uint(v);
+        }
+        e.append_uint(u128::MAX >> 124);
+        let mut cursor: usize = 0;
+        for v in 0..1000u32 {
+            assert_eq!(e.read_uint::<u32>(&mut cursor), v);
+        }
+        assert_eq!(e.read_uint::<u128>(&mut cursor), 15);
+        assert_eq!(cursor, e.get_bitstr_encoding().len());
+    }
+
+    #[test]
+    pub fn test_exp_golomb() {
+        let test_cases = vec![
+            (0u32, "b100"),
+            (3, "b111"),
+            (4, "b01000"),
+            (11, "b01111"),
+            (28, "b000100000"),
+        ];
+        for (v, expected) in test_cases {
+            let mut e = ExpGolombEncoding::<2>::new();
+            e.append_uint(v);
+            assert_eq!(e.get_bitstr_encoding(), DynBitString::from_str(expected).unwrap(), "v = {}", v);
+        }
+
+        // order 0 is Elias gamma
+        let mut e0 = ExpGolombEncoding::<0>::new();
+        let mut gamma = EliasGammaEncoding::new();
+        for v in 0..1000u32 {
+            e0.append_uint(v);
+            gamma.append_uint(v);
+        }
+        assert_eq!(e0.get_bitstr_encoding(), gamma.get_bitstr_encoding());
+
+        let mut e = ExpGolombEncoding::<5>::new();
+        let values: Vec<u32> = (0..3000).chain([u32::MAX, 1 << 31]).collect();
+        for v in values.iter() {
+            e.append_uint(*v);
+        }
+        e.append_uint(u128::MAX);
+        e.append_uint(u128::MAX - 31);
+        let mut cursor: usize = 0;
+        for v in values.iter() {
+            assert_eq!(e.read_uint::<u32>(&mut cursor), *v);
+        }
+        assert_eq!(e.read_uint::<u128>(&mut cursor), u128::MAX);
+        assert_eq!(e.read_uint::<u128>(&mut cursor), u128::MAX - 31);
+        assert_eq!(cursor, e.get_bitstr_encoding().len());
+    }
+}
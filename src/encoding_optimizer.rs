@@ -0,0 +1,338 @@
+// choose continue offsets for U32Encoding and SmallIntEncoding from sample values
+//
+// both encodings spend the same number of bits on every value of the same bit length,
+// so a histogram of bit lengths is all we need to count the total bits for any offsets,
+// and we can simply try every set of offsets that makes a difference (16 for a u32).
+// the samples are the fields of factor encodings, either of a corpus of u32 values
+// or of the values factor_random draws.
+// the width of the length-of-length field is not searched, it stays T::LEN_BITS:
+// a narrower one cannot hold the bit length of every u32, and since the continue
+// offsets already let short lengths stop early, a wider one only adds bits.
+//
+// the chosen codes are saved in a text file with one field per line, in UintCode names:
+//   length=smallint:0,3
+//   exponent=smallint
+//   index_delta=u32:1,3
+// blank lines and lines starting with # are ignored. the same codes must be used to
+// compress and decompress, see PrimeCompressor::new_with_codes().
+
+use std::fmt;
+use std::str::FromStr;
+use crate::encode_prime::{factors_to_int_as_prms, reserved_length_code, FactorCodes, Factorization};
+use crate::encode_prime::{BIG_COFACTOR_CODE_OFFSET, DEFAULT_FACTOR_CODES, ONE_CODE_OFFSET, ZERO_CODE_OFFSET};
+use crate::encoding_small_int::{small_int_encoding_len, SMALL_INT_DEFAULT_CONTINUE_OFFSETS};
+use crate::encoding_u32::{u32_encoding_len, U32_DEFAULT_CONTINUE_OFFSETS};
+use crate::encoding_uint_trait::ContinueOffsets;
+use crate::prime_table::PrimeTable;
+use crate::primes::factorization_of;
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
+
+// bit lengths of a u32, 0 through 32
+const BIT_LENGTHS: usize = u32::BITS as usize + 1;
+
+// how many values of each bit length were seen in each field of the factor encoding
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct FieldSamples {
+    pub lengths : [u64; BIT_LENGTHS],
+    pub exponents : [u64; BIT_LENGTHS],
+    pub index_deltas : [u64; BIT_LENGTHS],
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum FactorCodesFileErrcode {
+    BadLine,         // not blank, a comment or field=code
+    UnknownField,
+    BadCode,         // code name does not parse, see UintCode
+    MissingField,
+    DuplicateField,
+}
+
+impl fmt::Display for FactorCodesFileErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid factor codes file: {:?}", self)
+    }
+}
+
+impl std::error::Error for FactorCodesFileErrcode {}
+
+impl From<FactorCodesFileErrcode> for std::io::Error {
+    fn from(e: FactorCodesFileErrcode) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+fn bit_length(v: u32) -> usize {
+    (u32::BITS - v.leading_zeros()) as usize
+}
+
+// smallest value with this bit length, the encodings cost the same for all of them
+fn value_of_bit_length(b: usize) -> u32 {
+    if b == 0 { 0 } else { 1 << (b - 1) }
+}
+
+impl Default for FieldSamples {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FieldSamples {
+    pub fn new() -> Self {
+        FieldSamples { lengths: [0; BIT_LENGTHS], exponents: [0; BIT_LENGTHS], index_deltas: [0; BIT_LENGTHS] }
+    }
+
+    // the fields encode_factors() writes for these prime indexes.
+    // it cannot encode an empty list, so there is nothing to count for one
+    pub fn add_factors(&mut self, ixs: &[u32]) {
+        if ixs.is_empty() {
+            return;
+        }
+        let prm_powers = factors_to_int_as_prms(ixs).prm_powers;
+        self.lengths[bit_length(prm_powers.len() as u32 - 1)] += 1;
+        self.add_prime_powers(ixs);
+    }
+
+    fn add_prime_powers(&mut self, ixs: &[u32]) {
+        if ixs.is_empty() {
+            return;
+        }
+        let mut prev_index = 0;
+        for ppwr in factors_to_int_as_prms(ixs).prm_powers {
+            self.exponents[bit_length(ppwr.exp as u32 - 1)] += 1;
+            self.index_deltas[bit_length(ppwr.prm_idx - prev_index)] += 1;
+            prev_index = ppwr.prm_idx;
+        }
+    }
+
+    // the fields encode_factorization() writes for f, except for the cofactor
+    // of a BigCofactor, which is always in the default U32Encoding
+    pub fn add_factorization(&mut self, f: &Factorization<u32>) {
+        match f {
+            Factorization::Zero => {
+                self.lengths[bit_length(reserved_length_code(ZERO_CODE_OFFSET))] += 1;
+            }
+            Factorization::One => {
+                self.lengths[bit_length(reserved_length_code(ONE_CODE_OFFSET))] += 1;
+            }
+            Factorization::Indices(ixs) => self.add_factors(ixs),
+            Factorization::BigCofactor { ixs, cofactor: _ } => {
+                self.lengths[bit_length(reserved_length_code(BIG_COFACTOR_CODE_OFFSET))] += 1;
+                let l = if ixs.is_empty() { 0 } else { factors_to_int_as_prms(ixs).prm_powers.len() };
+                self.lengths[bit_length(l as u32)] += 1;
+                self.add_prime_powers(ixs);
+            }
+        }
+    }
+
+    // samples of every value in a corpus, factored with prms
+    pub fn from_corpus(values: &[u32], prms: &PrimeTable) -> Self {
+        let mut samples = FieldSamples::new();
+        for v in values {
+            samples.add_factorization(&factorization_of(*v, prms));
+        }
+        samples
+    }
+}
+
+// total bits to encode the values in hist with this code,
+// None for codes other than U32 and SmallInt or values SmallInt cannot hold
+pub fn field_bits(hist: &[u64; BIT_LENGTHS], code: UintCode) -> Option<u64> {
+    let mut total: u64 = 0;
+    for (b, count) in hist.iter().enumerate().filter(|(_, count)| **count > 0) {
+        let v = value_of_bit_length(b);
+        let bits = match code {
+            UintCode::U32(offsets) => u32_encoding_len(v, offsets),
+            UintCode::SmallInt(_) if b > u32::LEN_BITS as usize => return None,
+            UintCode::SmallInt(offsets) => small_int_encoding_len(v, offsets),
+            _ => return None,
+        };
+        total += count * bits as u64;
+    }
+    Some(total)
+}
+
+// offsets that encode hist in the fewest bits, the default ones if nothing does better
+fn best_offsets(hist: &[u64; BIT_LENGTHS], default_offsets: ContinueOffsets,
+                code_of: fn(ContinueOffsets) -> UintCode) -> Option<ContinueOffsets> {
+    let mut best_bits = field_bits(hist, code_of(default_offsets))?;
+    let mut best = default_offsets;
+    for offsets in ContinueOffsets::all_for_field(u32::LEN_BITS) {
+        if let Some(bits) = field_bits(hist, code_of(offsets)) {
+            if bits < best_bits {
+                best_bits = bits;
+                best = offsets;
+            }
+        }
+    }
+    Some(best)
+}
+
+pub fn best_u32_offsets(hist: &[u64; BIT_LENGTHS]) -> ContinueOffsets {
+    // any u32 fits in a U32Encoding
+    best_offsets(hist, U32_DEFAULT_CONTINUE_OFFSETS, UintCode::U32).unwrap()
+}
+
+// None if some value is too big for SmallIntEncoding
+pub fn best_small_int_offsets(hist: &[u64; BIT_LENGTHS]) -> Option<ContinueOffsets> {
+    best_offsets(hist, SMALL_INT_DEFAULT_CONTINUE_OFFSETS, UintCode::SmallInt)
+}
+
+fn optimize_code(hist: &[u64; BIT_LENGTHS], code: UintCode) -> UintCode {
+    match code {
+        UintCode::U32(_) => UintCode::U32(best_u32_offsets(hist)),
+        UintCode::SmallInt(offsets) => UintCode::SmallInt(best_small_int_offsets(hist).unwrap_or(offsets)),
+        _ => code,
+    }
+}
+
+// codes with the best continue offsets for each U32 or SmallInt field of codes,
+// other fields are left alone
+pub fn optimize_factor_codes(samples: &FieldSamples, codes: &FactorCodes) -> FactorCodes {
+    FactorCodes {
+        length: optimize_code(&samples.lengths, codes.length),
+        exponent: optimize_code(&samples.exponents, codes.exponent),
+        index_delta: optimize_code(&samples.index_deltas, codes.index_delta),
+    }
+}
+
+// total bits of all three fields, None if any field's code cannot be counted, see field_bits()
+pub fn factor_fields_bits(samples: &FieldSamples, codes: &FactorCodes) -> Option<u64> {
+    Some(field_bits(&samples.lengths, codes.length)?
+        + field_bits(&samples.exponents, codes.exponent)?
+        + field_bits(&samples.index_deltas, codes.index_delta)?)
+}
+
+pub fn format_factor_codes(codes: &FactorCodes) -> String {
+    format!("length={}\nexponent={}\nindex_delta={}\n", codes.length, codes.exponent, codes.index_delta)
+}
+
+// inverse of format_factor_codes()
+pub fn parse_factor_codes(text: &str) -> Result<FactorCodes, FactorCodesFileErrcode> {
+    let mut fields: [Option<UintCode>; 3] = [None; 3];
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let (name, code_name) = line.split_once('=').ok_or(FactorCodesFileErrcode::BadLine)?;
+        let field = match name.trim() {
+            "length" => &mut fields[0],
+            "exponent" => &mut fields[1],
+            "index_delta" => &mut fields[2],
+            _ => return Err(FactorCodesFileErrcode::UnknownField),
+        };
+        if field.is_some() {
+            return Err(FactorCodesFileErrcode::DuplicateField);
+        }
+        *field = Some(UintCode::from_str(code_name.trim()).map_err(|_| FactorCodesFileErrcode::BadCode)?);
+    }
+    match fields {
+        [Some(length), Some(exponent), Some(index_delta)] => Ok(FactorCodes { length, exponent, index_delta }),
+        _ => Err(FactorCodesFileErrcode::MissingField),
+    }
+}
+
+pub fn save_factor_codes(path: &str, codes: &FactorCodes) -> Result<(), std::io::Error> {
+    std::fs::write(path, format_factor_codes(codes))
+}
+
+pub fn load_factor_codes(path: &str) -> Result<FactorCodes, std::io::Error> {
+    Ok(parse_factor_codes(&std::fs::read_to_string(path)?)?)
+}
+
+// the codes in path if it exists, otherwise the default ones
+pub fn load_factor_codes_or_default(path: &str) -> Result<FactorCodes, std::io::Error> {
+    match load_factor_codes(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DEFAULT_FACTOR_CODES),
+        r => r,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use bitstring::BitString;
+    use crate::encode_prime::{decode_factorization_at_with, encode_factorization_with, COFACTOR_CODE};
+    use crate::uint_code::code_len;
+    use rand::Rng;
+
+    #[test]
+    pub fn test_saved_factor_codes() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut rng = rand::rng();
+        let path = std::env::temp_dir().join(format!("factor_codes_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        // values of every size, and only small ones, which want different offsets
+        for largest in [u32::MAX, 1000] {
+            let values: Vec<u32> = (0..2000).map(|_| rng.random_range(0..=largest)).collect();
+            let samples = FieldSamples::from_corpus(&values, &prms);
+            let best = optimize_factor_codes(&samples, &DEFAULT_FACTOR_CODES);
+            save_factor_codes(path, &best).unwrap();
+            let loaded = load_factor_codes(path).unwrap();
+            assert_eq!(loaded, best);
+            for (hist, best_code, default_code) in [
+                (&samples.lengths, loaded.length, DEFAULT_FACTOR_CODES.length),
+                (&samples.exponents, loaded.exponent, DEFAULT_FACTOR_CODES.exponent),
+                (&samples.index_deltas, loaded.index_delta, DEFAULT_FACTOR_CODES.index_delta)] {
+                assert!(field_bits(hist, best_code).unwrap() <= field_bits(hist, default_code).unwrap(), "{}", largest);
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(load_factor_codes_or_default(path).unwrap(), DEFAULT_FACTOR_CODES);
+    }
+
+    #[test]
+    pub fn test_optimize_factor_codes() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut rng = rand::rng();
+        let values: Vec<u32> = (0..2000).map(|_| rng.random::<u32>()).chain([0, 1, 2, 1 << 31]).collect();
+        let samples = FieldSamples::from_corpus(&values, &prms);
+
+        // the counted bits are exactly what the encodings take, apart from the cofactors
+        let encoded_bits = |codes: &FactorCodes| {
+            let mut total: u64 = 0;
+            for v in values.iter() {
+                let f = factorization_of(*v, &prms);
+                let bs = encode_factorization_with(&f, codes);
+                let mut cursor: usize = 0;
+                assert_eq!(decode_factorization_at_with::<u32>(&bs, &mut cursor, codes), f);
+                total += bs.len() as u64;
+                if let Factorization::BigCofactor { ixs: _, cofactor } = f {
+                    total -= code_len(COFACTOR_CODE, cofactor).unwrap() as u64;
+                }
+            }
+            total
+        };
+
+        // no prime indexes add nothing rather than underflowing the length
+        let mut with_empty = samples.clone();
+        with_empty.add_factors(&[]);
+        assert_eq!((with_empty.lengths, with_empty.exponents, with_empty.index_deltas),
+                   (samples.lengths, samples.exponents, samples.index_deltas));
+
+        let best = optimize_factor_codes(&samples, &DEFAULT_FACTOR_CODES);
+        let default_bits = factor_fields_bits(&samples, &DEFAULT_FACTOR_CODES).unwrap();
+        let best_bits = factor_fields_bits(&samples, &best).unwrap();
+        assert!(best_bits <= default_bits);
+        assert_eq!(encoded_bits(&DEFAULT_FACTOR_CODES), default_bits);
+        assert_eq!(encoded_bits(&best), best_bits);
+        // no other offsets do better
+        for offsets in ContinueOffsets::all_for_field(u32::LEN_BITS) {
+            assert!(field_bits(&samples.index_deltas, UintCode::U32(offsets)).unwrap()
+                    >= field_bits(&samples.index_deltas, best.index_delta).unwrap());
+        }
+        // 32 and up do not fit in a smallint
+        let mut hist = [0u64; BIT_LENGTHS];
+        hist[6] = 1;
+        assert_eq!(best_small_int_offsets(&hist), None);
+
+        assert_eq!(parse_factor_codes(&format_factor_codes(&best)), Ok(best));
+        assert_eq!(parse_factor_codes("# comment\n\nlength = u32:0\nexponent=gamma\nindex_delta=rice:3\n"),
+                   Ok(FactorCodes { length: UintCode::U32(ContinueOffsets { mask: 1 }),
+                                    exponent: UintCode::EliasGamma, index_delta: UintCode::Rice(3) }));
+        assert_eq!(parse_factor_codes("length=u32\n"), Err(FactorCodesFileErrcode::MissingField));
+        assert_eq!(parse_factor_codes("length=u32\nlength=u32"), Err(FactorCodesFileErrcode::DuplicateField));
+        assert_eq!(parse_factor_codes("size=u32"), Err(FactorCodesFileErrcode::UnknownField));
+        assert_eq!(parse_factor_codes("length=huffman"), Err(FactorCodesFileErrcode::BadCode));
+        assert_eq!(parse_factor_codes("length"), Err(FactorCodesFileErrcode::BadLine));
+    }
+}
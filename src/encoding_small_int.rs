@@ -1,99 +1,96 @@
 use bitstring::BitString;
 use crate::dyn_bit_string::DynBitString;
-use crate::encoding_uint_trait::{BITSTRING_CONTINUE, BITSTRING_END, EncodingUint, UintEncoding};
+use crate::encoding_uint_trait::*;
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
 
 // encode very small unsigned integers using variable-length encoding
-// this has to handle numbers up through 31, which is:
+// for an integer type T this has to handle numbers up through 2^T::LEN_BITS - 1,
+// (31 for u32) which is:
 // - maximum possible exponent-1
 // - theoretical upper bound on number of prime factors
 // encode starting from least significant bit to
-// most significant bit
+// most significant bit, by default first 1 bit then groups of 2 bits, with a continue bit
+// after each group except the one that holds the most significant bit.
+// the groups can be changed at run time by giving the offsets of the continue bits,
+// see encoding_optimizer, and must be the same when decoding.
+
+pub const SMALL_INT_DEFAULT_CONTINUE_OFFSETS: ContinueOffsets = ContinueOffsets { mask: 0b1010101 };
 
 pub struct SmallIntEncoding {
-    pub encoding : UintEncoding
+    pub encoding : UintEncoding,
+    pub offsets : ContinueOffsets,
 }
 
-impl EncodingUint for SmallIntEncoding {
-    fn new() -> Self {
-        SmallIntEncoding { encoding: UintEncoding { bstr: DynBitString::null() }}
+impl SmallIntEncoding {
+    pub fn with_offsets(offsets : ContinueOffsets) -> Self {
+        SmallIntEncoding { encoding: UintEncoding { bstr: DynBitString::null() }, offsets }
     }
 
-    fn get_bitstr_encoding(&self) -> DynBitString {
-        self.encoding.bstr.clone()
+    pub fn from_bitstr_encoding_with_offsets(bs : DynBitString, offsets : ContinueOffsets) -> Self {
+        SmallIntEncoding { encoding: UintEncoding { bstr: bs }, offsets }
+    }
+}
+
+impl EncodingUint for SmallIntEncoding {
+    fn new() -> Self {
+        SmallIntEncoding::with_offsets(SMALL_INT_DEFAULT_CONTINUE_OFFSETS)
     }
 
     fn from_bitstr_encoding(bs : DynBitString) -> Self {
-        SmallIntEncoding { encoding: UintEncoding { bstr: bs }}
+        SmallIntEncoding::from_bitstr_encoding_with_offsets(bs, SMALL_INT_DEFAULT_CONTINUE_OFFSETS)
     }
 
-    fn append_uint32(& mut self, v_in: u32) {
-        assert!(v_in < (1 << 5));
-        let mut v = v_in;
-        let bs = &mut  self.encoding.bstr;
-        let first_bit = (v & 1) != 0;
-        bs.append(first_bit);
-        v >>= 1;
-        if v == 0 {
-            bs.append(BITSTRING_END);
-        } else {
-            bs.append(BITSTRING_CONTINUE);
-            for _k in 0..2 { // from least significant to most significant
-                let next_bit = (v & 1) != 0;
-                bs.append(next_bit);
-                v >>= 1;
-            }
-            if v == 0 {
-                bs.append(BITSTRING_END);
-            } else {
-                bs.append(BITSTRING_CONTINUE);
-                for _k in 0..2 {
-                    let next_bit = (v & 1) != 0;
-                    bs.append(next_bit);
-                    v >>= 1;
-                }
-            }
-        }
-        assert_eq!(v, 0);
+    fn uint_code(&self) -> UintCode {
+        UintCode::SmallInt(self.offsets)
     }
 
-    // inverse of append_uint32_len
-    // note that first bit is least significant bit, same as above
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
 
-    fn read_uint32(&self, bitstring_cursor: &mut usize) -> u32 {
-        let mut v = 0;
-        let mut bitmask = 1;
-        let bs = self.get_bitstr_encoding();
-        if bs.get(*bitstring_cursor) {
-            v |= bitmask;
-        }
-        *bitstring_cursor += 1;
-        bitmask <<= 1;
-        let continue_bit = bs.get(*bitstring_cursor);
-        *bitstring_cursor += 1;
-        if continue_bit == BITSTRING_CONTINUE {
-            for _j in 0..2 {
-                if bs.get(*bitstring_cursor) {
-                    v |= bitmask;
-                }
-                *bitstring_cursor += 1;
-                bitmask <<= 1;
-            }
-            let continue_bit2 = bs.get(*bitstring_cursor);
-            *bitstring_cursor += 1;
-            if continue_bit2 == BITSTRING_CONTINUE {
-                for _j in 0..2 {
-                    if bs.get(*bitstring_cursor) {
-                        v |= bitmask;
-                    }
-                    *bitstring_cursor += 1;
-                    bitmask <<= 1;
-                }
-            }
-        }
-        v
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
     }
 }
 
+// append the SmallIntEncoding of v_in to bs, so other encodings can use it without a SmallIntEncoding
+pub fn append_small_int_encoding<T: UintWidth>(bs: &mut DynBitString, v_in: T) {
+    append_small_int_encoding_with(bs, v_in, SMALL_INT_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn append_small_int_encoding_with<T: UintWidth>(bs: &mut DynBitString, v_in: T, offsets: ContinueOffsets) {
+    let v = v_in.to_u128();
+    assert!(v < (1 << T::LEN_BITS));
+    append_continued_field(bs, v, T::LEN_BITS, offsets);
+}
+
+// inverse of append_small_int_encoding()
+// note that first bit is least significant bit, same as above
+
+pub fn read_small_int_encoding<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    read_small_int_encoding_with(bs, bitstring_cursor, SMALL_INT_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn read_small_int_encoding_with<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, offsets: ContinueOffsets) -> T {
+    try_read_small_int_encoding_with(bs, bitstring_cursor, offsets).unwrap()
+}
+
+// same as read_small_int_encoding() but safe for untrusted bits, see DecodeError
+// the field is T::LEN_BITS wide, so the value always fits in T
+pub fn try_read_small_int_encoding<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    try_read_small_int_encoding_with(bs, bitstring_cursor, SMALL_INT_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn try_read_small_int_encoding_with<T: UintWidth>(bs: &DynBitString, bitstring_cursor: &mut usize, offsets: ContinueOffsets) -> Result<T, DecodeError> {
+    Ok(T::from_u128(try_read_continued_field(bs, bitstring_cursor, T::LEN_BITS, offsets)?))
+}
+
+// number of bits append_small_int_encoding_with() appends for v
+pub fn small_int_encoding_len<T: UintWidth>(v_in: T, offsets: ContinueOffsets) -> u32 {
+    continued_field_len(u128::BITS - v_in.to_u128().leading_zeros(), T::LEN_BITS, offsets)
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -124,12 +121,52 @@ pub mod tests {
     pub fn test_read_uint32() {
         let mut t = SmallIntEncoding::new();
         let v_in : [u32; 5] = [ 0, 1, 2, 6, 7 ];
-        for k in 0..5 {
-            t.append_uint32(v_in[k]);
+        for v in v_in {
+            t.append_uint32(v);
+        }
+        let mut cursor : usize = 0;
+        for v in v_in {
+            assert_eq!(t.read_uint32(&mut cursor), v);
         }
+        // the last value is 3 bits, 7 is 5 bits
+        assert_eq!(t.try_read_uint32(&mut cursor), Err(DecodeError::Truncated));
+        let mut cursor = t.get_bitstr_encoding().len() - 5;
+        assert_eq!(t.try_read_uint32(&mut cursor), Ok(7));
+    }
+
+    #[test]
+    pub fn test_other_widths() {
+        use std::str::FromStr;
+        let mut t = SmallIntEncoding::new();
+        t.append_uint(15u16);
+        t.append_uint(63u64);
+        t.append_uint(127u128);
+        t.append_uint(1u128);
+        // 1+2+1 bits for u16, 1+2+2+1 bits for u64, 1+2+2+2 bits for u128, plus continue bits
+        let expected_bs = DynBitString::from_str("b111111_111111111_1111111111_10".replace('_', "").as_str()).unwrap();
+        assert_eq!(t.get_bitstr_encoding(), expected_bs);
         let mut cursor : usize = 0;
-        for i in 0..5 {
-            assert_eq!(t.read_uint32(&mut cursor), v_in[i]);
+        assert_eq!(t.read_uint::<u16>(&mut cursor), 15);
+        assert_eq!(t.read_uint::<u64>(&mut cursor), 63);
+        assert_eq!(t.read_uint::<u128>(&mut cursor), 127);
+        assert_eq!(t.read_uint::<u128>(&mut cursor), 1);
+        assert_eq!(cursor, t.get_bitstr_encoding().len());
+    }
+
+    // same as encoding_u32::tests::test_read_with_offsets()
+    #[test]
+    pub fn test_read_with_offsets() {
+        let values: Vec<u32> = (0..32).rev().collect();
+        for offsets in ContinueOffsets::all_for_field(u32::LEN_BITS) {
+            let mut bs = DynBitString::null();
+            for v in &values {
+                append_small_int_encoding_with(&mut bs, *v, offsets);
+            }
+            let mut cursor : usize = 0;
+            for v in &values {
+                assert_eq!(try_read_small_int_encoding_with::<u32>(&bs, &mut cursor, offsets), Ok(*v), "offsets {}", offsets);
+            }
+            assert_eq!(cursor, bs.len());
         }
     }
 }
\ No newline at end of file
@@ -1,119 +1,127 @@
 use bitstring::BitString;
-use crate::encoding_uint_trait::{EncodingUint, UintEncoding};
+use crate::encoding_uint_trait::*;
 use crate::dyn_bit_string::*;
-use std::num::NonZeroU32;
-use crate::encoding_uint_trait::{BITSTRING_CONTINUE, BITSTRING_END};
+use crate::uint_code::UintCode;
+use crate::uint_width::UintWidth;
 
-// bit offsets where decision to end/continue bitstring
-// the last array value is just there so we don't get out-of-bounds array reference
+// encode an unsigned integer as its bit length - 1 (the length of the length),
+// followed by the bits of the integer.
+// the length of the length takes T::LEN_BITS bits, 5 bits for a u32,
+// the fewest that hold the bit length of every T.
+// there are bit offsets where we decide to end/continue the length of the length,
+// by default just one, so small lengths only take 1 + 1 + 1 bits.
+// the offsets can be changed at run time, see encoding_optimizer,
+// and must be the same when decoding.
 
-const CONTINUE_OFFSETS: [usize; 2] = [ 1, 5 ];
+pub const U32_DEFAULT_CONTINUE_OFFSETS: ContinueOffsets = ContinueOffsets { mask: 1 << 1 };
 
 pub struct U32Encoding {
-    pub encoding : UintEncoding
+    pub encoding : UintEncoding,
+    pub offsets : ContinueOffsets,
+}
+
+impl U32Encoding {
+    pub fn with_offsets(offsets : ContinueOffsets) -> Self {
+        U32Encoding { encoding: UintEncoding { bstr : DynBitString::null() }, offsets }
+    }
+
+    pub fn from_bitstr_encoding_with_offsets( bs : DynBitString, offsets : ContinueOffsets ) -> Self {
+        U32Encoding { encoding: UintEncoding { bstr : bs }, offsets }
+    }
 }
 
 impl EncodingUint for U32Encoding {
 
     fn new() -> Self {
-        U32Encoding { encoding: UintEncoding { bstr : DynBitString::null() }}
+        U32Encoding::with_offsets(U32_DEFAULT_CONTINUE_OFFSETS)
     }
 
-    fn get_bitstr_encoding(&self) -> DynBitString {
-        self.encoding.bstr.clone()
-    }
     fn from_bitstr_encoding( bs : DynBitString ) -> Self {
-        U32Encoding { encoding: UintEncoding { bstr : bs }}
-    }
-    fn append_uint32(&mut self, v_in: u32) {
-        let bstr = &mut self.encoding.bstr;
-        // encode the length of the length
-        let leading_0s = if v_in == 0 {
-            NonZeroU32::BITS
-        } else {
-            NonZeroU32::new(v_in).unwrap().leading_zeros()
-        };
-        let mut len_bitct = NonZeroU32::BITS - leading_0s;
-        #[allow(clippy::implicit_saturating_sub)]
-        if len_bitct != 0 { len_bitct -= 1; } // so it fits in 5 bits
-        let mut continue_offsets_index: usize = 0;  // position in continue_offsets array
-        for k in 0..5 {  // length of length in bits is at most 2^5 - 1
-            let next_bit: bool = len_bitct & 1 != 0;
-            bstr.append(next_bit);
-            len_bitct >>= 1;
-            if CONTINUE_OFFSETS[continue_offsets_index] == k {
-                if len_bitct == 0 {
-                    bstr.append(BITSTRING_END);
-                    break;
-                } else {
-                    bstr.append(BITSTRING_CONTINUE);
-                    continue_offsets_index += 1;
-                }
-            }
-        }
-        assert_eq!(len_bitct, 0);
-
-        // we could replace this bit-by-bit loop
-        // with something more efficient later
-
-        let mut v = v_in;
-        if v == 0 {
-            // special case v=0 to have a 1-bit 0 encoded
-            bstr.append(false);
-        } else {
-            while v > 0 {
-                let next_bit = (v & 1) != 0;
-                bstr.append(next_bit);
-                v >>= 1;
-            }
-        }
+        U32Encoding::from_bitstr_encoding_with_offsets(bs, U32_DEFAULT_CONTINUE_OFFSETS)
     }
 
-    // inverse of append_uint32()
-    // read an unsigned 32-bit integer from the current position in the bitstring
-    // and return it, while also updating the bitstring cursor
-    // the caller must initialize the cursor to zero before calling
-    // read_uint32 for the first time.
-
-    fn read_uint32(&self, bitstring_cursor: &mut usize) -> u32 {
-        let enc_len_val = &self.encoding.bstr;
-        let mut vlen: u32 = 0;
-        let mut continue_offsets_index: usize = 0;  // position in continue_offsets array
-        let mut bitct_mask: u32 = 1;                // next bit to process from bitstring length
-        for k in 0..5 {
-            let next_bit_1: bool = enc_len_val.get(*bitstring_cursor);
-            *bitstring_cursor += 1;
-            if next_bit_1 {
-                vlen |= bitct_mask;
-            }
-            bitct_mask <<= 1;
-            if CONTINUE_OFFSETS[continue_offsets_index] == k {
-                continue_offsets_index += 1;
-
-                let next_continue_bit: bool = enc_len_val.get(*bitstring_cursor);
-                *bitstring_cursor += 1;
-                if !next_continue_bit {
-                    break;
-                }
-            }
-        }
-        vlen += 1;
-        assert!(vlen < 33);
-        // we now have the length of the integer in vlen
-        // now decode integer of vlen bits
-        // someday we can stop doing this bit-by-bit
-
-        let mut v = 0;
-        bitct_mask = 1;
-        for _j in 0..vlen {
-            if enc_len_val.get(*bitstring_cursor) {
-                v |= bitct_mask;
-            }
-            *bitstring_cursor += 1;
-            bitct_mask <<= 1;
+    fn uint_code(&self) -> UintCode {
+        UintCode::U32(self.offsets)
+    }
+
+    fn bitstr(&self) -> &DynBitString {
+        &self.encoding.bstr
+    }
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString {
+        &mut self.encoding.bstr
+    }
+}
+
+// append the U32Encoding of v_in to bstr, so other encodings can use it without a U32Encoding
+pub fn append_u32_encoding<T: UintWidth>(bstr: &mut DynBitString, v_in: T) {
+    append_u32_encoding_with(bstr, v_in, U32_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn append_u32_encoding_with<T: UintWidth>(bstr: &mut DynBitString, v_in: T, offsets: ContinueOffsets) {
+    // encode the length of the length
+    let v_u128 = v_in.to_u128();
+    // so it fits in T::LEN_BITS bits
+    let len_bitct = (u128::BITS - v_u128.leading_zeros()).saturating_sub(1);
+    append_continued_field(bstr, len_bitct as u128, T::LEN_BITS, offsets);
+
+    // then the value, a word at a time,
+    // v=0 is a 1-bit 0 like any other 1-bit value
+    append_bits_lsb_first(bstr, v_u128, len_bitct + 1);
+}
+
+// inverse of append_u32_encoding()
+// read an unsigned integer from the current position in the bitstring
+// and return it, while also updating the bitstring cursor
+// the caller must initialize the cursor to zero before calling
+// read_uint for the first time.
+
+pub fn read_u32_encoding<T: UintWidth>(enc_len_val: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    read_u32_encoding_with(enc_len_val, bitstring_cursor, U32_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn read_u32_encoding_with<T: UintWidth>(enc_len_val: &DynBitString, bitstring_cursor: &mut usize, offsets: ContinueOffsets) -> T {
+    try_read_u32_encoding_with(enc_len_val, bitstring_cursor, offsets).unwrap()
+}
+
+// same as read_u32_encoding() but safe for untrusted bits, see DecodeError
+pub fn try_read_u32_encoding<T: UintWidth>(enc_len_val: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    try_read_u32_encoding_with(enc_len_val, bitstring_cursor, U32_DEFAULT_CONTINUE_OFFSETS)
+}
+
+pub fn try_read_u32_encoding_with<T: UintWidth>(enc_len_val: &DynBitString, bitstring_cursor: &mut usize, offsets: ContinueOffsets) -> Result<T, DecodeError> {
+    // when the longest encoding fits in a word and is all there, take length and value from one word
+    let max_len = max_continued_field_len(T::LEN_BITS, offsets) + T::BITS;
+    if max_len <= u64::BITS && enc_len_val.len() >= *bitstring_cursor + max_len as usize {
+        let w = enc_len_val.read_uint(*bitstring_cursor, max_len);
+        let (len_bitct, len_len) = take_continued_field(w, T::LEN_BITS, offsets);
+        let vlen = len_bitct as u32 + 1;
+        if vlen > T::BITS {
+            return Err(DecodeError::InvalidLength);
         }
-        v
+        *bitstring_cursor += (len_len + vlen) as usize;
+        return Ok(T::from_u128(((w >> len_len) & low_bits_mask(vlen) as u64) as u128));
+    }
+    let mut cursor = *bitstring_cursor;
+    let vlen = try_read_continued_field(enc_len_val, &mut cursor, T::LEN_BITS, offsets)? as u32 + 1;
+    if vlen > T::BITS {
+        return Err(DecodeError::InvalidLength);
     }
+    if enc_len_val.len() - cursor < vlen as usize {
+        return Err(DecodeError::Truncated);
+    }
+    // we now have the length of the integer in vlen
+    // now decode integer of vlen bits
+    let v = try_read_bits_lsb_first(enc_len_val, &mut cursor, vlen)?;
+    *bitstring_cursor = cursor;
+    Ok(T::from_u128(v))
+}
+
+// number of bits append_u32_encoding_with() appends for v
+pub fn u32_encoding_len<T: UintWidth>(v_in: T, offsets: ContinueOffsets) -> u32 {
+    let v_bits = (u128::BITS - v_in.to_u128().leading_zeros()).max(1);
+    let len_bitct = v_bits - 1;
+    continued_field_len(u32::BITS - len_bitct.leading_zeros(), T::LEN_BITS, offsets) + v_bits
 }
 
 #[cfg(test)]
@@ -185,6 +193,23 @@ pub mod tests {
         expect_dbstr(sm, "b11111111111111111111111111111111111111");
     }
 
+    #[test]
+    pub fn test_other_widths() {
+        let mut int_encoding = U32Encoding::new();
+        int_encoding.append_uint(7u16);
+        int_encoding.append_uint(u16::MAX);
+        int_encoding.append_uint(u64::MAX);
+        int_encoding.append_uint(1u128 << 100);
+        // 2 bits for length of 7, 2 + 1 + 2 for length of u16::MAX
+        // 2 + 1 + 4 for length of u64::MAX, 2 + 1 + 5 for length of 1 << 100
+        assert_eq!(int_encoding.get_bitstr_encoding().len(), (3 + 3) + (5 + 16) + (7 + 64) + (8 + 101));
+        let mut cursor: usize = 0;
+        assert_eq!(int_encoding.read_uint::<u16>(&mut cursor), 7);
+        assert_eq!(int_encoding.read_uint::<u16>(&mut cursor), u16::MAX);
+        assert_eq!(int_encoding.read_uint::<u64>(&mut cursor), u64::MAX);
+        assert_eq!(int_encoding.read_uint::<u128>(&mut cursor), 1 << 100);
+    }
+
     #[test]
     pub fn test_decode_uint32() {
         for j in 0..2 << 20 {
@@ -193,4 +218,39 @@ pub mod tests {
             assert_eq!(v, j as u32);
         }
     }
+
+    #[test]
+    pub fn test_try_read_uint32() {
+        let bs = encode_uint32(1000);
+        let u32_enc = U32Encoding::from_bitstr_encoding(bs.clone());
+        let mut cursor: usize = 0;
+        assert_eq!(u32_enc.try_read_uint32(&mut cursor), Ok(1000));
+        assert_eq!(cursor, bs.len());
+
+        // every shorter prefix is truncated, and the cursor stays put
+        for len in 0..bs.len() {
+            let prefix = U32Encoding::from_bitstr_encoding(get_bits(&bs, 0, len as u32).unwrap_or(DynBitString::null()));
+            let mut cursor: usize = 0;
+            assert_eq!(prefix.try_read_uint32(&mut cursor), Err(DecodeError::Truncated), "{} bits", len);
+            assert_eq!(cursor, 0);
+        }
+    }
+
+    // a value with the longest encoding after it is taken from one word, the last one
+    // bit by bit, both must read back what was appended with any continue offsets
+    #[test]
+    pub fn test_read_with_offsets() {
+        let values: Vec<u32> = (0..u32::BITS).map(|k| (1 << k) | 1).chain([0, u32::MAX]).collect();
+        for offsets in ContinueOffsets::all_for_field(u32::LEN_BITS) {
+            let mut bs = DynBitString::null();
+            for v in &values {
+                append_u32_encoding_with(&mut bs, *v, offsets);
+            }
+            let mut cursor: usize = 0;
+            for v in &values {
+                assert_eq!(try_read_u32_encoding_with::<u32>(&bs, &mut cursor, offsets), Ok(*v), "offsets {}", offsets);
+            }
+            assert_eq!(cursor, bs.len());
+        }
+    }
 }
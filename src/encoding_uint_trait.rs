@@ -1,6 +1,14 @@
+use std::fmt;
+use std::str::FromStr;
+use bitstring::BitString;
+use crate::bit_stream::{BitReader, BitWriter};
 use crate::dyn_bit_string::DynBitString;
+use crate::uint_code::{append_code, read_code, try_read_code, UintCode};
+use crate::uint_width::UintWidth;
 
 // encode/decode a sequence of unsigned integer values
+// the integer type passed to append_uint/read_uint sets how wide the encoding is,
+// values must be read back with the same type they were appended with
 
 #[derive(Debug)]
 pub struct UintEncoding {
@@ -10,20 +18,496 @@ pub struct UintEncoding {
 pub const BITSTRING_CONTINUE : bool = true;
 pub const BITSTRING_END : bool = false;
 
+// why a try_ reader could not decode a value, so bits from outside can be
+// decoded without panicking. on error the cursor is left where it was.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub enum DecodeError {
+    Truncated,       // bitstring ends in the middle of an encoding
+    InvalidLength,   // a length field holds a length the encoding never writes
+    Overflow,        // value does not fit in the type it is read as
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot decode bits: {:?}", self)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// for readers of io streams, running out of bits is running out of stream
+impl From<DecodeError> for std::io::Error {
+    fn from(e: DecodeError) -> Self {
+        let kind = match e {
+            DecodeError::Truncated => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+// bit at the cursor, advancing the cursor past it
+pub fn try_read_bit(bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<bool, DecodeError> {
+    if *bitstring_cursor >= bs.len() {
+        return Err(DecodeError::Truncated);
+    }
+    let b = bs.get(*bitstring_cursor);
+    *bitstring_cursor += 1;
+    Ok(b)
+}
+
+// v as a T, or Overflow if it does not fit
+pub fn try_uint_from_u128<T: UintWidth>(v: u128) -> Result<T, DecodeError> {
+    if T::BITS < u128::BITS && v >> T::BITS != 0 {
+        return Err(DecodeError::Overflow);
+    }
+    Ok(T::from_u128(v))
+}
+
+// the bit offsets in a field, counting from its least significant bit, after which
+// a continue bit says whether any of the higher bits are non-zero. if none are, the
+// field ends there. U32Encoding uses this for its length of the length and
+// SmallIntEncoding for the value itself. no continue bit is needed after the last bit
+// of the field, so offsets past that are ignored.
+// written as a comma-separated list of offsets, or "none"
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub struct ContinueOffsets {
+    pub mask : u32,     // bit k set if there is a continue bit after bit k
+}
+
+impl ContinueOffsets {
+    pub fn from_offsets(offsets: &[u32]) -> Self {
+        let mut mask: u32 = 0;
+        for k in offsets {
+            assert!(*k < u32::BITS);
+            mask |= 1 << k;
+        }
+        ContinueOffsets { mask }
+    }
+
+    pub fn contains(&self, k: u32) -> bool {
+        k < u32::BITS && self.mask & (1 << k) != 0
+    }
+
+    pub fn offsets(&self) -> Vec<u32> {
+        (0..u32::BITS).filter(|k| self.contains(*k)).collect()
+    }
+
+    // every set of offsets that makes a difference in a field of field_bits bits
+    pub fn all_for_field(field_bits: u32) -> Vec<Self> {
+        let useful_offsets = field_bits.saturating_sub(1);
+        (0..1u32 << useful_offsets).map(|mask| ContinueOffsets { mask }).collect()
+    }
+}
+
+impl fmt::Display for ContinueOffsets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mask == 0 {
+            return write!(f, "none");
+        }
+        let offsets: Vec<String> = self.offsets().iter().map(|k| k.to_string()).collect();
+        write!(f, "{}", offsets.join(","))
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ParseContinueOffsetsErrcode {
+    BadNumber,        // not none or a list of numbers
+    OffsetTooBig,     // offset of 32 or more, which the mask cannot hold
+}
+
+impl FromStr for ContinueOffsets {
+    type Err = ParseContinueOffsetsErrcode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(ContinueOffsets { mask: 0 });
+        }
+        let mut mask: u32 = 0;
+        for k in s.split(',') {
+            let k = u32::from_str(k).map_err(|_| ParseContinueOffsetsErrcode::BadNumber)?;
+            if k >= u32::BITS {
+                return Err(ParseContinueOffsetsErrcode::OffsetTooBig);
+            }
+            mask |= 1 << k;
+        }
+        Ok(ContinueOffsets { mask })
+    }
+}
+
+// append the low field_bits bits of v, least significant first, with continue bits
+// at the given offsets, v must fit in field_bits bits
+pub fn append_continued_field(bs: &mut DynBitString, v: u128, field_bits: u32, offsets: ContinueOffsets) {
+    assert!(field_bits == u128::BITS || v >> field_bits == 0);
+    // the bits between continue bits go in as one word
+    let mut k = 0;
+    while k < field_bits {
+        let chunk_end = next_continue_offset(k, field_bits, offsets);
+        append_bits_lsb_first(bs, (v >> k) & low_bits_mask(chunk_end - k), chunk_end - k);
+        k = chunk_end;
+        if k < field_bits {
+            if v >> k == 0 {
+                bs.append(BITSTRING_END);
+                return;
+            }
+            bs.append(BITSTRING_CONTINUE);
+        }
+    }
+}
+
+// end of the run of field bits starting at k, just past the next continue offset or the field
+fn next_continue_offset(k: u32, field_bits: u32, offsets: ContinueOffsets) -> u32 {
+    let offsets_from_k = if k < u32::BITS { offsets.mask & (u32::MAX << k) } else { 0 };
+    if offsets_from_k == 0 { field_bits } else { (offsets_from_k.trailing_zeros() + 1).min(field_bits) }
+}
+
+// inverse of append_continued_field()
+pub fn read_continued_field(bs: &DynBitString, bitstring_cursor: &mut usize, field_bits: u32, offsets: ContinueOffsets) -> u128 {
+    try_read_continued_field(bs, bitstring_cursor, field_bits, offsets).unwrap()
+}
+
+pub fn try_read_continued_field(bs: &DynBitString, bitstring_cursor: &mut usize, field_bits: u32, offsets: ContinueOffsets) -> Result<u128, DecodeError> {
+    // when the longest the field can be fits in a word and is all there, read that word once
+    let max_len = max_continued_field_len(field_bits, offsets);
+    if max_len <= u64::BITS && bs.len() >= *bitstring_cursor + max_len as usize {
+        let (v, len) = take_continued_field(bs.read_uint(*bitstring_cursor, max_len), field_bits, offsets);
+        *bitstring_cursor += len as usize;
+        return Ok(v as u128);
+    }
+    let mut cursor = *bitstring_cursor;
+    let mut v: u128 = 0;
+    let mut k = 0;
+    while k < field_bits {
+        let chunk_end = next_continue_offset(k, field_bits, offsets);
+        v |= try_read_bits_lsb_first(bs, &mut cursor, chunk_end - k)? << k;
+        k = chunk_end;
+        if k < field_bits && try_read_bit(bs, &mut cursor)? == BITSTRING_END {
+            break;
+        }
+    }
+    *bitstring_cursor = cursor;
+    Ok(v)
+}
+
+// number of bits append_continued_field() appends for a value that needs all field_bits bits
+pub fn max_continued_field_len(field_bits: u32, offsets: ContinueOffsets) -> u32 {
+    let continue_bits = if field_bits > u32::BITS { offsets.mask } else { offsets.mask & (((1u64 << field_bits) - 1) >> 1) as u32 };
+    field_bits + continue_bits.count_ones()
+}
+
+// a field written by append_continued_field() from the low bits of w, and the number of bits
+// it took up. w must hold max_continued_field_len() bits, so field_bits can be at most 64
+pub fn take_continued_field(mut w: u64, field_bits: u32, offsets: ContinueOffsets) -> (u64, u32) {
+    let mut continue_bits = offsets.mask;
+    let mut v: u64 = 0;
+    let mut len = 0;
+    for k in 0..field_bits {
+        v |= (w & 1) << k;
+        w >>= 1;
+        len += 1;
+        if continue_bits & 1 != 0 && k + 1 < field_bits {
+            let continue_bit = w & 1 != 0;
+            w >>= 1;
+            len += 1;
+            if continue_bit == BITSTRING_END {
+                break;
+            }
+        }
+        continue_bits >>= 1;
+    }
+    (v, len)
+}
+
+// number of bits append_continued_field() appends for a value of bit length v_bits
+pub fn continued_field_len(v_bits: u32, field_bits: u32, offsets: ContinueOffsets) -> u32 {
+    let mut len = 0;
+    for k in 0..field_bits {
+        len += 1;
+        if k + 1 < field_bits && offsets.contains(k) {
+            len += 1;
+            if k + 1 >= v_bits {
+                break;
+            }
+        }
+    }
+    len
+}
+
+// each encoding is one of the UintCodes, with a bitstring of its own to append to.
+// everything else is done with the code, so encoders can also write into a shared
+// BitWriter and decoders read from a borrowed BitReader, without copying any bits.
+
 pub trait EncodingUint {
     // create new instance
     fn new() -> Self;
 
-    // get bitstring encoding
-    fn get_bitstr_encoding(&self) -> DynBitString;
-
     // prepare to decode an encoded bitstring using read_
     fn from_bitstr_encoding( bstr_in : DynBitString ) -> Self;
 
+    // the code this encoding writes and reads
+    fn uint_code(&self) -> UintCode;
+
+    // borrow the bitstring this encoding appends to
+    fn bitstr(&self) -> &DynBitString;
+
+    fn bitstr_mut(&mut self) -> &mut DynBitString;
+
+    // get a copy of the bitstring encoding, bitstr() does not copy it
+    fn get_bitstr_encoding(&self) -> DynBitString {
+        self.bitstr().clone()
+    }
+
+    // append the encoding of an unsigned integer of type T to another bitstring
+    fn write_uint<T: UintWidth>(&self, w : &mut BitWriter, v_in : T) {
+        w.write_code(self.uint_code(), v_in)
+    }
+
+    // read the next encoding of an unsigned integer of type T from another bitstring
+    fn read_uint_from<T: UintWidth>(&self, r : &mut BitReader) -> T {
+        r.read_code(self.uint_code())
+    }
+
+    fn try_read_uint_from<T: UintWidth>(&self, r : &mut BitReader) -> Result<T, DecodeError> {
+        r.try_read_code(self.uint_code())
+    }
+
+    // concatenate encoding of an unsigned integer of type T to a previously existing bit string
+    fn append_uint<T: UintWidth>(&mut self, v_in : T) {
+        let code = self.uint_code();
+        append_code(code, self.bitstr_mut(), v_in)
+    }
+
+    // read the next encoding of an unsigned integer of type T from a bit string
+    // at the bit offset indicated by the cursor
+    // cursor must be initialized to zero before using it
+    fn read_uint<T: UintWidth>(&self, bitstring_cursor : & mut usize) -> T {
+        read_code(self.uint_code(), self.bitstr(), bitstring_cursor)
+    }
+
+    // same as read_uint() but returns an error instead of panicking on truncated or corrupt bits
+    fn try_read_uint<T: UintWidth>(&self, bitstring_cursor : & mut usize) -> Result<T, DecodeError> {
+        try_read_code(self.uint_code(), self.bitstr(), bitstring_cursor)
+    }
+
     // concatenate u32 encoding to a previously existing bit string
-    fn append_uint32(&mut self, v_in : u32);
+    fn append_uint32(&mut self, v_in : u32) {
+        self.append_uint(v_in)
+    }
 
     // read the next u32 encoding from a bit string at the bit offset indicated by the cursor
     // cursor must be initialized to zero before using it
-    fn read_uint32(&self, bitstring_cursor : & mut usize) -> u32;
-}
\ No newline at end of file
+    fn read_uint32(&self, bitstring_cursor : & mut usize) -> u32 {
+        self.read_uint(bitstring_cursor)
+    }
+
+    fn try_read_uint32(&self, bitstring_cursor : & mut usize) -> Result<u32, DecodeError> {
+        self.try_read_uint(bitstring_cursor)
+    }
+}
+
+// helpers for the classic universal codes, which write the bits of a value
+// most significant bit first, unlike U32Encoding and SmallIntEncoding.
+// these codes encode v + 1 or v + 2^k, which does not fit in a u128 when v is near u128::MAX,
+// so we carry the sum as its low 128 bits plus its bit length, which can be 129.
+
+// low 128 bits of v + offset, and the bit length of the whole sum
+pub fn sum_with_bit_length(v: u128, offset: u128) -> (u128, u32) {
+    let (sum, carry) = v.overflowing_add(offset);
+    if carry {
+        (sum, u128::BITS + 1)
+    } else {
+        (sum, u128::BITS - sum.leading_zeros())
+    }
+}
+
+// 2^n - 1, for n up to 128
+pub fn low_bits_mask(n: u32) -> u128 {
+    assert!(n <= u128::BITS);
+    if n == u128::BITS { u128::MAX } else { (1 << n) - 1 }
+}
+
+// append the low n bits of v, least significant first, a 64-bit word at a time
+pub fn append_bits_lsb_first(bs: &mut DynBitString, v: u128, n: u32) {
+    assert!(n <= u128::BITS);
+    if n <= u64::BITS {
+        bs.append_uint(v as u64, n);
+    } else {
+        bs.append_uint(v as u64, u64::BITS);
+        bs.append_uint((v >> u64::BITS) as u64, n - u64::BITS);
+    }
+}
+
+// inverse of append_bits_lsb_first(), Truncated if fewer than n bits are left
+pub fn try_read_bits_lsb_first(bs: &DynBitString, bitstring_cursor: &mut usize, n: u32) -> Result<u128, DecodeError> {
+    if n > u128::BITS {
+        return Err(DecodeError::Overflow);
+    }
+    if bs.len().saturating_sub(*bitstring_cursor) < n as usize {
+        return Err(DecodeError::Truncated);
+    }
+    let low_bits = n.min(u64::BITS);
+    let mut v = bs.read_uint(*bitstring_cursor, low_bits) as u128;
+    if n > u64::BITS {
+        v |= (bs.read_uint(*bitstring_cursor + u64::BITS as usize, n - u64::BITS) as u128) << u64::BITS;
+    }
+    *bitstring_cursor += n as usize;
+    Ok(v)
+}
+
+// the low n bits of v in reverse order
+fn reverse_low_bits(v: u128, n: u32) -> u128 {
+    if n == 0 { 0 } else { v.reverse_bits() >> (u128::BITS - n) }
+}
+
+// append the low n bits of v, most significant first
+pub fn append_bits_msb_first(bs: &mut DynBitString, v: u128, n: u32) {
+    assert!(n <= u128::BITS);
+    append_bits_lsb_first(bs, reverse_low_bits(v & low_bits_mask(n), n), n);
+}
+
+// inverse of append_bits_msb_first()
+pub fn read_bits_msb_first(bs: &DynBitString, bitstring_cursor: &mut usize, n: u32) -> u128 {
+    assert!(n <= u128::BITS);
+    try_read_bits_msb_first(bs, bitstring_cursor, n).unwrap()
+}
+
+// Overflow if n > 128
+pub fn try_read_bits_msb_first(bs: &DynBitString, bitstring_cursor: &mut usize, n: u32) -> Result<u128, DecodeError> {
+    let v = try_read_bits_lsb_first(bs, bitstring_cursor, n)?;
+    Ok(reverse_low_bits(v, n))
+}
+
+// append n copies of bit followed by one !bit
+pub fn append_unary(bs: &mut DynBitString, n: u128, bit: bool) {
+    let word = if bit { u64::MAX } else { 0 };
+    let mut rest = n;
+    while rest > 0 {
+        let nbits = rest.min(u64::BITS as u128) as u32;
+        bs.append_uint(word, nbits);
+        rest -= nbits as u128;
+    }
+    bs.append(!bit);
+}
+
+// inverse of append_unary()
+pub fn read_unary(bs: &DynBitString, bitstring_cursor: &mut usize, bit: bool) -> u128 {
+    try_read_unary(bs, bitstring_cursor, bit).unwrap()
+}
+
+pub fn try_read_unary(bs: &DynBitString, bitstring_cursor: &mut usize, bit: bool) -> Result<u128, DecodeError> {
+    // count a word of bits at a time, up to the first !bit
+    let mut cursor = *bitstring_cursor;
+    let mut n: u128 = 0;
+    loop {
+        let nbits = bs.len().saturating_sub(cursor).min(u64::BITS as usize) as u32;
+        if nbits == 0 {
+            return Err(DecodeError::Truncated);
+        }
+        let word = bs.read_uint(cursor, nbits);
+        let run = if bit { word.trailing_ones() } else { word.trailing_zeros() }.min(nbits);
+        n += run as u128;
+        cursor += run as usize;
+        if run < nbits {
+            break;
+        }
+    }
+    *bitstring_cursor = cursor + 1;
+    Ok(n)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::dyn_bit_string::get_bits;
+
+    // every prefix shorter than bs fails to read as Truncated and leaves the cursor at 0
+    fn check_prefixes_truncated(bs: &DynBitString, read: &dyn Fn(&DynBitString, &mut usize) -> Result<u128, DecodeError>) {
+        for prefix_len in 0..bs.len() {
+            let prefix = get_bits(bs, 0, prefix_len as u32).unwrap_or(DynBitString::null());
+            let mut cursor: usize = 0;
+            assert_eq!(read(&prefix, &mut cursor), Err(DecodeError::Truncated), "{} bits", prefix_len);
+            assert_eq!(cursor, 0);
+        }
+    }
+
+    #[test]
+    pub fn test_continued_field() {
+        let offsets = ContinueOffsets::from_offsets(&[0, 3, 7]);
+        // 12 bits take the one word path, 100 bits the bit by bit path
+        for field_bits in [12, 100] {
+            let values: [u128; 6] = [0, 1, 2, 0xf, 0x10, low_bits_mask(field_bits)];
+            // start 60 bits in so fields cross a word boundary
+            let mut bs = DynBitString::null();
+            bs.append_uint(0, 60);
+            for v in values {
+                append_continued_field(&mut bs, v, field_bits, offsets);
+            }
+            let mut cursor: usize = 60;
+            for v in values {
+                let start = cursor;
+                assert_eq!(try_read_continued_field(&bs, &mut cursor, field_bits, offsets), Ok(v));
+                let v_bits = u128::BITS - v.leading_zeros();
+                assert_eq!(cursor - start, continued_field_len(v_bits, field_bits, offsets) as usize);
+            }
+            assert_eq!(cursor, bs.len());
+
+            let mut one = DynBitString::null();
+            append_continued_field(&mut one, low_bits_mask(field_bits), field_bits, offsets);
+            assert_eq!(one.len(), max_continued_field_len(field_bits, offsets) as usize);
+            check_prefixes_truncated(&one, &|b, c| try_read_continued_field(b, c, field_bits, offsets));
+        }
+    }
+
+    #[test]
+    pub fn test_unary() {
+        for bit in [false, true] {
+            let values: [u128; 5] = [0, 1, 63, 64, 200];
+            let mut bs = DynBitString::null();
+            bs.append_uint(0, 60);
+            for n in values {
+                append_unary(&mut bs, n, bit);
+            }
+            let mut cursor: usize = 60;
+            for n in values {
+                assert_eq!(try_read_unary(&bs, &mut cursor, bit), Ok(n));
+            }
+            assert_eq!(cursor, bs.len());
+
+            let mut one = DynBitString::null();
+            append_unary(&mut one, 130, bit);
+            check_prefixes_truncated(&one, &|b, c| try_read_unary(b, c, bit));
+        }
+    }
+
+    #[test]
+    pub fn test_bits_msb_first() {
+        let values: [(u128, u32); 6] = [(0, 0), (1, 1), (0b110, 3), (0x8000_0000_0000_0001, 64), (1 << 100, 101), (u128::MAX, 128)];
+        let mut bs = DynBitString::null();
+        bs.append_uint(0, 60);
+        for (v, n) in values {
+            append_bits_msb_first(&mut bs, v, n);
+        }
+        // the most significant bit goes first
+        let mut cursor: usize = 60 + 1;
+        assert_eq!(try_read_bit(&bs, &mut cursor), Ok(true));
+        assert_eq!(try_read_bit(&bs, &mut cursor), Ok(true));
+        assert_eq!(try_read_bit(&bs, &mut cursor), Ok(false));
+        let mut cursor: usize = 60;
+        for (v, n) in values {
+            assert_eq!(try_read_bits_msb_first(&bs, &mut cursor, n), Ok(v));
+        }
+        assert_eq!(cursor, bs.len());
+        assert_eq!(try_read_bits_msb_first(&bs, &mut cursor, 129), Err(DecodeError::Overflow));
+
+        let mut one = DynBitString::null();
+        append_bits_msb_first(&mut one, 1 << 100, 101);
+        check_prefixes_truncated(&one, &|b, c| try_read_bits_msb_first(b, c, 101));
+    }
+}
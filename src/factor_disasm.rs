@@ -0,0 +1,490 @@
+// disassembler for a bitstring of u32 factor encodings, or for a prime_codec stream,
+// for finding out why one does not decode
+//
+// decodes encode_factorization_with() or encode_factors_with() encodings one after another,
+// or the hybrid values of a PrimeCompressor stream, with the decoders' field traces
+// (see encode_prime::try_decode_factorization_traced_at_with()), and keeps the bit offset,
+// length and value of each field, and the prime powers they add up to.
+// it stops at the first thing the decoder rejects, or that the encoder would never
+// write even if the decoder takes it, and says what and where that is:
+//   a field that does not decode (DecodeError), such as an exponent above 32
+//   an index delta of 0 after the first prime power, a prime the encoder already merged
+//   a prime index past the last prime that fits in a u32
+//   prime powers that multiply to more than u32::MAX
+//   anything but a padding count and zero bits after a stream's end marker
+// when the bits came from whole bytes, up to 7 zero bits at the end are taken to be
+// padding rather than the start of another encoding.
+
+use std::fmt;
+use bitstring::BitString;
+use crate::dyn_bit_string::{DynBitString, BITS_PER_BYTE};
+use crate::encode_hybrid::{try_decode_hybrid_traced_with, HybridValue, SELECT_RAW};
+use crate::encode_prime::{reserved_length_code, try_decode_factorization_traced_at_with, DecodedField, FactorCodes, Factorization,
+                          FieldKind, IntAsPrms, PrmPwr, BIG_COFACTOR_CODE_OFFSET, END_OF_STREAM_CODE_OFFSET, ONE_CODE_OFFSET, ZERO_CODE_OFFSET};
+use crate::encoding_uint_trait::{try_read_bits_lsb_first, DecodeError};
+use crate::prime_codec::{PrimeStreamErrcode, PrimeStreamHeader, PrimeStreamMode, PRIME_STREAM_HEADER_LEN};
+use crate::prime_count::nth_prime;
+
+// bits after a problem to show, so the bits that threw the decoder off can be seen
+const BITS_SHOWN_AFTER_PROBLEM: usize = 64;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DisasmField {
+    pub offset : usize,     // of the first bit of the field
+    pub bits : usize,
+    pub kind : FieldKind,
+    pub value : u128,       // as read, before the exponent or prime index is worked out
+    pub note : String,      // what the value means
+}
+
+// offsets are of the field the problem was found in
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum DisasmProblem {
+    Undecodable { offset: usize, kind: FieldKind, error: DecodeError },
+    RepeatedPrime { offset: usize, prm_idx: u32 },
+    NoSuchPrime { offset: usize, prm_idx: u32 },
+    ValueOverflow { offset: usize },
+    BadEnd { offset: usize },
+}
+
+impl DisasmProblem {
+    pub fn offset(&self) -> usize {
+        match self {
+            DisasmProblem::Undecodable { offset, .. } => *offset,
+            DisasmProblem::RepeatedPrime { offset, .. } => *offset,
+            DisasmProblem::NoSuchPrime { offset, .. } => *offset,
+            DisasmProblem::ValueOverflow { offset } => *offset,
+            DisasmProblem::BadEnd { offset } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for DisasmProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DisasmProblem::Undecodable { kind, error, .. } => write!(f, "{} field: {}", kind, error),
+            DisasmProblem::RepeatedPrime { prm_idx, .. } =>
+                write!(f, "index delta of 0 repeats prime index {}, which the encoder never does", prm_idx),
+            DisasmProblem::NoSuchPrime { prm_idx, .. } =>
+                write!(f, "prime index {} is past the last prime below 2^32", prm_idx),
+            DisasmProblem::ValueOverflow { .. } => write!(f, "value is more than u32::MAX"),
+            DisasmProblem::BadEnd { .. } =>
+                write!(f, "end of stream is not followed by a 2-bit padding count and zero bits to a whole byte"),
+        }
+    }
+}
+
+impl std::error::Error for DisasmProblem {}
+
+#[derive(Debug)]
+pub struct DisasmEncoding {
+    pub offset : usize,
+    pub bits : usize,                                  // up to the problem, if there is one
+    pub fields : Vec<DisasmField>,
+    pub prime_powers : IntAsPrms<u32>,                 // as far as they were read
+    pub factorization : Option<Factorization<u32>>,    // None if there is a problem, or the value is raw
+    pub value : Option<u32>,                           // None if there is a problem
+    pub problem : Option<DisasmProblem>,
+}
+
+// a prime_codec stream's end marker, the selector and length fields and the padding count after them
+#[derive(Debug)]
+pub struct DisasmEndOfStream {
+    pub offset : usize,
+    pub bits : usize,
+    pub padding_bytes : Option<u32>,      // in the last value, None if there is a problem
+    pub problem : Option<DisasmProblem>,
+}
+
+#[derive(Debug)]
+pub struct Disassembly {
+    pub encodings : Vec<DisasmEncoding>,           // the last one has the problem, if there is one
+    pub end_of_stream : Option<DisasmEndOfStream>, // prime_codec streams only
+    pub padding_bits : usize,
+}
+
+impl Disassembly {
+    pub fn problem(&self) -> Option<&DisasmProblem> {
+        match &self.end_of_stream {
+            Some(end) => end.problem.as_ref(),
+            None => self.encodings.last().and_then(|e| e.problem.as_ref()),
+        }
+    }
+
+    // one line per encoding followed by a line per field, for the bits that were disassembled
+    pub fn format(&self, bits : &DynBitString) -> String {
+        let offset_width = bits.len().to_string().len();
+        let raw_width = self.encodings.iter().flat_map(|e| e.fields.iter()).map(|f| f.bits + 1).max().unwrap_or(0);
+        let mut s = String::new();
+        for (k, e) in self.encodings.iter().enumerate() {
+            s += &format!("#{} at bit {}, {} bits", k, e.offset, e.bits);
+            match e.value {
+                Some(v) => { s += &format!(": {}{}\n", v, format_prime_powers(e)); }
+                None => { s += ":\n"; }
+            }
+            for f in e.fields.iter() {
+                let raw = bits.slice(f.offset..f.offset + f.bits).to_bit_string().to_string();
+                s += &format!("  {:>ow$}  {:<rw$}  {:<11} {:>10}  {}\n", f.offset, raw, f.kind.to_string(), f.value, f.note,
+                              ow = offset_width, rw = raw_width);
+            }
+            if let Some(p) = &e.problem {
+                s += &format_problem(p, bits);
+            }
+        }
+        if let Some(end) = &self.end_of_stream {
+            s += &format!("end of stream at bit {}, {} bits", end.offset, end.bits);
+            match end.padding_bytes {
+                Some(n) => { s += &format!(": {} padding bytes in the last value\n", n); }
+                None => { s += ":\n"; }
+            }
+            if let Some(p) = &end.problem {
+                s += &format_problem(p, bits);
+            }
+        }
+        if self.padding_bits > 0 {
+            s += &format!("padding : {} zero bits\n", self.padding_bits);
+        }
+        s
+    }
+}
+
+fn format_problem(p : &DisasmProblem, bits : &DynBitString) -> String {
+    let end = bits.len().min(p.offset() + BITS_SHOWN_AFTER_PROBLEM);
+    let mut s = format!("!! bit {}: {}\n", p.offset(), p);
+    if end > p.offset() {
+        s += &format!("!! bits from there: {}{}\n", bits.slice(p.offset()..end).to_bit_string(),
+                      if end < bits.len() { " ..." } else { "" });
+    } else {
+        s += "!! at the end of the bits\n";
+    }
+    s
+}
+
+fn format_prime_power(p : u32, exp : u32) -> String {
+    if exp == 1 { p.to_string() } else { format!("{}^{}", p, exp) }
+}
+
+// " = 2^2 * 3", with the cofactor if there is one
+fn format_prime_powers(e : &DisasmEncoding) -> String {
+    let mut terms : Vec<String> = e.prime_powers.prm_powers.iter().map(|pp| {
+        format_prime_power(nth_prime(pp.prm_idx).ok().flatten().unwrap(), pp.exp as u32)
+    }).collect();
+    if let Some(Factorization::BigCofactor { cofactor, .. }) = &e.factorization {
+        terms.push(cofactor.to_string());
+    }
+    if terms.is_empty() { String::new() } else { format!(" = {}", terms.join(" * ")) }
+}
+
+// disassemble encodings from the start of bs until its end or the first problem
+// byte_padded says bs came from whole bytes, so a short run of zero bits at the end is padding
+pub fn disassemble_factorizations( bs : &DynBitString, codes : &FactorCodes, byte_padded : bool ) -> Disassembly {
+    let mut d = Disassembly { encodings: vec![], end_of_stream: None, padding_bits: 0 };
+    let mut cursor : usize = 0;
+    while cursor < bs.len() {
+        let rest = bs.slice(cursor..bs.len());
+        if byte_padded && rest.len() < BITS_PER_BYTE && rest.find_first_set().is_none() {
+            d.padding_bits = rest.len();
+            break;
+        }
+        let offset = cursor;
+        let mut trace : Vec<DecodedField> = vec![];
+        let decoded = try_decode_factorization_traced_at_with::<u32>(bs, &mut cursor, codes, &mut trace);
+        let e = disasm_encoding(offset, &trace, decoded.map(HybridValue::Factored));
+        let stop = e.problem.is_some();
+        d.encodings.push(e);
+        if stop {
+            break;
+        }
+    }
+    d
+}
+
+#[derive(Debug)]
+pub struct PrimeStreamDisassembly {
+    pub header : PrimeStreamHeader,
+    pub bits : DynBitString,          // after the header, the offsets in disassembly are of these
+    pub disassembly : Disassembly,
+}
+
+// disassemble a whole PrimeCompressor stream, header and all, see prime_codec
+// the header must be good, the codes must be the ones the stream was compressed with
+pub fn disassemble_prime_stream( bytes : &[u8], codes : &FactorCodes ) -> Result<PrimeStreamDisassembly, PrimeStreamErrcode> {
+    let header = PrimeStreamHeader::from_bytes(bytes)?;
+    if header.mode == PrimeStreamMode::ArithU32Values {
+        return Err(PrimeStreamErrcode::WrongMode);
+    }
+    let body = &bytes[PRIME_STREAM_HEADER_LEN..];
+    let bs = DynBitString::from_bytes(body, body.len() * BITS_PER_BYTE).unwrap();
+    let mut d = Disassembly { encodings: vec![], end_of_stream: None, padding_bits: 0 };
+    let mut cursor : usize = 0;
+    loop {
+        let offset = cursor;
+        let mut trace : Vec<DecodedField> = vec![];
+        let decoded = try_decode_hybrid_traced_with::<u32>(&bs, &mut cursor, codes, &mut trace);
+        if decoded.is_err() && is_end_of_stream(&trace) {
+            d.end_of_stream = Some(disasm_end_of_stream(&bs, offset, &trace, &mut d.padding_bits));
+            break;
+        }
+        let e = disasm_encoding(offset, &trace, decoded);
+        let stop = e.problem.is_some();
+        d.encodings.push(e);
+        if stop {
+            break;
+        }
+    }
+    Ok(PrimeStreamDisassembly { header, bits: bs, disassembly: d })
+}
+
+// the decoder stopped at the length code reserved for the end of a stream
+fn is_end_of_stream( trace : &[DecodedField] ) -> bool {
+    matches!(trace, [DecodedField { kind: FieldKind::Selector, value: Some(0), .. },
+                     DecodedField { kind: FieldKind::Length, value: Some(v), .. }]
+             if *v == reserved_length_code::<u32>(END_OF_STREAM_CODE_OFFSET) as u128)
+}
+
+// the padding count after the end marker, and zero bits to a whole byte after that
+fn disasm_end_of_stream( bs : &DynBitString, offset : usize, trace : &[DecodedField], padding_bits : &mut usize ) -> DisasmEndOfStream {
+    let mut cursor = trace[1].offset + trace[1].bits;
+    let mut end = DisasmEndOfStream { offset, bits: cursor - offset, padding_bytes: None, problem: None };
+    let count_offset = cursor;
+    let padding_bytes = match try_read_bits_lsb_first(bs, &mut cursor, 2) {
+        Ok(n) => n as u32,
+        Err(_) => {
+            end.problem = Some(DisasmProblem::BadEnd { offset: count_offset });
+            return end;
+        }
+    };
+    end.bits = cursor - offset;
+    let rest = bs.slice(cursor..bs.len());
+    if rest.len() >= BITS_PER_BYTE || rest.find_first_set().is_some() {
+        end.problem = Some(DisasmProblem::BadEnd { offset: cursor });
+        return end;
+    }
+    end.padding_bytes = Some(padding_bytes);
+    *padding_bits = rest.len();
+    end
+}
+
+// a DisasmEncoding of the fields the decoder read for one encoding, checked for what the
+// encoder never writes. decoded is what the decoder made of them, on error the last
+// field in trace is the one it stopped at
+
+fn disasm_encoding( offset : usize, trace : &[DecodedField], decoded : Result<HybridValue<u32>, DecodeError> ) -> DisasmEncoding {
+    let mut e = DisasmEncoding { offset, bits: 0, fields: vec![], prime_powers: IntAsPrms { prm_powers: vec![] },
+                                 factorization: None, value: None, problem: None };
+    let checked_fields = if decoded.is_ok() { trace.len() } else { trace.len() - 1 };
+    let mut exponents : Vec<u32> = vec![];
+    let mut prm_idx : u128 = 0;
+    let mut value : u128 = 1;
+    for (k, field) in trace.iter().enumerate() {
+        let Some(v) = field.value else { break };
+        let mut note = String::new();
+        if k < checked_fields {
+            match note_field(field.kind, v, field.offset, &mut exponents, &mut prm_idx, &mut value, &mut e) {
+                Ok(n) => { note = n; }
+                Err(p) => { e.problem = Some(p); }
+            }
+        } else {
+            // the field the decoder stopped at, only say what it reads as
+            note = match field.kind {
+                FieldKind::Exponent => format!("exponent {}", v + 1),
+                FieldKind::Length if v == reserved_length_code::<u32>(END_OF_STREAM_CODE_OFFSET) as u128 => "end of stream".to_string(),
+                _ => String::new(),
+            };
+        }
+        e.fields.push(DisasmField { offset: field.offset, bits: field.bits, kind: field.kind, value: v, note });
+        if e.problem.is_some() {
+            break;
+        }
+    }
+    e.bits = e.fields.last().map_or(0, |f| f.offset + f.bits - offset);
+    if e.problem.is_some() {
+        return e;
+    }
+    match decoded {
+        Ok(HybridValue::Factored(f)) => {
+            e.value = Some(value as u32);
+            e.factorization = Some(f);
+        }
+        Ok(HybridValue::Raw(v)) => { e.value = Some(v); }
+        Err(error) => {
+            let last = trace.last().unwrap();
+            e.problem = Some(DisasmProblem::Undecodable { offset: last.offset, kind: last.kind, error });
+        }
+    }
+    e
+}
+
+// what the value v of a field means, adding any prime power it completes to e.prime_powers
+// and to value. exponents are kept until the index deltas they go with are read
+fn note_field( kind : FieldKind, v : u128, offset : usize, exponents : &mut Vec<u32>, prm_idx : &mut u128,
+               value : &mut u128, e : &mut DisasmEncoding ) -> Result<String, DisasmProblem> {
+    let note = match kind {
+        FieldKind::Selector => if v == SELECT_RAW as u128 { "raw".to_string() } else { "factors".to_string() },
+        FieldKind::Raw => String::new(),
+        FieldKind::Length => match v {
+            _ if v == reserved_length_code::<u32>(ZERO_CODE_OFFSET) as u128 => { *value = 0; "zero".to_string() }
+            _ if v == reserved_length_code::<u32>(ONE_CODE_OFFSET) as u128 => "one".to_string(),
+            _ if v == reserved_length_code::<u32>(BIG_COFACTOR_CODE_OFFSET) as u128 => "big cofactor after the prime powers".to_string(),
+            _ if v == reserved_length_code::<u32>(END_OF_STREAM_CODE_OFFSET) as u128 => "end of stream".to_string(),
+            _ => format!("{} prime powers", v + 1),
+        },
+        FieldKind::PrimePowerCount => format!("{} prime powers", v),
+        FieldKind::Exponent => {
+            exponents.push(v as u32 + 1);
+            format!("exponent {}", v + 1)
+        }
+        FieldKind::IndexDelta => {
+            let k = e.prime_powers.prm_powers.len();
+            *prm_idx += v;
+            let idx = *prm_idx as u32;
+            if k > 0 && v == 0 {
+                return Err(DisasmProblem::RepeatedPrime { offset, prm_idx: idx });
+            }
+            let p = nth_prime(idx).ok().flatten().ok_or(DisasmProblem::NoSuchPrime { offset, prm_idx: idx })?;
+            let exp = exponents[k];
+            e.prime_powers.prm_powers.push(PrmPwr { exp: exp as u8, prm_idx: idx });
+            for _j in 0..exp {
+                *value *= p as u128;
+                if *value > u32::MAX as u128 {
+                    return Err(DisasmProblem::ValueOverflow { offset });
+                }
+            }
+            format!("prime index {}, {}", idx, format_prime_power(p, exp))
+        }
+        FieldKind::Cofactor => {
+            *value *= v;
+            if *value > u32::MAX as u128 {
+                return Err(DisasmProblem::ValueOverflow { offset });
+            }
+            String::new()
+        }
+    };
+    Ok(note)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::bit_stream::BitWriter;
+    use crate::encode_prime::{write_factorization_with, DEFAULT_FACTOR_CODES};
+    use crate::uint_code::append_code;
+
+    #[test]
+    pub fn test_disassemble_factorizations() {
+        let codes = DEFAULT_FACTOR_CODES;
+        let factorizations = [
+            (Factorization::Indices(vec![0, 0, 1]), 12),
+            (Factorization::Zero, 0),
+            (Factorization::One, 1),
+            (Factorization::BigCofactor { ixs: vec![0], cofactor: 2147483647 }, 4294967294),
+            (Factorization::Indices(vec![203280220]), 4294967291),
+            (Factorization::BigCofactor { ixs: vec![], cofactor: 7 }, 7),
+        ];
+        let mut bs = DynBitString::null();
+        for (f, _v) in factorizations.iter() {
+            write_factorization_with(&mut BitWriter::new(&mut bs), f, &codes);
+        }
+        let d = disassemble_factorizations(&bs, &codes, false);
+        assert_eq!(d.problem(), None);
+        assert_eq!(d.encodings.len(), factorizations.len());
+        let mut next_offset = 0;
+        for (e, (f, v)) in d.encodings.iter().zip(factorizations.iter()) {
+            assert_eq!(e.factorization.as_ref(), Some(f));
+            assert_eq!(e.value, Some(*v));
+            // the fields cover the encoding with no gaps
+            for field in e.fields.iter() {
+                assert_eq!(field.offset, next_offset);
+                next_offset += field.bits;
+            }
+            assert_eq!(e.offset + e.bits, next_offset);
+        }
+        assert_eq!(next_offset, bs.len());
+        let text = d.format(&bs);
+        assert!(text.contains(": 12 = 2^2 * 3\n"), "{}", text);
+        assert!(text.contains(": 4294967294 = 2 * 2147483647\n"), "{}", text);
+
+        // from whole bytes, the padding is not another encoding
+        let padded = DynBitString::from_bytes(&bs.to_bytes(), bs.len().next_multiple_of(BITS_PER_BYTE)).unwrap();
+        let d = disassemble_factorizations(&padded, &codes, true);
+        assert_eq!(d.encodings.len(), factorizations.len());
+        assert_eq!(d.padding_bits, padded.len() - bs.len());
+
+        // cut off in the middle of the 4th encoding
+        let cut = d.encodings[3].fields[3].offset + 1;
+        let truncated = bs.slice(0..cut).to_bit_string();
+        let d = disassemble_factorizations(&truncated, &codes, false);
+        assert_eq!(d.encodings.len(), 4);
+        assert_eq!(d.encodings[3].fields.len(), 3);
+        assert_eq!(d.problem(), Some(&DisasmProblem::Undecodable { offset: cut - 1, kind: FieldKind::IndexDelta, error: DecodeError::Truncated }));
+        assert!(d.format(&truncated).contains(&format!("!! bit {}: index delta field: cannot decode bits: Truncated\n", cut - 1)));
+
+        // what the decoder takes but the encoder never writes
+        let mut repeated = DynBitString::null();
+        append_code(codes.length, &mut repeated, 1u32);
+        append_code(codes.exponent, &mut repeated, 0u32);
+        append_code(codes.exponent, &mut repeated, 0u32);
+        append_code(codes.index_delta, &mut repeated, 3u32);
+        let offset = repeated.len();
+        append_code(codes.index_delta, &mut repeated, 0u32);
+        let d = disassemble_factorizations(&repeated, &codes, false);
+        assert_eq!(d.problem(), Some(&DisasmProblem::RepeatedPrime { offset, prm_idx: 3 }));
+
+        let mut overflow = DynBitString::null();
+        append_code(codes.length, &mut overflow, 0u32);
+        append_code(codes.exponent, &mut overflow, 4u32);
+        let offset = overflow.len();
+        append_code(codes.index_delta, &mut overflow, 1000u32);
+        let d = disassemble_factorizations(&overflow, &codes, false);
+        assert_eq!(d.problem(), Some(&DisasmProblem::ValueOverflow { offset }));
+        assert_eq!(d.encodings[0].prime_powers.prm_powers.len(), 1);
+    }
+
+    #[test]
+    pub fn test_disassemble_prime_stream() {
+        use std::io::Write;
+        use crate::prime_codec::{compress_u32s, PrimeCompressor};
+        use crate::prime_table::PrimeTable;
+
+        let prms = PrimeTable::up_to(100);
+        let values = [12, 0, 1, 4294967294, 4294967291, 97 * 101, u32::MAX];
+        let bytes = compress_u32s(&values, &prms).unwrap();
+        let sd = disassemble_prime_stream(&bytes, &DEFAULT_FACTOR_CODES).unwrap();
+        let d = &sd.disassembly;
+        assert_eq!(sd.header.upper_bound, 100);
+        assert_eq!(d.problem(), None);
+        let decoded : Vec<u32> = d.encodings.iter().map(|e| e.value.unwrap()).collect();
+        assert_eq!(decoded, values);
+        // every value starts with its selector, and a raw value is 33 bits
+        assert!(d.encodings.iter().all(|e| e.fields[0].kind == FieldKind::Selector));
+        assert!(d.encodings.iter().any(|e| e.factorization.is_none() && e.bits == 33));
+        assert_eq!(d.end_of_stream.as_ref().unwrap().padding_bytes, Some(0));
+        assert_eq!(d.encodings.last().map(|e| e.offset + e.bits), d.end_of_stream.as_ref().map(|end| end.offset));
+        assert!(d.format(&sd.bits).contains(": 12 = 2^2 * 3\n"));
+
+        let mut compressor = PrimeCompressor::new(vec![], &prms, PrimeStreamMode::Bytes).unwrap();
+        compressor.write_all(b"hello").unwrap();
+        let bytes = compressor.finish().unwrap();
+        let d = disassemble_prime_stream(&bytes, &DEFAULT_FACTOR_CODES).unwrap().disassembly;
+        assert_eq!(d.encodings.len(), 2);
+        assert_eq!(d.end_of_stream.as_ref().unwrap().padding_bytes, Some(3));
+
+        // no end marker, and a byte after it
+        let d = disassemble_prime_stream(&bytes[..bytes.len() - 1], &DEFAULT_FACTOR_CODES).unwrap().disassembly;
+        assert!(matches!(d.problem(), Some(DisasmProblem::Undecodable { error: DecodeError::Truncated, .. })), "{:?}", d.problem());
+        let mut longer = bytes.clone();
+        longer.push(0);
+        let d = disassemble_prime_stream(&longer, &DEFAULT_FACTOR_CODES).unwrap().disassembly;
+        assert!(matches!(d.problem(), Some(DisasmProblem::BadEnd { .. })));
+        assert_eq!(disassemble_prime_stream(&bytes[..4], &DEFAULT_FACTOR_CODES).unwrap_err(), PrimeStreamErrcode::Truncated);
+
+        // outside a stream the end of stream code is not a factor encoding
+        let mut end = DynBitString::null();
+        append_code(DEFAULT_FACTOR_CODES.length, &mut end, reserved_length_code::<u32>(END_OF_STREAM_CODE_OFFSET));
+        let d = disassemble_factorizations(&end, &DEFAULT_FACTOR_CODES, false);
+        assert_eq!(d.problem(), Some(&DisasmProblem::Undecodable { offset: 0, kind: FieldKind::Length, error: DecodeError::InvalidLength }));
+        assert_eq!(d.encodings[0].fields[0].note, "end of stream");
+    }
+}
@@ -78,6 +78,14 @@ pub fn get_env_var_bool_with_default(str_var_name : &str, default_value : bool)
 
 }
 
+// any type that can be parsed from a string, such as uint_code::UintCode
+pub fn get_env_var_parsed_with_default<T: FromStr>(str_var_name : &str, default_value : T) -> Result<T, EnvVarFailure> {
+    match env::var(str_var_name) {
+        Err(_) => Ok(default_value),
+        Ok(env_var_val) => T::from_str(env_var_val.as_str()).map_err(|_| EnvVarFailure::CouldNotParseVar)
+    }
+}
+
 // FIXME: do we need a "mock" capability to test this (exit call)?
 pub fn env_var_usage( e : EnvVarFailure, var : &String ) {
     let s = match e {
@@ -116,4 +124,10 @@ pub mod tests {
         assert_eq!(get_env_var_bool_with_default("BOOL_VAL_UNDEFINED", true), Ok(true));
         assert_eq!(get_env_var_bool_with_default("BOOL_VAL_UNDEFINED", false), Ok(false));
     }
-}
\ No newline at end of file
+    #[test]
+    pub fn test_get_env_var_parsed_with_default() {
+        assert_eq!(get_env_var_parsed_with_default("U32_VAL", 3u64), Ok(55));
+        assert_eq!(get_env_var_parsed_with_default("U32_VAL_UNPARSEABLE", 3u64), Err(EnvVarFailure::CouldNotParseVar));
+        assert_eq!(get_env_var_parsed_with_default("U32_VAL_NOT_THERE", 3u64), Ok(3));
+    }
+}
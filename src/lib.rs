@@ -4,9 +4,33 @@ pub mod encode_prime;
 pub mod primes;
 pub mod encoding_u32;
 pub mod dyn_bit_string;
+pub mod bit_stream;
+pub mod bit_slice;
+pub mod bit_text;
+pub mod bit_io;
 pub mod get_env_var;
 pub mod plot;
 pub mod encoding_small_int;
 pub mod encoding_uint_trait;
+pub mod segmented_sieve;
+pub mod wheel;
+pub mod prime_file;
+pub mod prime_table;
+pub mod prime_index;
+pub mod miller_rabin;
+pub mod pollard_rho;
+pub mod uint_width;
+pub mod prime_codec;
+pub mod prime_count;
+pub mod encode_hybrid;
+pub mod encoding_elias;
+pub mod encoding_fibonacci;
+pub mod encoding_golomb;
+pub mod uint_code;
+pub mod range_coder;
+pub mod arith_codec;
+pub mod encoding_bounded;
+pub mod encoding_optimizer;
+pub mod factor_disasm;
 
 
@@ -0,0 +1,107 @@
+// deterministic Miller-Rabin primality test, no prime table needed
+// for n < 2^64 it is enough to try a fixed set of witnesses (bases):
+//  - n < 4,759,123,141 : 2, 7, 61 (Jaeschke), covers every u32
+//  - n < 3,317,044,064,679,887,385,961,981 : the first 12 primes, covers every u64
+// so the answer is always right, not just probably right.
+
+const U32_WITNESSES: [u64; 3] = [2, 7, 61];
+const U64_WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+// small primes to divide out before doing any modular exponentiation
+const SMALL_PRIMES: [u64; 12] = U64_WITNESSES;
+
+pub(crate) fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result: u64 = 1;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+// n is odd and > witness, n - 1 = d * 2^s with d odd
+// returns true if witness proves n is composite
+fn is_witness_for_composite(witness: u64, n: u64, d: u64, s: u32) -> bool {
+    let mut x = pow_mod(witness, d, n);
+    if x == 1 || x == n - 1 {
+        return false;
+    }
+    for _ in 1..s {
+        x = mul_mod(x, x, n);
+        if x == n - 1 {
+            return false;
+        }
+    }
+    true
+}
+
+fn miller_rabin(n: u64, witnesses: &[u64]) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in SMALL_PRIMES {
+        if n == p {
+            return true;
+        }
+        if n.is_multiple_of(p) {
+            return false;
+        }
+    }
+    // n has no factor <= 37, so if n < 41^2 it must be prime
+    if n < 41 * 41 {
+        return true;
+    }
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    !witnesses.iter().any(|w| is_witness_for_composite(*w, n, d, s))
+}
+
+pub fn is_prime_u32(n: u32) -> bool {
+    miller_rabin(n as u64, &U32_WITNESSES)
+}
+
+pub fn is_prime_u64(n: u64) -> bool {
+    miller_rabin(n, &U64_WITNESSES)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::primes::gen_primes_up_to;
+
+    #[test]
+    pub fn test_is_prime_u32() {
+        let prms = gen_primes_up_to(100000);
+        for n in 0..100000 {
+            assert_eq!(is_prime_u32(n), prms.binary_search(&n).is_ok(), "n = {}", n);
+        }
+        // strong pseudoprimes to base 2, or to bases 2, 3, 5 and 7
+        assert!(!is_prime_u32(2047));
+        assert!(!is_prime_u32(3215031751));
+        assert!(is_prime_u32(4294967291));
+        assert!(!is_prime_u32(u32::MAX));
+        assert!(!is_prime_u32(65521 * 65519));
+    }
+
+    #[test]
+    pub fn test_is_prime_u64() {
+        for n in 0..100000u32 {
+            assert_eq!(is_prime_u64(n as u64), is_prime_u32(n));
+        }
+        assert!(is_prime_u64(4294967311));
+        // composite, but a strong pseudoprime to bases 2, 7 and 61
+        assert!(!is_prime_u64(4759123141));
+        assert!(!is_prime_u64(3825123056546413051));
+        assert!(is_prime_u64(18446744073709551557));
+        assert!(!is_prime_u64(u64::MAX));
+        assert!(!is_prime_u64(4294967291 * 4294967279));
+    }
+}
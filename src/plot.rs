@@ -22,7 +22,11 @@ pub fn plot_histogram_f64( filename : &str, plot_name : &str, x_label : &str, y_
                         -> Result<String, Box<dyn std::error::Error>> {
     let binding = std::path::MAIN_SEPARATOR.to_string();
     let sep = binding.as_str();
-    let pathname = env::var("PLOT_DIR").unwrap() + sep + filename;
+    let mut pathname = env::var("PLOT_DIR").unwrap() + sep + filename;
+    // the image format comes from the file extension, so plot to a PNG if there is none
+    if std::path::Path::new(filename).extension().is_none() {
+        pathname += ".png";
+    }
     println!("creating file {}", pathname);
     let root = BitMapBackend::new(pathname.as_str(), (640, 480)).into_drawing_area();
     root.fill(&WHITE)?;
@@ -78,10 +82,10 @@ pub mod tests {
         let fake_histo: Vec<f64> = vec![1.0, 2.0, 3.0, 4.0];
         let fake_fn = "foo_plot_f64";
         let result = plot_histogram_f64(
-            fake_fn.as_ref(),
-            fake_fn.as_ref(),
-            "my X-label".as_ref(),
-            "my Y-label".as_ref(),
+            fake_fn,
+            fake_fn,
+            "my X-label",
+            "my Y-label",
             &fake_histo);
         match result {
             Ok( filenm ) => {
@@ -97,10 +101,10 @@ pub mod tests {
         let fake_histo: Vec<u32> = vec![1, 2, 3, 4];
         let fake_fn = "foo_plot_u32";
         let result = plot_histogram_u32(
-            fake_fn.as_ref(),
-            fake_fn.as_ref(),
-            "my X-label".as_ref(),
-            "my Y-label".as_ref(),
+            fake_fn,
+            fake_fn,
+            "my X-label",
+            "my Y-label",
             &fake_histo);
         match result {
             Ok( filenm ) => {
@@ -0,0 +1,130 @@
+// factor u64 values without a prime table
+// small primes are divided out by trial division, then each remaining cofactor
+// is either proven prime with Miller-Rabin or split in two with Pollard's rho
+// (Brent's variant), until only primes are left.
+//
+// Pollard's rho iterates x -> x^2 + c mod n. mod any prime factor p of n the
+// sequence must repeat after about sqrt(p) steps, and when it does gcd(x - y, n)
+// picks up p. Brent's variant finds the cycle with a power-of-2 step count and
+// multiplies RHO_BATCH differences together before each gcd, so gcd is rare.
+
+use crate::miller_rabin::{is_prime_u64, mul_mod};
+
+// trial division by every odd number below this before using rho
+pub const RHO_TRIAL_DIVISION_LIMIT: u64 = 1 << 10;
+const RHO_BATCH: u64 = 128;
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// find a factor of odd composite n, None if this c does not work
+fn pollard_brent(n: u64, c: u64) -> Option<u64> {
+    let f = |x: u64| ((mul_mod(x, x, n) as u128 + c as u128) % n as u128) as u64;
+    let mut y: u64 = 2;
+    let mut x: u64 = y;
+    let mut ys: u64 = y;
+    let mut r: u64 = 1;
+    let mut q: u64 = 1;
+    let mut g: u64 = 1;
+    while g == 1 {
+        x = y;
+        for _ in 0..r {
+            y = f(y);
+        }
+        let mut k: u64 = 0;
+        while k < r && g == 1 {
+            ys = y;
+            for _ in 0..RHO_BATCH.min(r - k) {
+                y = f(y);
+                q = mul_mod(q, x.abs_diff(y), n);
+            }
+            g = gcd(q, n);
+            k += RHO_BATCH;
+        }
+        r *= 2;
+    }
+    if g == n {
+        // batch went past the factor, redo it one step at a time
+        loop {
+            ys = f(ys);
+            g = gcd(x.abs_diff(ys), n);
+            if g > 1 {
+                break;
+            }
+        }
+    }
+    if g == n { None } else { Some(g) }
+}
+
+// a non-trivial factor of composite n, 2 if n is even since rho may never find it
+pub fn find_factor(n: u64) -> u64 {
+    assert!(n > 3 && !is_prime_u64(n));
+    if n.is_multiple_of(2) {
+        return 2;
+    }
+    (1..n).find_map(|c| pollard_brent(n, c)).unwrap()
+}
+
+// prime factors of n in non-decreasing order, each one repeated as many times as it divides n
+// returns empty list for 0 and 1, which have no prime factorization
+pub fn factor_u64_primes(n: u64) -> Vec<u64> {
+    let mut factors: Vec<u64> = vec![];
+    if n < 2 {
+        return factors;
+    }
+    let mut num_to_factor = n;
+    let mut d: u64 = 2;
+    while d < RHO_TRIAL_DIVISION_LIMIT && d * d <= num_to_factor {
+        while num_to_factor.is_multiple_of(d) {
+            factors.push(d);
+            num_to_factor /= d;
+        }
+        d += if d == 2 { 1 } else { 2 };
+    }
+    let mut composites: Vec<u64> = vec![];
+    if num_to_factor > 1 {
+        composites.push(num_to_factor);
+    }
+    while let Some(m) = composites.pop() {
+        if is_prime_u64(m) {
+            factors.push(m);
+        } else {
+            let f = find_factor(m);
+            composites.push(f);
+            composites.push(m / f);
+        }
+    }
+    factors.sort();
+    factors
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_factor_u64_primes() {
+        assert!(factor_u64_primes(0).is_empty());
+        assert!(factor_u64_primes(1).is_empty());
+        assert_eq!(factor_u64_primes(2), [2]);
+        assert_eq!(factor_u64_primes(1 << 63), [2; 63]);
+        assert_eq!(factor_u64_primes(1000003 * 1000033), [1000003, 1000033]);
+        assert_eq!(factor_u64_primes(4294967291 * 4294967279), [4294967279, 4294967291]);
+        assert_eq!(factor_u64_primes(4294967291 * 4294967291), [4294967291, 4294967291]);
+        assert_eq!(factor_u64_primes(18446744073709551557), [18446744073709551557]);
+        assert_eq!(factor_u64_primes(u64::MAX), [3, 5, 17, 257, 641, 65537, 6700417]);
+        assert_eq!(factor_u64_primes(3 * 3 * 1021 * 1021 * 65537), [3, 3, 1021, 1021, 65537]);
+        assert_eq!(find_factor(2 * 4294967291), 2);
+        assert_eq!(find_factor(1 << 62), 2);
+        for n in (1u64 << 40)..(1u64 << 40) + 2000 {
+            let f = factor_u64_primes(n);
+            assert!(f.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(f.iter().all(|p| is_prime_u64(*p)));
+            assert_eq!(f.iter().product::<u64>(), n);
+        }
+    }
+}
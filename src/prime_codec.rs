@@ -0,0 +1,567 @@
+// streaming compressor/decompressor for a sequence of u32 values or a byte stream
+//
+// a compressed stream is a fixed-size header followed by one bitstream holding
+// the encodings of every value, concatenated. bits are packed into bytes least
+// significant bit first, same order as DynBitString.
+//
+// header, multi-byte fields are little-endian:
+//
+//  offset  size  field
+//       0     4  magic number "CWPZ"
+//       4     1  format version
+//       5     1  stream mode, see PrimeStreamMode, arith_codec has its own body format
+//       6     2  reserved, must be zero
+//       8     4  upper bound of prime table used to compress, the decompressor
+//                needs a table at least this big to turn indexes back into primes
+//
+// the bitstream starts right after the header. each value in it is
+// encode_hybrid::write_hybrid_with() of the value, so no value takes more than 33 bits:
+//   0   followed by encode_prime::encode_factorization() of its primes::factorization_of(),
+//       when that is shorter than 32 bits. 0, 1 and values with a prime factor past the
+//       compressor's prime table are written with the length codes reserved for them.
+//   1   followed by the 32 bits of the value, for any other value
+// the factor encodings use DEFAULT_FACTOR_CODES unless the compressor was given other
+// FactorCodes. the codes are not stored in the header, so the decompressor must be given
+// the same ones, see encoding_optimizer.
+// the stream ends with a 0 followed by the length code reserved for the end of a stream,
+// see encode_prime::END_OF_STREAM_CODE_OFFSET, and a 2-bit count of padding bytes in
+// the last value. after that the bitstream is padded with zero bits to a whole byte,
+// which is the last byte of the stream.
+//
+// in Bytes mode every 4 input bytes become one little-endian u32 value, the last
+// value is padded with zero bytes, and the padding is dropped again when decompressing.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Read, Write};
+use bitstring::BitString;
+use byteorder::{ByteOrder, LittleEndian};
+use crate::bit_stream::{BitReader, BitWriter};
+use crate::dyn_bit_string::{DynBitString, BITS_PER_BYTE};
+use crate::encode_hybrid::{try_read_hybrid_with, write_hybrid_with, HybridValue, SELECT_FACTORS};
+use crate::encode_prime::{reserved_length_code, FactorCodes, Factorization, DEFAULT_FACTOR_CODES, END_OF_STREAM_CODE_OFFSET};
+use crate::encoding_uint_trait::{try_read_bit, try_read_bits_lsb_first, DecodeError};
+use crate::prime_table::PrimeTable;
+use crate::primes::factorization_of;
+use crate::uint_code::try_read_code;
+
+pub const PRIME_STREAM_MAGIC: [u8; 4] = *b"CWPZ";
+pub const PRIME_STREAM_VERSION: u8 = 2;
+pub const PRIME_STREAM_HEADER_LEN: usize = 12;
+
+// compressor writes encoded bits to its stream once it has this many
+const FLUSH_BITS: usize = 1 << 15;
+
+// bits to have buffered before trying to decode, doubled until the value fits
+const MIN_READ_AHEAD_BITS: usize = 2048;
+const READ_CHUNK_BYTES: usize = 4096;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum PrimeStreamMode {
+    U32Values = 0,   // write_u32()/read_u32()
+    Bytes = 1,       // std::io::Write/std::io::Read
+    ArithU32Values = 2,  // arith_codec, range coded instead of the bitstream below
+}
+
+impl PrimeStreamMode {
+    pub fn from_u8(mode_code: u8) -> Option<Self> {
+        match mode_code {
+            0 => Some(PrimeStreamMode::U32Values),
+            1 => Some(PrimeStreamMode::Bytes),
+            2 => Some(PrimeStreamMode::ArithU32Values),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum PrimeStreamErrcode {
+    BadMagic,            // not a compressed stream
+    UnsupportedVersion,
+    UnknownMode,
+    BadHeader,           // reserved bytes are not zero
+    Truncated,           // stream ends before header or end of stream marker
+    TableTooSmall,       // prime table is smaller than the one used to compress
+    WrongMode,           // u32 call on a Bytes stream or byte call on a U32Values stream, or the other codec's stream
+    BadPrimeIndex,       // prime index past end of prime table, or product of primes overflows
+    BadEncoding,         // bits do not decode to a valid value
+    TrailingData,        // data after the end of stream marker other than zero bits padding its byte
+}
+
+impl fmt::Display for PrimeStreamErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid compressed stream: {:?}", self)
+    }
+}
+
+impl std::error::Error for PrimeStreamErrcode {}
+
+impl From<DecodeError> for PrimeStreamErrcode {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Truncated => PrimeStreamErrcode::Truncated,
+            DecodeError::InvalidLength | DecodeError::Overflow => PrimeStreamErrcode::BadEncoding,
+        }
+    }
+}
+
+impl From<PrimeStreamErrcode> for std::io::Error {
+    fn from(e: PrimeStreamErrcode) -> Self {
+        let kind = match e {
+            PrimeStreamErrcode::TableTooSmall | PrimeStreamErrcode::WrongMode => std::io::ErrorKind::InvalidInput,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct PrimeStreamHeader {
+    pub version: u8,
+    pub mode: PrimeStreamMode,
+    pub upper_bound: u32,
+}
+
+impl PrimeStreamHeader {
+    pub fn to_bytes(&self) -> [u8; PRIME_STREAM_HEADER_LEN] {
+        let mut hdr = [0u8; PRIME_STREAM_HEADER_LEN];
+        hdr[0..4].copy_from_slice(&PRIME_STREAM_MAGIC);
+        hdr[4] = self.version;
+        hdr[5] = self.mode as u8;
+        LittleEndian::write_u32(&mut hdr[8..12], self.upper_bound);
+        hdr
+    }
+
+    pub fn from_bytes(hdr: &[u8]) -> Result<Self, PrimeStreamErrcode> {
+        if hdr.len() < PRIME_STREAM_HEADER_LEN {
+            return Err(PrimeStreamErrcode::Truncated);
+        }
+        if hdr[0..4] != PRIME_STREAM_MAGIC {
+            return Err(PrimeStreamErrcode::BadMagic);
+        }
+        if hdr[4] != PRIME_STREAM_VERSION {
+            return Err(PrimeStreamErrcode::UnsupportedVersion);
+        }
+        let mode = match PrimeStreamMode::from_u8(hdr[5]) {
+            Some(m) => m,
+            None => { return Err(PrimeStreamErrcode::UnknownMode); }
+        };
+        if hdr[6..8].iter().any(|b| *b != 0) {
+            return Err(PrimeStreamErrcode::BadHeader);
+        }
+        Ok(PrimeStreamHeader { version: hdr[4], mode, upper_bound: LittleEndian::read_u32(&hdr[8..12]) })
+    }
+}
+
+// bits [0, whole bytes) of bs packed into bytes, least significant bit first
+fn whole_bytes_of(bs: &DynBitString) -> Vec<u8> {
+    let mut bytes = bs.to_bytes();
+    bytes.truncate(bs.len() / BITS_PER_BYTE);
+    bytes
+}
+
+pub struct PrimeCompressor<'a, W: Write> {
+    stream: W,
+    prms: &'a PrimeTable,
+    mode: PrimeStreamMode,
+    codes: FactorCodes,
+    bits: DynBitString,       // encoded bits not written to stream yet
+    partial_value: Vec<u8>,   // Bytes mode: input bytes that do not make a whole u32 yet
+}
+
+impl<'a, W: Write> PrimeCompressor<'a, W> {
+    // write the stream header, values are factored using prms
+    pub fn new(stream: W, prms: &'a PrimeTable, mode: PrimeStreamMode) -> Result<Self, std::io::Error> {
+        Self::new_with_codes(stream, prms, mode, DEFAULT_FACTOR_CODES)
+    }
+
+    // same as new() with the codes of your choice for the factor encodings
+    pub fn new_with_codes(mut stream: W, prms: &'a PrimeTable, mode: PrimeStreamMode, codes: FactorCodes) -> Result<Self, std::io::Error> {
+        if mode == PrimeStreamMode::ArithU32Values {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        let hdr = PrimeStreamHeader { version: PRIME_STREAM_VERSION, mode, upper_bound: prms.upper_bound() };
+        stream.write_all(&hdr.to_bytes())?;
+        Ok(PrimeCompressor { stream, prms, mode, codes, bits: DynBitString::null(), partial_value: vec![] })
+    }
+
+    pub fn write_u32(&mut self, v: u32) -> Result<(), std::io::Error> {
+        if self.mode != PrimeStreamMode::U32Values {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        self.append_value(v);
+        self.write_whole_bytes(FLUSH_BITS)
+    }
+
+    fn append_value(&mut self, v: u32) {
+        write_hybrid_with(&mut BitWriter::new(&mut self.bits), v, &factorization_of(v, self.prms), &self.codes);
+    }
+
+    // write out every whole byte of encoded bits, if there are at least min_bits of them
+    fn write_whole_bytes(&mut self, min_bits: usize) -> Result<(), std::io::Error> {
+        if self.bits.len() < min_bits {
+            return Ok(());
+        }
+        let bytes = whole_bytes_of(&self.bits);
+        self.stream.write_all(&bytes)?;
+        let bits_written = bytes.len() * BITS_PER_BYTE;
+        self.bits = self.bits.slice(bits_written..self.bits.len()).to_bit_string();
+        Ok(())
+    }
+
+    // write end of stream and return the underlying stream
+    // nothing written after the last call to this is decodable until finish() is called
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        let mut padding: usize = 0;
+        if !self.partial_value.is_empty() {
+            padding = 4 - self.partial_value.len();
+            self.partial_value.resize(4, 0);
+            let v = LittleEndian::read_u32(&self.partial_value);
+            self.append_value(v);
+        }
+        let mut w = BitWriter::new(&mut self.bits);
+        w.write_bit(SELECT_FACTORS);
+        w.write_code(self.codes.length, reserved_length_code::<u32>(END_OF_STREAM_CODE_OFFSET));
+        self.bits.append(padding & 1 != 0);
+        self.bits.append(padding & 2 != 0);
+        while !self.bits.len().is_multiple_of(BITS_PER_BYTE) {
+            self.bits.append(false);
+        }
+        self.write_whole_bytes(0)?;
+        self.stream.flush()?;
+        Ok(self.stream)
+    }
+}
+
+impl<W: Write> Write for PrimeCompressor<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        if self.mode != PrimeStreamMode::Bytes {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        for b in buf {
+            self.partial_value.push(*b);
+            if self.partial_value.len() == 4 {
+                let v = LittleEndian::read_u32(&self.partial_value);
+                self.partial_value.clear();
+                self.append_value(v);
+            }
+        }
+        self.write_whole_bytes(FLUSH_BITS)?;
+        Ok(buf.len())
+    }
+
+    // only whole bytes of whole values can be written before finish()
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.write_whole_bytes(0)?;
+        self.stream.flush()
+    }
+}
+
+pub struct PrimeDecompressor<'a, R: Read> {
+    stream: R,
+    prms: &'a PrimeTable,
+    header: PrimeStreamHeader,
+    codes: FactorCodes,
+    bits: DynBitString,       // bits read from stream, starting at a byte boundary, decoding continues at cursor
+    cursor: usize,
+    stream_ended: bool,       // read everything there is in stream
+    padding: Option<usize>,   // set once end of stream is decoded
+    held_value: Option<u32>,  // Bytes mode: last value, may be padded
+    out_bytes: VecDeque<u8>,  // Bytes mode: decoded bytes not returned by read() yet
+}
+
+impl<'a, R: Read> PrimeDecompressor<'a, R> {
+    // read and check stream header, prms must be at least as big as the compressor's table
+    pub fn new(stream: R, prms: &'a PrimeTable) -> Result<Self, std::io::Error> {
+        Self::new_with_codes(stream, prms, DEFAULT_FACTOR_CODES)
+    }
+
+    // codes must be the ones the compressor was given
+    pub fn new_with_codes(mut stream: R, prms: &'a PrimeTable, codes: FactorCodes) -> Result<Self, std::io::Error> {
+        let mut hdr_bytes = [0u8; PRIME_STREAM_HEADER_LEN];
+        match stream.read_exact(&mut hdr_bytes) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => { return Err(PrimeStreamErrcode::Truncated.into()); }
+            r => r?
+        }
+        let header = PrimeStreamHeader::from_bytes(&hdr_bytes)?;
+        if header.upper_bound > prms.upper_bound() {
+            return Err(PrimeStreamErrcode::TableTooSmall.into());
+        }
+        Ok(PrimeDecompressor {
+            stream, prms, header, codes,
+            bits: DynBitString::null(), cursor: 0, stream_ended: false,
+            padding: None, held_value: None, out_bytes: VecDeque::new()
+        })
+    }
+
+    pub fn header(&self) -> &PrimeStreamHeader { &self.header }
+
+    // read from the stream until there are min_bits past the cursor, or it ends
+    fn refill(&mut self, min_bits: usize) -> Result<(), std::io::Error> {
+        if self.stream_ended || self.bits.len() - self.cursor >= min_bits {
+            return Ok(());
+        }
+        // keep the bits of the cursor's byte, so the padding after the end of stream can be found
+        let keep_from = self.cursor - self.cursor % BITS_PER_BYTE;
+        self.bits = self.bits.slice(keep_from..self.bits.len()).to_bit_string();
+        self.cursor -= keep_from;
+        let mut chunk = [0u8; READ_CHUNK_BYTES];
+        while !self.stream_ended && self.bits.len() < min_bits {
+            let byte_count = match self.stream.read(&mut chunk) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                r => r?
+            };
+            self.stream_ended = byte_count == 0;
+            self.bits.append_bytes(&chunk[..byte_count]);
+        }
+        Ok(())
+    }
+
+    fn read_value(&mut self) -> Result<Option<u32>, std::io::Error> {
+        if self.padding.is_some() {
+            return Ok(None);
+        }
+        // whatever the codes, a value that runs past the bits buffered is read again with more
+        let mut read_ahead = MIN_READ_AHEAD_BITS;
+        loop {
+            self.refill(read_ahead)?;
+            match self.decode_value() {
+                Err(DecodeError::Truncated) if !self.stream_ended => { read_ahead *= 2; }
+                Err(e) => { return Err(PrimeStreamErrcode::from(e).into()); }
+                Ok(None) => {
+                    self.check_nothing_after_end()?;
+                    return Ok(None);
+                }
+                Ok(Some(hv)) => { return Ok(Some(self.value_of(hv)?)); }
+            }
+        }
+    }
+
+    // the value at the cursor, or None at the end of the stream, moving the cursor past it
+    // on error the cursor is left where it was
+    fn decode_value(&mut self) -> Result<Option<HybridValue<u32>>, DecodeError> {
+        // the end of the stream looks like a factor encoding with the reserved length code
+        let mut c = self.cursor;
+        if try_read_bit(&self.bits, &mut c)? == SELECT_FACTORS
+            && try_read_code::<u32>(self.codes.length, &self.bits, &mut c)? == reserved_length_code(END_OF_STREAM_CODE_OFFSET) {
+            let padding = try_read_bits_lsb_first(&self.bits, &mut c, 2)? as usize;
+            self.cursor = c;
+            self.padding = Some(padding);
+            return Ok(None);
+        }
+        // read in place, self.bits can hold many buffered values
+        let mut r = BitReader::at(&self.bits, self.cursor);
+        let hv = try_read_hybrid_with(&mut r, &self.codes)?;
+        self.cursor = r.cursor();
+        Ok(Some(hv))
+    }
+
+    fn value_of(&self, hv: HybridValue<u32>) -> Result<u32, PrimeStreamErrcode> {
+        match hv {
+            HybridValue::Raw(v) => Ok(v),
+            HybridValue::Factored(Factorization::Zero) => Ok(0),
+            HybridValue::Factored(Factorization::One) => Ok(1),
+            HybridValue::Factored(Factorization::Indices(ixs)) => self.product_of(&ixs, 1),
+            HybridValue::Factored(Factorization::BigCofactor { ixs, cofactor }) => self.product_of(&ixs, cofactor),
+        }
+    }
+
+    // product of the primes with indexes ixs and cofactor, checking that it is a u32
+    fn product_of(&self, ixs: &[u32], cofactor: u32) -> Result<u32, PrimeStreamErrcode> {
+        let mut v = cofactor;
+        for ix in ixs {
+            v = match self.prms.nth(*ix as usize).and_then(|p| v.checked_mul(p)) {
+                Some(product) => product,
+                None => { return Err(PrimeStreamErrcode::BadPrimeIndex); }
+            };
+        }
+        Ok(v)
+    }
+
+    // after the end of stream marker there can only be zero bits up to the end of its byte
+    fn check_nothing_after_end(&mut self) -> Result<(), std::io::Error> {
+        // if there is a byte more than the padding, this reads at least some of it
+        self.refill(BITS_PER_BYTE)?;
+        let padding_end = self.cursor.next_multiple_of(BITS_PER_BYTE);
+        if self.bits.len() > padding_end || (self.cursor..padding_end).any(|j| self.bits.get(j)) {
+            return Err(PrimeStreamErrcode::TrailingData.into());
+        }
+        Ok(())
+    }
+
+    // next value, None at end of stream
+    pub fn read_u32(&mut self) -> Result<Option<u32>, std::io::Error> {
+        if self.header.mode != PrimeStreamMode::U32Values {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        self.read_value()
+    }
+}
+
+impl<R: Read> Read for PrimeDecompressor<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if self.header.mode != PrimeStreamMode::Bytes {
+            return Err(PrimeStreamErrcode::WrongMode.into());
+        }
+        // hold on to each value until we know if it is the padded last one
+        while self.out_bytes.len() < buf.len() && self.padding.is_none() {
+            let next_value = self.read_value()?;
+            if let Some(v) = self.held_value.take() {
+                let value_bytes = v.to_le_bytes();
+                let byte_count = match self.padding {
+                    Some(padding) => 4 - padding,
+                    None => 4
+                };
+                self.out_bytes.extend(&value_bytes[..byte_count]);
+            }
+            self.held_value = next_value;
+        }
+        let byte_count = buf.len().min(self.out_bytes.len());
+        for (b, out_b) in buf.iter_mut().zip(self.out_bytes.drain(..byte_count)) {
+            *b = out_b;
+        }
+        Ok(byte_count)
+    }
+}
+
+// compress a whole array of u32 values
+pub fn compress_u32s(values: &[u32], prms: &PrimeTable) -> Result<Vec<u8>, std::io::Error> {
+    let mut compressor = PrimeCompressor::new(vec![], prms, PrimeStreamMode::U32Values)?;
+    for v in values {
+        compressor.write_u32(*v)?;
+    }
+    compressor.finish()
+}
+
+// inverse of compress_u32s()
+pub fn decompress_u32s(compressed: &[u8], prms: &PrimeTable) -> Result<Vec<u32>, std::io::Error> {
+    let mut decompressor = PrimeDecompressor::new(compressed, prms)?;
+    let mut values: Vec<u32> = vec![];
+    while let Some(v) = decompressor.read_u32()? {
+        values.push(v);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::uint_code::UintCode;
+    use rand::Rng;
+    use std::str::FromStr;
+
+    #[test]
+    pub fn test_u32_round_trip() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut values: Vec<u32> = (0..3000).collect();
+        values.extend([u32::MAX, 4294967291, 65521 * 65521, 1 << 31]);
+        let mut rng = rand::rng();
+        values.extend((0..3000).map(|_| rng.random::<u32>()));
+        let compressed = compress_u32s(&values, &prms).unwrap();
+        assert_eq!(decompress_u32s(&compressed, &prms).unwrap(), values);
+        assert_eq!(decompress_u32s(&compress_u32s(&[], &prms).unwrap(), &prms).unwrap(), vec![]);
+
+        // no value takes more than 33 bits, however badly it factors
+        for v in [0, 1, u32::MAX, 4294967291, 2 * 2147483647] {
+            let compressed = compress_u32s(&vec![v; 100], &prms).unwrap();
+            assert!(compressed.len() <= PRIME_STREAM_HEADER_LEN + (100 * 33 + 16usize).div_ceil(BITS_PER_BYTE), "{}", v);
+        }
+
+        // a table that is too small for some values still works, their big prime factors
+        // are written as cofactors
+        let small_prms = PrimeTable::up_to(100);
+        let compressed = compress_u32s(&values, &small_prms).unwrap();
+        assert_eq!(decompress_u32s(&compressed, &prms).unwrap(), values);
+
+        // other factor codes, the decompressor has to be given the same ones
+        let codes = FactorCodes {
+            length: UintCode::from_str("smallint:0,3").unwrap(),
+            exponent: UintCode::EliasGamma,
+            index_delta: UintCode::from_str("u32:0,2").unwrap(),
+        };
+        let mut compressor = PrimeCompressor::new_with_codes(vec![], &prms, PrimeStreamMode::U32Values, codes).unwrap();
+        for v in values.iter() {
+            compressor.write_u32(*v).unwrap();
+        }
+        let compressed = compressor.finish().unwrap();
+        let mut decompressor = PrimeDecompressor::new_with_codes(compressed.as_slice(), &prms, codes).unwrap();
+        for v in values.iter() {
+            assert_eq!(decompressor.read_u32().unwrap(), Some(*v));
+        }
+        assert_eq!(decompressor.read_u32().unwrap(), None);
+
+        // codes that make long factor encodings, such as unary index deltas, still round trip
+        let codes = FactorCodes { index_delta: UintCode::from_str("rice:0").unwrap(), ..DEFAULT_FACTOR_CODES };
+        let mut compressor = PrimeCompressor::new_with_codes(vec![], &prms, PrimeStreamMode::U32Values, codes).unwrap();
+        for v in values.iter() {
+            compressor.write_u32(*v).unwrap();
+        }
+        let compressed = compressor.finish().unwrap();
+        let mut decompressor = PrimeDecompressor::new_with_codes(compressed.as_slice(), &prms, codes).unwrap();
+        for v in values.iter() {
+            assert_eq!(decompressor.read_u32().unwrap(), Some(*v));
+        }
+        assert_eq!(decompressor.read_u32().unwrap(), None);
+    }
+
+    #[test]
+    pub fn test_bytes_round_trip() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let mut rng = rand::rng();
+        for len in [0, 1, 2, 3, 4, 5, 100003] {
+            let data: Vec<u8> = (0..len).map(|_| rng.random::<u8>()).collect();
+            let mut compressor = PrimeCompressor::new(vec![], &prms, PrimeStreamMode::Bytes).unwrap();
+            std::io::copy(&mut data.as_slice(), &mut compressor).unwrap();
+            let compressed = compressor.finish().unwrap();
+            let mut decompressor = PrimeDecompressor::new(compressed.as_slice(), &prms).unwrap();
+            assert_eq!(decompressor.header().mode, PrimeStreamMode::Bytes);
+            let mut decompressed: Vec<u8> = vec![];
+            decompressor.read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, data);
+        }
+    }
+
+    #[test]
+    pub fn test_bad_streams() {
+        let prms = PrimeTable::up_to(1 << 16);
+        let compressed = compress_u32s(&[10, 20, 30], &prms).unwrap();
+        let mut bad_magic = compressed.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(decompress_u32s(&bad_magic, &prms).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert!(decompress_u32s(&compressed[..8], &prms).is_err());
+        assert!(decompress_u32s(&compressed[..PRIME_STREAM_HEADER_LEN], &prms).is_err());
+        let small_prms = PrimeTable::up_to(100);
+        assert_eq!(PrimeDecompressor::new(compressed.as_slice(), &small_prms).err().unwrap().kind(), std::io::ErrorKind::InvalidInput);
+        let mut decompressor = PrimeDecompressor::new(compressed.as_slice(), &prms).unwrap();
+        assert_eq!(decompressor.read(&mut [0u8; 4]).err().unwrap().kind(), std::io::ErrorKind::InvalidInput);
+
+        // cut anywhere after the header, or random bits after it, are errors and not panics
+        let mut rng = rand::rng();
+        let values: Vec<u32> = (0..100).map(|_| rng.random::<u32>()).collect();
+        let compressed = compress_u32s(&values, &prms).unwrap();
+        for len in PRIME_STREAM_HEADER_LEN..compressed.len() {
+            assert!(decompress_u32s(&compressed[..len], &prms).is_err(), "{} bytes", len);
+        }
+        // nothing may follow the end of stream, not even zero bytes, and its padding bits are zero
+        let mut trailing = compressed.clone();
+        trailing.push(0);
+        assert_eq!(decompress_u32s(&trailing, &prms).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        // [10, 20, 30] take 66 bits with the end of stream, so the top 6 bits of the last byte are padding
+        let mut padding_set = compress_u32s(&[10, 20, 30], &prms).unwrap();
+        assert_eq!(padding_set.len(), PRIME_STREAM_HEADER_LEN + 9);
+        *padding_set.last_mut().unwrap() |= 0x80;
+        let e = decompress_u32s(&padding_set, &prms).err().unwrap();
+        assert_eq!(*e.into_inner().unwrap().downcast::<PrimeStreamErrcode>().unwrap(), PrimeStreamErrcode::TrailingData);
+        for _k in 0..200 {
+            let mut garbage = compressed[..PRIME_STREAM_HEADER_LEN].to_vec();
+            garbage.extend((0..64).map(|_| rng.random::<u8>()));
+            let _ = decompress_u32s(&garbage, &prms);
+        }
+    }
+}
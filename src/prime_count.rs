@@ -0,0 +1,305 @@
+// prime indexes without a prime table
+// every composite u32 has a prime factor below 2^16, so the 6542 primes below 2^16
+// are all that trial division needs, and they are small enough to build at compile time.
+// the index of a bigger prime p is pi(p) - 1, where pi(x) counts the primes <= x,
+// and the prime with a given index is found by inverting pi(x).
+//
+// pi(x) uses Lucy Hedgehog's variant of the Meissel-Lehmer method: for every v = x / i,
+// start with S(v) = v - 1 (count of 2..=v), then for each prime p <= sqrt(x), remove the
+// numbers whose smallest prime factor is p:
+//   S(v) -= S(v / p) - S(p - 1)     for every v >= p^2
+// when we are done S(v) = pi(v). there are only 2 * sqrt(x) distinct values of x / i,
+// so this takes O(sqrt(x)) memory and O(x^(3/4)) time, about 16M steps for x = 2^32.
+//
+// nth_prime(i) estimates the i'th prime, counts primes up to the estimate with pi(),
+// then sieves small windows up or down from there until it finds it.
+// both are much slower than looking up a PrimeTable, but need no table at all.
+//
+// a pi() near 2^32 takes about as long as sieving 2^23 integers, so every count is kept
+// in PI_CACHE, and a count within PI_CACHE_REACH of one we already have is found by
+// sieving the gap instead. the big prime factors of random values are all over the u32
+// range, so after the first few hundred pi() calls the gaps only get smaller.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use crate::primes::{FactorPrimesErrcode, GenPrimesErrcode};
+use crate::segmented_sieve::sieve_primes_in_range;
+
+// all primes below SMALL_PRIMES_UP_TO
+pub const SMALL_PRIMES_UP_TO: u32 = 1 << 16;
+pub const SMALL_PRIME_COUNT: usize = 6542;
+pub static SMALL_PRIMES: [u32; SMALL_PRIME_COUNT] = small_primes();
+
+// pi(u32::MAX), the number of primes that fit in a u32
+pub const U32_PRIME_COUNT: u32 = 203280221;
+
+// width of each window that nth_prime() sieves
+const NTH_PRIME_WINDOW: u32 = 1 << 16;
+
+// x -> pi(x) for every x cached_pi() has been asked for
+static PI_CACHE: Mutex<BTreeMap<u32, u32>> = Mutex::new(BTreeMap::new());
+// farthest cached_pi() will sieve from a cached count
+const PI_CACHE_REACH: u32 = 1 << 23;
+
+const fn small_primes() -> [u32; SMALL_PRIME_COUNT] {
+    let mut composite = [false; SMALL_PRIMES_UP_TO as usize];
+    let mut prms = [0u32; SMALL_PRIME_COUNT];
+    let mut count = 0;
+    let mut n = 2;
+    while n < SMALL_PRIMES_UP_TO as usize {
+        if !composite[n] {
+            prms[count] = n as u32;
+            count += 1;
+            let mut multiple = n * n;
+            while multiple < SMALL_PRIMES_UP_TO as usize {
+                composite[multiple] = true;
+                multiple += n;
+            }
+        }
+        n += 1;
+    }
+    assert!(count == SMALL_PRIME_COUNT);
+    prms
+}
+
+// number of primes <= x
+pub fn pi(x: u64) -> u64 {
+    if x < 2 {
+        return 0;
+    }
+    let r = x.isqrt() as usize;
+    // small_counts[v] = S(v) for v <= r, large_counts[i] = S(x / i) for i <= r
+    let mut small_counts: Vec<u64> = (0..=r as u64).map(|v| v.saturating_sub(1)).collect();
+    let mut large_counts: Vec<u64> = (0..=r as u64).map(|i| x.checked_div(i).map_or(0, |v| v - 1)).collect();
+    for p in 2..=r {
+        if small_counts[p] == small_counts[p - 1] {
+            continue;   // p is not prime
+        }
+        let primes_below_p = small_counts[p - 1];
+        let p_squared = (p * p) as u64;
+        let last_large = (r as u64).min(x / p_squared) as usize;
+        for i in 1..=last_large {
+            let d = i * p;
+            let count_at_v_div_p = if d <= r { large_counts[d] } else { small_counts[(x / d as u64) as usize] };
+            large_counts[i] -= count_at_v_div_p - primes_below_p;
+        }
+        for v in (p * p..=r).rev() {
+            small_counts[v] -= small_counts[v / p] - primes_below_p;
+        }
+    }
+    large_counts[1]
+}
+
+// primes in [lower, upper], which SMALL_PRIMES can sieve for any u32
+fn sieve_window(lower: u32, upper: u32) -> Result<Vec<u32>, GenPrimesErrcode> {
+    sieve_primes_in_range(&SMALL_PRIMES, SMALL_PRIMES_UP_TO, lower, upper)
+}
+
+// same as pi() for a u32, from the nearest count in PI_CACHE if it is close enough
+fn cached_pi(x: u32) -> Result<u32, GenPrimesErrcode> {
+    let nearest = {
+        let cache = PI_CACHE.lock().unwrap();
+        let below = cache.range(..=x).next_back();
+        let above = cache.range(x..).next();
+        [below, above].into_iter().flatten().map(|(y, count)| (*y, *count)).min_by_key(|(y, _)| y.abs_diff(x))
+    };
+    let count = match nearest {
+        Some((y, count)) if y == x => { return Ok(count); }
+        Some((y, count)) if y < x && x - y <= PI_CACHE_REACH => count + sieve_window(y + 1, x)?.len() as u32,
+        Some((y, count)) if y > x && y - x <= PI_CACHE_REACH => count - sieve_window(x + 1, y)?.len() as u32,
+        _ => pi(x as u64) as u32,
+    };
+    PI_CACHE.lock().unwrap().insert(x, count);
+    Ok(count)
+}
+
+// the i'th prime, zero-based, so nth_prime(0) is 2
+// None if that prime does not fit in a u32
+pub fn nth_prime(i: u32) -> Result<Option<u32>, GenPrimesErrcode> {
+    if (i as usize) < SMALL_PRIME_COUNT {
+        return Ok(Some(SMALL_PRIMES[i as usize]));
+    }
+    if i >= U32_PRIME_COUNT {
+        return Ok(None);
+    }
+    // Cipolla's estimate of the n'th prime, one-based
+    let n = i as f64 + 1.0;
+    let ln_n = n.ln();
+    let ln_ln_n = ln_n.ln();
+    let estimate = n * (ln_n + ln_ln_n - 1.0 + (ln_ln_n - 2.0) / ln_n);
+    let mut x = (estimate as u64).min(u32::MAX as u64) as u32;
+    let mut count_up_to_x = cached_pi(x)?;
+    if count_up_to_x > i {
+        // prime is <= x, sieve down until window holds it
+        loop {
+            let lower = x.saturating_sub(NTH_PRIME_WINDOW - 1);
+            let window = sieve_window(lower, x)?;
+            let count_below_window = count_up_to_x - window.len() as u32;
+            if count_below_window <= i {
+                return Ok(Some(window[(i - count_below_window) as usize]));
+            }
+            count_up_to_x = count_below_window;
+            x = lower - 1;
+        }
+    } else {
+        // prime is > x, sieve up until window holds it
+        loop {
+            let upper = x.saturating_add(NTH_PRIME_WINDOW);
+            let window = sieve_window(x + 1, upper)?;
+            if count_up_to_x + window.len() as u32 > i {
+                return Ok(Some(window[(i - count_up_to_x) as usize]));
+            }
+            count_up_to_x += window.len() as u32;
+            x = upper;
+        }
+    }
+}
+
+// zero-based index of prime p, None if p is not prime
+pub fn index_of_prime(p: u32) -> Result<Option<u32>, GenPrimesErrcode> {
+    if p < SMALL_PRIMES_UP_TO {
+        return Ok(SMALL_PRIMES.binary_search(&p).ok().map(|ix| ix as u32));
+    }
+    if !crate::miller_rabin::is_prime_u32(p) {
+        return Ok(None);
+    }
+    Ok(Some(cached_pi(p)? - 1))
+}
+
+// same result as primes::factor() with a table of every u32 prime, using only SMALL_PRIMES
+// returns NIsBigPrime for 0 and 1, same as factor()
+pub fn factor_without_table(n: u32) -> Result<Vec<u32>, FactorPrimesErrcode> {
+    let mut num_to_factor = n;
+    let mut factors: Vec<u32> = vec![];
+    for (ix, p) in SMALL_PRIMES.iter().enumerate() {
+        if num_to_factor < 2 || (*p as u64) * (*p as u64) > num_to_factor as u64 {
+            break;
+        }
+        while num_to_factor.is_multiple_of(*p) {
+            factors.push(ix as u32);
+            num_to_factor /= p;
+        }
+    }
+    if num_to_factor > 1 {
+        // no prime up to sqrt(num_to_factor) divides it, so it is prime
+        match index_of_prime(num_to_factor) {
+            Ok(Some(ix)) => factors.push(ix),
+            Ok(None) => { return Err(FactorPrimesErrcode::AlgorithmFailed); }
+            Err(_) => { return Err(FactorPrimesErrcode::NotEnoughPrimesToFactorIt); }
+        }
+    }
+    if factors.is_empty() {
+        return Err(FactorPrimesErrcode::NIsBigPrime);
+    }
+    Ok(factors)
+}
+
+// same as primes::indices_to_prime_factors() without a prime table
+pub fn indices_to_primes_without_table(ixs: &[u32]) -> Vec<u32> {
+    ixs.iter().map(|ix| nth_prime(*ix).unwrap().expect("prime index past the last u32 prime")).collect()
+}
+
+// bounds that need no prime table, for any size of integer
+// exact while the primes involved are below SMALL_PRIMES_UP_TO, then from
+//   p_n > n ln n (Rosser), for the one-based n'th prime
+//   pi(x) < 1.25506 x / ln x (Rosser and Schoenfeld), for x > 1
+// with ln x >= (bit length of x - 1) * ln 2, using integer math so every platform agrees
+
+fn bit_length(v: u128) -> u32 {
+    u128::BITS - v.leading_zeros()
+}
+
+// a number no bigger than the prime with zero-based index ix
+pub fn prime_at_least(ix: u128) -> u128 {
+    if ix < SMALL_PRIME_COUNT as u128 {
+        return SMALL_PRIMES[ix as usize] as u128;
+    }
+    let n = ix + 1;
+    // 693 / 1000 < ln 2
+    let rosser_bound = n.checked_mul((bit_length(n) - 1) as u128).map_or(n, |m| m / 1000 * 693);
+    // the n'th prime is also at least n + 1, and bigger than every small prime
+    rosser_bound.max(n + 1).max(SMALL_PRIMES_UP_TO as u128 + 1)
+}
+
+// a number no smaller than the zero-based index of the largest prime <= x,
+// None if there is no prime <= x
+pub fn max_prime_index_up_to(x: u128) -> Option<u128> {
+    if x < SMALL_PRIMES_UP_TO as u128 {
+        let count = SMALL_PRIMES.partition_point(|p| (*p as u128) <= x);
+        return count.checked_sub(1).map(|ix| ix as u128);
+    }
+    // 1.25506 / ln 2 < 1811 / 1000, and rounding x / d down loses less than 1
+    let d = 1000 * (bit_length(x) - 1) as u128;
+    Some((x / d + 1) * 1811)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::encode_prime::{decode_factors, encode_factors};
+    use crate::prime_table::PrimeTable;
+    use crate::primes::{factor, gen_primes_up_to};
+
+    #[test]
+    pub fn test_small_primes() {
+        assert_eq!(SMALL_PRIMES.to_vec(), gen_primes_up_to(SMALL_PRIMES_UP_TO - 1)
+            .into_iter().filter(|p| *p < SMALL_PRIMES_UP_TO).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    pub fn test_pi() {
+        let prms = gen_primes_up_to(200000);
+        for x in (0..200000u32).step_by(997).chain([0, 1, 2, 3, 4, 24, 25, 26, 48, 49, 50, 65536, 65537]) {
+            assert_eq!(pi(x as u64), prms.partition_point(|p| *p <= x) as u64, "x = {}", x);
+        }
+        assert_eq!(pi(1000000000), 50847534);
+        assert_eq!(pi(u32::MAX as u64), U32_PRIME_COUNT as u64);
+    }
+
+    #[test]
+    pub fn test_nth_prime() {
+        let prms = gen_primes_up_to(3000000);
+        for i in (0..prms.len()).step_by(7919).chain([6541, 6542, 6543, prms.len() - 1]) {
+            assert_eq!(nth_prime(i as u32), Ok(Some(prms[i])), "i = {}", i);
+            assert_eq!(index_of_prime(prms[i]), Ok(Some(i as u32)));
+        }
+        assert_eq!(index_of_prime(65537 * 3), Ok(None));
+        assert_eq!(nth_prime(U32_PRIME_COUNT - 1), Ok(Some(4294967291)));
+        assert_eq!(nth_prime(U32_PRIME_COUNT), Ok(None));
+        // counts sieved from a cached one, on either side of it, agree with pi()
+        for x in [4000000000u32, 4000000000 + 100000, 4000000000 - 200000, 4000000000 + PI_CACHE_REACH + 1000] {
+            assert_eq!(cached_pi(x), Ok(pi(x as u64) as u32), "x = {}", x);
+        }
+    }
+
+    #[test]
+    pub fn test_prime_bounds() {
+        let prms = gen_primes_up_to(3000000);
+        for ix in (0..prms.len()).step_by(997).chain([6541, 6542, 6543, prms.len() - 1]) {
+            assert!(prime_at_least(ix as u128) <= prms[ix] as u128, "ix = {}", ix);
+        }
+        assert_eq!(prime_at_least(0), 2);
+        assert_eq!(prime_at_least(6541), 65521);
+        for x in (0..3000000u32).step_by(9973).chain([0, 1, 2, 3, 65535, 65536, 65537]) {
+            let max_ix = max_prime_index_up_to(x as u128);
+            match prms.partition_point(|p| *p <= x).checked_sub(1) {
+                None => assert_eq!(max_ix, None),
+                Some(ix) => assert!(max_ix.unwrap() >= ix as u128, "x = {}", x),
+            }
+        }
+        assert_eq!(max_prime_index_up_to(1000), Some(167));
+        assert!(max_prime_index_up_to(u32::MAX as u128).unwrap() >= U32_PRIME_COUNT as u128 - 1);
+        assert!(max_prime_index_up_to(u128::MAX).is_some());
+    }
+
+    #[test]
+    pub fn test_factor_without_table() {
+        let prms = PrimeTable::up_to(1 << 20);
+        for n in (0..1 << 20).step_by(101).chain([0, 1, 2, 65536, 65537, 999983]) {
+            assert_eq!(factor_without_table(n), factor(n, &prms));
+        }
+        let ixs = factor_without_table(4294967291 - 2).unwrap();
+        let decoded: Vec<u32> = decode_factors(&encode_factors(&ixs));
+        assert_eq!(indices_to_primes_without_table(&decoded).iter().product::<u32>(), 4294967291 - 2);
+    }
+}
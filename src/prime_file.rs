@@ -0,0 +1,464 @@
+// on-disk format for a table of all prime numbers up to some upper bound
+//
+// the file starts with a fixed-size header, followed by a payload and a rank index.
+// all multi-byte fields are little-endian.
+//
+//  offset  size  field
+//       0     8  magic number "CWPRIMES"
+//       8     2  format version
+//      10     2  payload layout, see PrimeFileLayout
+//      12     4  header length in bytes (64)
+//      16     4  lower bound of covered range, always 0 for now
+//      20     4  upper bound of covered range, file contains every prime <= this
+//      24     8  number of primes in file
+//      32     8  payload length in bytes
+//      40     8  rank index length in bytes
+//      48    12  reserved, must be zero
+//      60     4  CRC-32 of header bytes 0..60, payload and rank index
+//
+// payload layouts:
+//  U32Array      - every prime as a u32, 4 bytes per prime (~800 MB for all u32 primes)
+//  Wheel30Bitmap - one byte per 30 integers, bit j of byte i is set when 30*i + WHEEL30_RESIDUES[j]
+//                  is prime (~140 MB for all u32 primes). 2, 3 and 5 are implied by the range.
+//                  the rank index holds a u32 count of bitmap primes before every
+//                  block of RANK_BLOCK_BYTES bitmap bytes, so prime counts can be looked up
+//                  without scanning the whole bitmap.
+
+use std::fmt;
+use std::io::{Read, Write};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub const PRIME_FILE_MAGIC: [u8; 8] = *b"CWPRIMES";
+pub const PRIME_FILE_VERSION: u16 = 1;
+pub const PRIME_FILE_HEADER_LEN: usize = 64;
+
+// offset of checksum in header, checksum covers everything before it
+const CHECKSUM_OFFSET: usize = 60;
+
+// the integers in [0, 30) that are coprime to 30, same as wheel::Wheel::new(30).residues()
+pub const WHEEL30_RESIDUES: [u32; 8] = [1, 7, 11, 13, 17, 19, 23, 29];
+
+// the primes that divide 30 and so never appear in a mod-30 wheel bitmap
+pub const WHEEL30_PRIMES: [u32; 3] = [2, 3, 5];
+
+// number of bitmap bytes (30 integers each) counted by each rank index entry
+pub const RANK_BLOCK_BYTES: usize = 2048;
+
+// size of chunks read from file when loading U32Array payload
+const READ_CHUNK_BYTES: usize = 1 << 20;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum PrimeFileLayout {
+    U32Array = 0,
+    Wheel30Bitmap = 1,
+}
+
+pub const DEFAULT_PRIME_FILE_LAYOUT: PrimeFileLayout = PrimeFileLayout::Wheel30Bitmap;
+
+impl PrimeFileLayout {
+    pub fn from_u32(layout_code: u32) -> Option<Self> {
+        match layout_code {
+            0 => Some(PrimeFileLayout::U32Array),
+            1 => Some(PrimeFileLayout::Wheel30Bitmap),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum PrimeFileErrcode {
+    BadMagic,            // not a prime file, or one written before the file had a header
+    UnsupportedVersion,
+    UnknownLayout,
+    BadHeader,           // header length or reserved bytes are wrong
+    Truncated,           // file ends before payload or rank index does
+    LengthMismatch,      // file has more data than header says it should
+    RangeMismatch,       // file does not cover the range the caller asked for
+    ChecksumMismatch,
+    BadPrimeData,        // payload decodes to the wrong count or an unsorted sequence
+}
+
+impl fmt::Display for PrimeFileErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid prime file: {:?}", self)
+    }
+}
+
+impl std::error::Error for PrimeFileErrcode {}
+
+impl From<PrimeFileErrcode> for std::io::Error {
+    fn from(e: PrimeFileErrcode) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct PrimeFileHeader {
+    pub version: u16,
+    pub layout: PrimeFileLayout,
+    pub lower_bound: u32,
+    pub upper_bound: u32,
+    pub prime_count: u64,
+    pub payload_len: u64,
+    pub rank_index_len: u64,
+    pub checksum: u32,
+}
+
+impl PrimeFileHeader {
+    // serialize header, checksum goes in the last 4 bytes
+    pub fn to_bytes(&self) -> [u8; PRIME_FILE_HEADER_LEN] {
+        let mut hdr = [0u8; PRIME_FILE_HEADER_LEN];
+        hdr[0..8].copy_from_slice(&PRIME_FILE_MAGIC);
+        LittleEndian::write_u16(&mut hdr[8..10], self.version);
+        LittleEndian::write_u16(&mut hdr[10..12], self.layout as u16);
+        LittleEndian::write_u32(&mut hdr[12..16], PRIME_FILE_HEADER_LEN as u32);
+        LittleEndian::write_u32(&mut hdr[16..20], self.lower_bound);
+        LittleEndian::write_u32(&mut hdr[20..24], self.upper_bound);
+        LittleEndian::write_u64(&mut hdr[24..32], self.prime_count);
+        LittleEndian::write_u64(&mut hdr[32..40], self.payload_len);
+        LittleEndian::write_u64(&mut hdr[40..48], self.rank_index_len);
+        LittleEndian::write_u32(&mut hdr[CHECKSUM_OFFSET..], self.checksum);
+        hdr
+    }
+
+    // parse and sanity-check header, does not verify checksum
+    pub fn from_bytes(hdr: &[u8]) -> Result<Self, PrimeFileErrcode> {
+        if hdr.len() < PRIME_FILE_HEADER_LEN {
+            return Err(PrimeFileErrcode::Truncated);
+        }
+        if hdr[0..8] != PRIME_FILE_MAGIC {
+            return Err(PrimeFileErrcode::BadMagic);
+        }
+        let version = LittleEndian::read_u16(&hdr[8..10]);
+        if version != PRIME_FILE_VERSION {
+            return Err(PrimeFileErrcode::UnsupportedVersion);
+        }
+        let layout = match PrimeFileLayout::from_u32(LittleEndian::read_u16(&hdr[10..12]) as u32) {
+            Some(l) => l,
+            None => { return Err(PrimeFileErrcode::UnknownLayout); }
+        };
+        if LittleEndian::read_u32(&hdr[12..16]) as usize != PRIME_FILE_HEADER_LEN
+            || hdr[48..CHECKSUM_OFFSET].iter().any(|b| *b != 0) {
+            return Err(PrimeFileErrcode::BadHeader);
+        }
+        let h = PrimeFileHeader {
+            version,
+            layout,
+            lower_bound: LittleEndian::read_u32(&hdr[16..20]),
+            upper_bound: LittleEndian::read_u32(&hdr[20..24]),
+            prime_count: LittleEndian::read_u64(&hdr[24..32]),
+            payload_len: LittleEndian::read_u64(&hdr[32..40]),
+            rank_index_len: LittleEndian::read_u64(&hdr[40..48]),
+            checksum: LittleEndian::read_u32(&hdr[CHECKSUM_OFFSET..]),
+        };
+        // payload and rank index sizes are fully determined by layout and range
+        let (expected_payload_len, expected_rank_index_len) = match layout {
+            PrimeFileLayout::U32Array => (h.prime_count.checked_mul(4).ok_or(PrimeFileErrcode::BadHeader)?, 0),
+            PrimeFileLayout::Wheel30Bitmap => {
+                let bitmap_len = wheel30_bitmap_len(h.upper_bound);
+                (bitmap_len as u64, (bitmap_len.div_ceil(RANK_BLOCK_BYTES) * 4) as u64)
+            }
+        };
+        if h.lower_bound != 0 || h.payload_len != expected_payload_len || h.rank_index_len != expected_rank_index_len {
+            return Err(PrimeFileErrcode::BadHeader);
+        }
+        Ok(h)
+    }
+}
+
+// number of bitmap bytes needed to cover [0, upper_bound]
+
+pub fn wheel30_bitmap_len(upper_bound: u32) -> usize {
+    upper_bound as usize / 30 + 1
+}
+
+// bit within a wheel-30 bitmap byte for n, or None if n is divisible by 2, 3 or 5
+
+pub fn wheel30_bit(n: u32) -> Option<u32> {
+    WHEEL30_RESIDUES.iter().position(|r| *r == n % 30).map(|j| j as u32)
+}
+
+// pack increasing list of primes into mod-30 wheel bitmap plus its rank index
+
+pub fn primes_to_wheel30_bitmap(prms: &[u32], upper_bound: u32) -> (Vec<u8>, Vec<u32>) {
+    let mut bitmap: Vec<u8> = vec![0; wheel30_bitmap_len(upper_bound)];
+    for p in prms {
+        if let Some(bit) = wheel30_bit(*p) {
+            bitmap[(*p / 30) as usize] |= 1 << bit;
+        }
+    }
+    let rank_index = wheel30_rank_index(&bitmap);
+    (bitmap, rank_index)
+}
+
+// count of primes in the bitmap before each block of RANK_BLOCK_BYTES bytes
+
+pub fn wheel30_rank_index(bitmap: &[u8]) -> Vec<u32> {
+    let mut rank_index: Vec<u32> = Vec::with_capacity(bitmap.len().div_ceil(RANK_BLOCK_BYTES));
+    let mut count_so_far: u32 = 0;
+    for block in bitmap.chunks(RANK_BLOCK_BYTES) {
+        rank_index.push(count_so_far);
+        count_so_far += block.iter().map(|b| b.count_ones()).sum::<u32>();
+    }
+    rank_index
+}
+
+// inverse of primes_to_wheel30_bitmap()
+// the last byte can have bits for integers past upper_bound, even past u32::MAX,
+// so a bitmap with any of those set is rejected
+
+pub fn wheel30_bitmap_to_primes(bitmap: &[u8], upper_bound: u32) -> Result<Vec<u32>, PrimeFileErrcode> {
+    let mut prms: Vec<u32> = WHEEL30_PRIMES.iter().copied().filter(|p| *p <= upper_bound).collect();
+    for (byte_index, byte) in bitmap.iter().enumerate() {
+        let mut bits = *byte;
+        while bits != 0 {
+            let bit = bits.trailing_zeros();
+            let p = byte_index as u64 * 30 + WHEEL30_RESIDUES[bit as usize] as u64;
+            if p > upper_bound as u64 {
+                return Err(PrimeFileErrcode::BadPrimeData);
+            }
+            prms.push(p as u32);
+            bits &= bits - 1;
+        }
+    }
+    Ok(prms)
+}
+
+// write prime table in the format described at top of file
+// prms must contain every prime up to upper_bound, in increasing order
+
+pub fn write_prime_file<W: Write>(stream: &mut W, prms: &[u32], upper_bound: u32, layout: PrimeFileLayout) -> Result<(), std::io::Error> {
+    let mut hdr = PrimeFileHeader {
+        version: PRIME_FILE_VERSION,
+        layout,
+        lower_bound: 0,
+        upper_bound,
+        prime_count: prms.len() as u64,
+        payload_len: 0,
+        rank_index_len: 0,
+        checksum: 0,
+    };
+    let mut crc = crc32fast::Hasher::new();
+    match layout {
+        PrimeFileLayout::U32Array => {
+            hdr.payload_len = prms.len() as u64 * 4;
+            crc.update(&hdr.to_bytes()[..CHECKSUM_OFFSET]);
+            for p in prms {
+                crc.update(&p.to_le_bytes());
+            }
+            hdr.checksum = crc.finalize();
+            stream.write_all(&hdr.to_bytes())?;
+            for p in prms {
+                stream.write_u32::<LittleEndian>(*p)?;
+            }
+        }
+        PrimeFileLayout::Wheel30Bitmap => {
+            let (bitmap, rank_index) = primes_to_wheel30_bitmap(prms, upper_bound);
+            let mut rank_bytes: Vec<u8> = vec![0; rank_index.len() * 4];
+            LittleEndian::write_u32_into(&rank_index, &mut rank_bytes);
+            hdr.payload_len = bitmap.len() as u64;
+            hdr.rank_index_len = rank_bytes.len() as u64;
+            crc.update(&hdr.to_bytes()[..CHECKSUM_OFFSET]);
+            crc.update(&bitmap);
+            crc.update(&rank_bytes);
+            hdr.checksum = crc.finalize();
+            stream.write_all(&hdr.to_bytes())?;
+            stream.write_all(&bitmap)?;
+            stream.write_all(&rank_bytes)?;
+        }
+    }
+    Ok(())
+}
+
+// read_exact that reports a short read as a truncated prime file
+
+fn read_exact_or_truncated<R: Read>(stream: &mut R, buf: &mut [u8]) -> Result<(), std::io::Error> {
+    match stream.read_exact(buf) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(PrimeFileErrcode::Truncated.into()),
+        r => r
+    }
+}
+
+// read and validate prime table written by write_prime_file()
+// if expected_upper_bound is given, the file must cover exactly that range
+
+pub fn read_prime_file<R: Read>(stream: &mut R, expected_upper_bound: Option<u32>) -> Result<(PrimeFileHeader, Vec<u32>), std::io::Error> {
+    let mut hdr_bytes = [0u8; PRIME_FILE_HEADER_LEN];
+    read_exact_or_truncated(stream, &mut hdr_bytes)?;
+    let hdr = PrimeFileHeader::from_bytes(&hdr_bytes)?;
+    if let Some(upper_bound) = expected_upper_bound {
+        if hdr.upper_bound != upper_bound {
+            return Err(PrimeFileErrcode::RangeMismatch.into());
+        }
+    }
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&hdr_bytes[..CHECKSUM_OFFSET]);
+
+    // the bitmap is only decoded once the checksum says it is what was written
+    let mut wheel30_payload: Option<(Vec<u8>, Vec<u8>)> = None;
+    let mut prms: Vec<u32> = match hdr.layout {
+        PrimeFileLayout::U32Array => {
+            // grow array as data arrives so a corrupt prime count can't make us allocate it all up front
+            let mut prms: Vec<u32> = vec![];
+            let mut chunk: Vec<u8> = vec![0; READ_CHUNK_BYTES];
+            let mut u32s_left = hdr.prime_count as usize;
+            while u32s_left > 0 {
+                let u32s_in_chunk = u32s_left.min(READ_CHUNK_BYTES / 4);
+                let chunk_bytes = &mut chunk[..u32s_in_chunk * 4];
+                read_exact_or_truncated(stream, chunk_bytes)?;
+                crc.update(chunk_bytes);
+                let old_len = prms.len();
+                prms.resize(old_len + u32s_in_chunk, 0);
+                LittleEndian::read_u32_into(chunk_bytes, &mut prms[old_len..]);
+                u32s_left -= u32s_in_chunk;
+            }
+            prms
+        }
+        PrimeFileLayout::Wheel30Bitmap => {
+            let mut bitmap: Vec<u8> = vec![0; hdr.payload_len as usize];
+            read_exact_or_truncated(stream, &mut bitmap)?;
+            let mut rank_bytes: Vec<u8> = vec![0; hdr.rank_index_len as usize];
+            read_exact_or_truncated(stream, &mut rank_bytes)?;
+            crc.update(&bitmap);
+            crc.update(&rank_bytes);
+            wheel30_payload = Some((bitmap, rank_bytes));
+            vec![]
+        }
+    };
+    if stream.read_u8().is_ok() {
+        return Err(PrimeFileErrcode::LengthMismatch.into());
+    }
+    if crc.finalize() != hdr.checksum {
+        return Err(PrimeFileErrcode::ChecksumMismatch.into());
+    }
+    // the rank index is only stored for bitmap layouts
+    let mut rank_index_matches = true;
+    if let Some((bitmap, rank_bytes)) = wheel30_payload {
+        let mut stored_rank_index: Vec<u32> = vec![0; rank_bytes.len() / 4];
+        LittleEndian::read_u32_into(&rank_bytes, &mut stored_rank_index);
+        rank_index_matches = stored_rank_index == wheel30_rank_index(&bitmap);
+        prms = wheel30_bitmap_to_primes(&bitmap, hdr.upper_bound)?;
+    }
+    if !rank_index_matches
+        || prms.len() as u64 != hdr.prime_count
+        || prms.windows(2).any(|pair| pair[0] >= pair[1])
+        || prms.last().is_some_and(|p| *p > hdr.upper_bound) {
+        return Err(PrimeFileErrcode::BadPrimeData.into());
+    }
+    Ok((hdr, prms))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::primes::gen_primes_up_to;
+
+    fn write_to_vec(prms: &[u32], upper_bound: u32, layout: PrimeFileLayout) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![];
+        write_prime_file(&mut buf, prms, upper_bound, layout).unwrap();
+        buf
+    }
+
+    fn read_errcode(buf: &[u8], expected_upper_bound: Option<u32>) -> PrimeFileErrcode {
+        let e = read_prime_file(&mut Cursor::new(buf), expected_upper_bound).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        *e.into_inner().unwrap().downcast::<PrimeFileErrcode>().unwrap()
+    }
+
+    #[test]
+    pub fn test_wheel30_residues() {
+        assert_eq!(crate::wheel::Wheel::new(30).unwrap().residues(), WHEEL30_RESIDUES);
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        let upper_bound = 200000;
+        let prms = gen_primes_up_to(upper_bound);
+        for layout in [PrimeFileLayout::U32Array, PrimeFileLayout::Wheel30Bitmap] {
+            let buf = write_to_vec(&prms, upper_bound, layout);
+            let (hdr, prms_read) = read_prime_file(&mut Cursor::new(&buf), Some(upper_bound)).unwrap();
+            assert_eq!(prms_read, prms);
+            assert_eq!(hdr.layout, layout);
+            assert_eq!(hdr.prime_count, prms.len() as u64);
+            assert_eq!(buf.len() as u64, PRIME_FILE_HEADER_LEN as u64 + hdr.payload_len + hdr.rank_index_len);
+        }
+        // the bitmap is much smaller than the u32 array
+        let bitmap_buf = write_to_vec(&prms, upper_bound, PrimeFileLayout::Wheel30Bitmap);
+        assert!(bitmap_buf.len() < PRIME_FILE_HEADER_LEN + wheel30_bitmap_len(upper_bound) + 64);
+    }
+
+    #[test]
+    pub fn test_small_upper_bounds() {
+        for upper_bound in [2, 3, 4, 5, 6, 7, 29, 30, 31] {
+            let prms: Vec<u32> = gen_primes_up_to(31).into_iter().filter(|p| *p <= upper_bound).collect();
+            let buf = write_to_vec(&prms, upper_bound, PrimeFileLayout::Wheel30Bitmap);
+            let (_, prms_read) = read_prime_file(&mut Cursor::new(&buf), None).unwrap();
+            assert_eq!(prms_read, prms);
+        }
+    }
+
+    #[test]
+    pub fn test_rejects_bad_files() {
+        let prms = gen_primes_up_to(10000);
+        for layout in [PrimeFileLayout::U32Array, PrimeFileLayout::Wheel30Bitmap] {
+            let buf = write_to_vec(&prms, 10000, layout);
+
+            assert_eq!(read_errcode(&buf, Some(10001)), PrimeFileErrcode::RangeMismatch);
+            assert_eq!(read_errcode(&buf[..buf.len() - 1], None), PrimeFileErrcode::Truncated);
+            assert_eq!(read_errcode(&buf[..10], None), PrimeFileErrcode::Truncated);
+
+            let mut too_long = buf.clone();
+            too_long.push(0);
+            assert_eq!(read_errcode(&too_long, None), PrimeFileErrcode::LengthMismatch);
+
+            let mut corrupt = buf.clone();
+            corrupt[PRIME_FILE_HEADER_LEN + 7] ^= 0x10;
+            assert_eq!(read_errcode(&corrupt, None), PrimeFileErrcode::ChecksumMismatch);
+
+            let mut bad_magic = buf.clone();
+            bad_magic[0] = b'X';
+            assert_eq!(read_errcode(&bad_magic, None), PrimeFileErrcode::BadMagic);
+
+            let mut bad_version = buf.clone();
+            bad_version[8] = 99;
+            assert_eq!(read_errcode(&bad_version, None), PrimeFileErrcode::UnsupportedVersion);
+        }
+
+        // bitmap bits past upper_bound are refused, but only once the checksum matches
+        let upper_bound = 100;
+        let prms: Vec<u32> = prms.iter().copied().filter(|p| *p <= upper_bound).collect();
+        let mut past_end = write_to_vec(&prms, upper_bound, PrimeFileLayout::Wheel30Bitmap);
+        let last_byte = PRIME_FILE_HEADER_LEN + wheel30_bitmap_len(upper_bound) - 1;
+        past_end[last_byte] |= 0x80;
+        assert_eq!(read_errcode(&past_end, None), PrimeFileErrcode::ChecksumMismatch);
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&past_end[..CHECKSUM_OFFSET]);
+        crc.update(&past_end[PRIME_FILE_HEADER_LEN..]);
+        let checksum = crc.finalize();
+        LittleEndian::write_u32(&mut past_end[CHECKSUM_OFFSET..PRIME_FILE_HEADER_LEN], checksum);
+        assert_eq!(read_errcode(&past_end, None), PrimeFileErrcode::BadPrimeData);
+        // the same bit in the last byte of a bitmap up to u32::MAX is past u32::MAX
+        let mut bitmap = vec![0u8; wheel30_bitmap_len(u32::MAX)];
+        *bitmap.last_mut().unwrap() = 0xff;
+        assert_eq!(wheel30_bitmap_to_primes(&bitmap, u32::MAX), Err(PrimeFileErrcode::BadPrimeData));
+
+        // a prime count whose payload length wraps around to the real one is refused
+        let mut huge_count = write_to_vec(&prms, 10000, PrimeFileLayout::U32Array);
+        LittleEndian::write_u64(&mut huge_count[24..32], (1 << 62) + prms.len() as u64);
+        assert_eq!(read_errcode(&huge_count, None), PrimeFileErrcode::BadHeader);
+
+        // the old headerless format is refused instead of being read as garbage
+        let mut old_format: Vec<u8> = vec![];
+        for p in &prms {
+            old_format.extend_from_slice(&p.to_be_bytes());
+        }
+        assert_eq!(read_errcode(&old_format, None), PrimeFileErrcode::BadMagic);
+    }
+}
@@ -0,0 +1,301 @@
+// rank/select index over a mod-30 wheel bitmap of primes (see prime_file.rs)
+// this lets us count primes up to x (pi(x)) and find the i'th prime
+// without keeping every prime in memory as a u32.
+//
+// rank is done in two levels:
+//  - block ranks, one u32 per RANK_BLOCK_BYTES bitmap bytes, stored in the prime file
+//  - sub-block ranks, one u16 per SUB_BLOCK_BYTES bitmap bytes, counted from the start of
+//    the block, computed the first time the block is used, so opening a mapped file
+//    does not read every page of the bitmap
+// so a rank costs two array lookups plus popcount of less than SUB_BLOCK_BYTES bytes.
+// select does a binary search over the same two levels.
+
+use std::ops::Range;
+use std::sync::OnceLock;
+use memmap2::Mmap;
+use crate::prime_file::{primes_to_wheel30_bitmap, wheel30_bit, wheel30_bitmap_len, PrimeFileErrcode, RANK_BLOCK_BYTES, WHEEL30_PRIMES, WHEEL30_RESIDUES};
+
+pub const SUB_BLOCK_BYTES: usize = 64;
+const SUB_BLOCKS_PER_BLOCK: usize = RANK_BLOCK_BYTES / SUB_BLOCK_BYTES;
+
+// bits in a bitmap byte for residues <= r, for each r in [0, 30)
+const fn residue_masks() -> [u8; 30] {
+    let mut masks = [0u8; 30];
+    let mut r = 0;
+    while r < 30 {
+        let mut j = 0;
+        while j < WHEEL30_RESIDUES.len() {
+            if WHEEL30_RESIDUES[j] <= r as u32 {
+                masks[r] |= 1 << j;
+            }
+            j += 1;
+        }
+        r += 1;
+    }
+    masks
+}
+const RESIDUE_MASKS: [u8; 30] = residue_masks();
+
+enum BitmapStorage {
+    Owned(Vec<u8>),
+    Mapped { map: Mmap, bitmap_range: Range<usize> },  // bitmap is a slice of a prime file
+}
+
+pub struct PrimeBitmapIndex {
+    bitmap: BitmapStorage,
+    upper_bound: u32,
+    block_ranks: Vec<u32>,
+    sub_block_ranks: Vec<OnceLock<Vec<u16>>>,   // one per block
+    prime_count: u32,      // including 2, 3 and 5
+}
+
+fn popcount(bytes: &[u8]) -> u32 {
+    let mut chunks = bytes.chunks_exact(8);
+    let mut count: u32 = (&mut chunks).map(|c| u64::from_le_bytes(c.try_into().unwrap()).count_ones()).sum();
+    count += chunks.remainder().iter().map(|b| b.count_ones()).sum::<u32>();
+    count
+}
+
+impl PrimeBitmapIndex {
+    // build index for increasing array of every prime up to upper_bound
+    pub fn from_primes(prms: &[u32], upper_bound: u32) -> Self {
+        let (bitmap, block_ranks) = primes_to_wheel30_bitmap(prms, upper_bound);
+        Self::new(BitmapStorage::Owned(bitmap), upper_bound, block_ranks).unwrap()
+    }
+
+    // use bitmap and block ranks of a memory-mapped prime file in place
+    // block ranks are trusted as long as they start at 0 and no block holds more primes
+    // than it has bits, PrimeTable::verify() checks them against the bitmap
+    pub fn from_mapped_file(map: Mmap, bitmap_range: Range<usize>, rank_range: Range<usize>, upper_bound: u32)
+        -> Result<Self, PrimeFileErrcode> {
+        let block_ranks: Vec<u32> = map[rank_range].chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        Self::new(BitmapStorage::Mapped { map, bitmap_range }, upper_bound, block_ranks)
+    }
+
+    fn new(bitmap: BitmapStorage, upper_bound: u32, block_ranks: Vec<u32>) -> Result<Self, PrimeFileErrcode> {
+        const BLOCK_BITS: u32 = (RANK_BLOCK_BYTES * 8) as u32;
+        if block_ranks.first().is_some_and(|r| *r != 0)
+            || block_ranks.windows(2).any(|pair| pair[0] > pair[1] || pair[1] - pair[0] > BLOCK_BITS) {
+            return Err(PrimeFileErrcode::BadPrimeData);
+        }
+        let sub_block_ranks = block_ranks.iter().map(|_| OnceLock::new()).collect();
+        let mut idx = PrimeBitmapIndex { bitmap, upper_bound, block_ranks, sub_block_ranks, prime_count: 0 };
+        let bitmap = idx.bitmap_bytes();
+        if bitmap.len() != wheel30_bitmap_len(upper_bound) || bitmap.len().div_ceil(RANK_BLOCK_BYTES) != idx.block_ranks.len() {
+            return Err(PrimeFileErrcode::BadPrimeData);
+        }
+        // the last byte has bits for integers past upper_bound, which must not be set
+        if bitmap[bitmap.len() - 1] & !RESIDUE_MASKS[(upper_bound % 30) as usize] != 0 {
+            return Err(PrimeFileErrcode::BadPrimeData);
+        }
+        let wheel_prime_count = WHEEL30_PRIMES.iter().filter(|p| **p <= upper_bound).count() as u32;
+        let bitmap_prime_count = match (idx.block_ranks.last(), bitmap.chunks(RANK_BLOCK_BYTES).last()) {
+            (Some(r), Some(last_block)) => *r + popcount(last_block),
+            _ => 0
+        };
+        idx.prime_count = wheel_prime_count + bitmap_prime_count;
+        Ok(idx)
+    }
+
+    // count of primes before each sub-block of a block, counted from the start of the block
+    fn sub_block_ranks(&self, block: usize) -> &[u16] {
+        self.sub_block_ranks[block].get_or_init(|| {
+            let bitmap = self.bitmap_bytes();
+            let block_bytes = &bitmap[block * RANK_BLOCK_BYTES..bitmap.len().min((block + 1) * RANK_BLOCK_BYTES)];
+            let mut ranks: Vec<u16> = Vec::with_capacity(SUB_BLOCKS_PER_BLOCK);
+            let mut count_in_block: u32 = 0;
+            for sub_block in block_bytes.chunks(SUB_BLOCK_BYTES) {
+                ranks.push(count_in_block as u16);
+                count_in_block += popcount(sub_block);
+            }
+            ranks
+        })
+    }
+
+    pub fn bitmap_bytes(&self) -> &[u8] {
+        match &self.bitmap {
+            BitmapStorage::Owned(v) => v.as_slice(),
+            BitmapStorage::Mapped { map, bitmap_range } => &map[bitmap_range.clone()],
+        }
+    }
+
+    // whole prime file if the bitmap was memory-mapped from one
+    pub fn mapped_file(&self) -> Option<&[u8]> {
+        match &self.bitmap {
+            BitmapStorage::Owned(_) => None,
+            BitmapStorage::Mapped { map, .. } => Some(&map[..]),
+        }
+    }
+
+    pub fn upper_bound(&self) -> u32 { self.upper_bound }
+
+    pub fn prime_count(&self) -> u32 { self.prime_count }
+
+    // number of primes <= x, x must be <= upper_bound
+    pub fn pi(&self, x: u32) -> u32 {
+        assert!(x <= self.upper_bound);
+        let wheel_prime_count = WHEEL30_PRIMES.iter().filter(|p| **p <= x).count() as u32;
+        let bitmap = self.bitmap_bytes();
+        let byte_index = (x / 30) as usize;
+        let sub_block_start = byte_index - byte_index % SUB_BLOCK_BYTES;
+        let block = byte_index / RANK_BLOCK_BYTES;
+        wheel_prime_count
+            + self.block_ranks[block]
+            + self.sub_block_ranks(block)[byte_index % RANK_BLOCK_BYTES / SUB_BLOCK_BYTES] as u32
+            + popcount(&bitmap[sub_block_start..byte_index])
+            + (bitmap[byte_index] & RESIDUE_MASKS[(x % 30) as usize]).count_ones()
+    }
+
+    pub fn is_prime(&self, n: u32) -> bool {
+        assert!(n <= self.upper_bound);
+        match wheel30_bit(n) {
+            Some(bit) => self.bitmap_bytes()[(n / 30) as usize] & (1 << bit) != 0,
+            None => WHEEL30_PRIMES.contains(&n),
+        }
+    }
+
+    // zero-based index of prime p, same as position of p in an array of primes
+    pub fn index_of(&self, p: u32) -> Option<u32> {
+        if p > self.upper_bound || !self.is_prime(p) {
+            None
+        } else {
+            Some(self.pi(p) - 1)
+        }
+    }
+
+    // the i'th prime, zero-based
+    // None past the last prime, or if the block ranks from the file do not match the bitmap
+    pub fn nth(&self, i: u32) -> Option<u32> {
+        if i >= self.prime_count {
+            return None;
+        }
+        let wheel_prime_count = WHEEL30_PRIMES.iter().filter(|p| **p <= self.upper_bound).count() as u32;
+        if i < wheel_prime_count {
+            return Some(WHEEL30_PRIMES[i as usize]);
+        }
+        // k'th set bit in bitmap
+        let k = i - wheel_prime_count;
+        let block = self.block_ranks.partition_point(|r| *r <= k) - 1;
+        let k_in_block = k - self.block_ranks[block];
+        let sub_block_ranks = self.sub_block_ranks(block);
+        let sub_block = sub_block_ranks.partition_point(|r| *r as u32 <= k_in_block) - 1;
+        let mut k_left = k_in_block - sub_block_ranks[sub_block] as u32;
+        let bitmap = self.bitmap_bytes();
+        let scan_start = block * RANK_BLOCK_BYTES + sub_block * SUB_BLOCK_BYTES;
+        let block_end = bitmap.len().min((block + 1) * RANK_BLOCK_BYTES);
+        for (byte_index, byte) in bitmap[scan_start..block_end].iter().enumerate().map(|(j, b)| (scan_start + j, *b)) {
+            let mut bits = byte;
+            let ones = bits.count_ones();
+            if k_left < ones {
+                for _ in 0..k_left {
+                    bits &= bits - 1;
+                }
+                let residue = WHEEL30_RESIDUES[bits.trailing_zeros() as usize];
+                return u32::try_from(byte_index as u64 * 30 + residue as u64).ok();
+            }
+            k_left -= ones;
+        }
+        None
+    }
+
+    // all primes in increasing order
+    pub fn iter(&self) -> PrimeBitmapIter<'_> {
+        PrimeBitmapIter { idx: self, wheel_prime_index: 0, byte_index: 0, bits: 0 }
+    }
+}
+
+pub struct PrimeBitmapIter<'a> {
+    idx: &'a PrimeBitmapIndex,
+    wheel_prime_index: usize,
+    byte_index: usize,   // next bitmap byte to load into bits
+    bits: u8,            // bits of current byte not yet returned
+}
+
+impl Iterator for PrimeBitmapIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.wheel_prime_index < WHEEL30_PRIMES.len() {
+            let p = WHEEL30_PRIMES[self.wheel_prime_index];
+            self.wheel_prime_index += 1;
+            if p <= self.idx.upper_bound {
+                return Some(p);
+            }
+            self.wheel_prime_index = WHEEL30_PRIMES.len();
+        }
+        let bitmap = self.idx.bitmap_bytes();
+        while self.bits == 0 {
+            if self.byte_index == bitmap.len() {
+                return None;
+            }
+            self.bits = bitmap[self.byte_index];
+            self.byte_index += 1;
+        }
+        let bit = self.bits.trailing_zeros();
+        self.bits &= self.bits - 1;
+        u32::try_from((self.byte_index as u64 - 1) * 30 + WHEEL30_RESIDUES[bit as usize] as u64).ok()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::primes::gen_primes_up_to;
+
+    #[test]
+    pub fn test_pi_and_nth() {
+        for upper_bound in [2, 4, 5, 6, 7, 100, 61439, 61440, 200003] {
+            let prms: Vec<u32> = gen_primes_up_to(upper_bound).into_iter().filter(|p| *p <= upper_bound).collect();
+            let idx = PrimeBitmapIndex::from_primes(&prms, upper_bound);
+            assert_eq!(idx.prime_count(), prms.len() as u32);
+            let mut count: u32 = 0;
+            for x in 0..=upper_bound {
+                if prms.binary_search(&x).is_ok() {
+                    count += 1;
+                    assert!(idx.is_prime(x));
+                    assert_eq!(idx.index_of(x), Some(count - 1));
+                } else {
+                    assert!(!idx.is_prime(x));
+                    assert_eq!(idx.index_of(x), None);
+                }
+                assert_eq!(idx.pi(x), count);
+            }
+            for (i, p) in prms.iter().enumerate() {
+                assert_eq!(idx.nth(i as u32), Some(*p));
+            }
+            assert_eq!(idx.nth(prms.len() as u32), None);
+            assert_eq!(idx.iter().collect::<Vec<u32>>(), prms);
+        }
+    }
+
+    #[test]
+    pub fn test_bad_block_ranks() {
+        let upper_bound = 200003;
+        let prms = gen_primes_up_to(upper_bound);
+        let (bitmap, block_ranks) = primes_to_wheel30_bitmap(&prms, upper_bound);
+        assert_eq!(block_ranks.len(), 4);
+        let open = |ranks: Vec<u32>| PrimeBitmapIndex::new(BitmapStorage::Owned(bitmap.clone()), upper_bound, ranks);
+
+        // more primes in a block than it has bits
+        assert!(open(vec![0, 0, 0, 100000]).is_err());
+        // bits set for integers past upper_bound
+        let mut past_end = bitmap.clone();
+        *past_end.last_mut().unwrap() |= 0x80;
+        assert!(PrimeBitmapIndex::new(BitmapStorage::Owned(past_end), upper_bound, block_ranks.clone()).is_err());
+        // ranks that are possible but wrong give wrong answers or None, never a panic
+        let too_low = vec![0, block_ranks[1], block_ranks[2] - 1000, block_ranks[3]];
+        let idx = open(too_low).unwrap();
+        assert!((0..idx.prime_count()).any(|i| idx.nth(i).is_none()));
+    }
+
+    #[test]
+    pub fn test_residue_masks() {
+        assert_eq!(RESIDUE_MASKS[0], 0);
+        assert_eq!(RESIDUE_MASKS[1], 1);
+        assert_eq!(RESIDUE_MASKS[6], 1);
+        assert_eq!(RESIDUE_MASKS[7], 3);
+        assert_eq!(RESIDUE_MASKS[29], 0xff);
+    }
+}
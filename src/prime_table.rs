@@ -0,0 +1,400 @@
+// table of all prime numbers from 2 up to some upper bound
+// every function that needs an array of primes takes a PrimeTable instead of &[u32],
+// so the array is always sorted, starts at 2, has no gaps, and we know how far it
+// goes. a table covering [2, upper_bound] can factor any n <= upper_bound^2.
+//
+// when loaded from a file that uses the U32Array layout, the table memory-maps the file
+// and looks up primes directly in the mapped pages, so nothing has to be read
+// up front. a file that uses the Wheel30Bitmap layout is memory-mapped too, and
+// primes are found with the rank/select index in prime_index.rs, so the full u32
+// array never exists in memory. use nth(), index_of(), pi() and iter() to get at
+// primes, these work the same for every kind of table.
+
+use std::fmt;
+use std::fs::File;
+use std::ops::Range;
+use memmap2::Mmap;
+use crate::prime_file::{read_prime_file, PrimeFileErrcode, PrimeFileHeader, PrimeFileLayout, PRIME_FILE_HEADER_LEN};
+use crate::prime_index::{PrimeBitmapIndex, PrimeBitmapIter};
+use crate::primes::{gen_primes_up_to, parallel_calc_primes, prime_data_pathname, read_primes, PrimeIndexError};
+
+// every n < 2^32 that is not prime has a prime factor below this
+pub const TRIAL_DIVISION_LIMIT: u32 = 1 << 16;
+
+enum PrimeStorage {
+    Owned(Vec<u32>),
+    Mapped { map: Mmap, prime_count: usize },  // primes start right after file header
+    Bitmap { index: PrimeBitmapIndex, trial_primes: Vec<u32> },
+}
+
+pub struct PrimeTable {
+    storage: PrimeStorage,
+    upper_bound: u32,   // table contains every prime <= upper_bound
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum PrimeTableErrcode {
+    DoesNotStartAt2,
+    NotIncreasing,
+    PastUpperBound,   // table has a prime bigger than the upper bound it claims to cover
+}
+
+impl fmt::Display for PrimeTableErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid prime table: {:?}", self)
+    }
+}
+
+impl std::error::Error for PrimeTableErrcode {}
+
+impl From<PrimeTableErrcode> for std::io::Error {
+    fn from(e: PrimeTableErrcode) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl PrimeTable {
+    // wrap an array of primes that is already in memory
+    // prms must hold every prime up to upper_bound, in increasing order
+    // we check everything about that except for missing primes
+    pub fn from_vec(prms: Vec<u32>, upper_bound: u32) -> Result<Self, PrimeTableErrcode> {
+        if prms.first() != Some(&2) {
+            return Err(PrimeTableErrcode::DoesNotStartAt2);
+        } else if prms.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(PrimeTableErrcode::NotIncreasing);
+        } else if *prms.last().unwrap() > upper_bound {
+            return Err(PrimeTableErrcode::PastUpperBound);
+        }
+        Ok(PrimeTable { storage: PrimeStorage::Owned(prms), upper_bound })
+    }
+
+    // generate every prime up to n in this thread
+    pub fn up_to(n: u32) -> Self {
+        let prms = gen_primes_up_to(n);
+        // gen_primes_up_to always returns at least its starter primes
+        let upper_bound = n.max(*prms.last().unwrap());
+        Self::from_vec(prms, upper_bound).unwrap()
+    }
+
+    // generate every prime up to n using nthreads threads
+    pub fn parallel_up_to(nthreads: usize, n: u32) -> Self {
+        let prms = parallel_calc_primes(nthreads, n);
+        let upper_bound = n.max(*prms.last().unwrap());
+        Self::from_vec(prms, upper_bound).unwrap()
+    }
+
+    // read entire file written by write_primes(prms, upper_bound) into memory
+    pub fn read(upper_bound: u32) -> Result<Self, std::io::Error> {
+        let prms = read_primes(upper_bound)?;
+        Ok(Self::from_vec(prms, upper_bound)?)
+    }
+
+    // open the file that write_primes(prms, upper_bound) created in PRIME_DIR
+    pub fn open(upper_bound: u32) -> Result<Self, std::io::Error> {
+        Self::open_path(prime_data_pathname(upper_bound).as_str(), Some(upper_bound))
+    }
+
+    // header and file length are checked here, but the checksum is not,
+    // since that would mean reading every page of the file. use verify() for that.
+    pub fn open_path(pathname: &str, expected_upper_bound: Option<u32>) -> Result<Self, std::io::Error> {
+        let file_handle = File::open(pathname)?;
+        // SAFETY: prime files are written once and never modified in place
+        let map = unsafe { Mmap::map(&file_handle)? };
+        let hdr = PrimeFileHeader::from_bytes(&map)?;
+        if let Some(upper_bound) = expected_upper_bound {
+            if hdr.upper_bound != upper_bound {
+                return Err(PrimeFileErrcode::RangeMismatch.into());
+            }
+        }
+        let file_len = (PRIME_FILE_HEADER_LEN as u64) + hdr.payload_len + hdr.rank_index_len;
+        if (map.len() as u64) < file_len {
+            return Err(PrimeFileErrcode::Truncated.into());
+        } else if (map.len() as u64) > file_len {
+            return Err(PrimeFileErrcode::LengthMismatch.into());
+        }
+        match hdr.layout {
+            // file stores little-endian u32, so we can only use it in place on a little-endian CPU
+            PrimeFileLayout::U32Array if cfg!(target_endian = "little") => {
+                let t = PrimeTable {
+                    storage: PrimeStorage::Mapped { map, prime_count: hdr.prime_count as usize },
+                    upper_bound: hdr.upper_bound,
+                };
+                // mmap is page-aligned and header is a multiple of 4 bytes,
+                // so the payload is always aligned for u32
+                assert_eq!(t.len(), hdr.prime_count as usize);
+                Ok(t)
+            }
+            PrimeFileLayout::Wheel30Bitmap => {
+                let bitmap_start = PRIME_FILE_HEADER_LEN;
+                let rank_start = bitmap_start + hdr.payload_len as usize;
+                let rank_end = rank_start + hdr.rank_index_len as usize;
+                let index = PrimeBitmapIndex::from_mapped_file(map, bitmap_start..rank_start, rank_start..rank_end, hdr.upper_bound)?;
+                if index.prime_count() as u64 != hdr.prime_count {
+                    return Err(PrimeFileErrcode::BadPrimeData.into());
+                }
+                Ok(Self::from_bitmap_index(index))
+            }
+            PrimeFileLayout::U32Array => {
+                drop(map);
+                let mut stream = std::io::BufReader::new(File::open(pathname)?);
+                let (hdr, prms) = read_prime_file(&mut stream, expected_upper_bound)?;
+                Ok(Self::from_vec(prms, hdr.upper_bound)?)
+            }
+        }
+    }
+
+    // use a rank/select index over a prime bitmap as the table,
+    // the primes small enough for trial division are kept as an array as well
+    pub fn from_bitmap_index(index: PrimeBitmapIndex) -> Self {
+        let upper_bound = index.upper_bound();
+        let trial_primes: Vec<u32> = index.iter().take_while(|p| *p <= TRIAL_DIVISION_LIMIT).collect();
+        PrimeTable { storage: PrimeStorage::Bitmap { index, trial_primes }, upper_bound }
+    }
+
+    // check the file checksum and contents, reading the entire file
+    pub fn verify(&self) -> Result<(), std::io::Error> {
+        let mapped_file = match &self.storage {
+            PrimeStorage::Owned(_) => None,
+            PrimeStorage::Mapped { map, .. } => Some(&map[..]),
+            PrimeStorage::Bitmap { index, .. } => index.mapped_file(),
+        };
+        if let Some(mut bytes) = mapped_file {
+            read_prime_file(&mut bytes, Some(self.upper_bound))?;
+        }
+        Ok(())
+    }
+
+    // true if primes are looked up in the memory-mapped file
+    pub fn is_mapped(&self) -> bool {
+        match &self.storage {
+            PrimeStorage::Owned(_) => false,
+            PrimeStorage::Mapped { .. } => true,
+            PrimeStorage::Bitmap { index, .. } => index.mapped_file().is_some(),
+        }
+    }
+
+    // true if primes are stored as a bitmap instead of an array
+    pub fn is_bitmap(&self) -> bool {
+        matches!(self.storage, PrimeStorage::Bitmap { .. })
+    }
+
+    // every prime <= upper_bound is in the table
+    pub fn upper_bound(&self) -> u32 { self.upper_bound }
+
+    // largest n that this table has enough primes to factor
+    pub fn factorable_up_to(&self) -> u64 {
+        self.upper_bound as u64 * self.upper_bound as u64
+    }
+
+    // number of primes in the table
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            PrimeStorage::Bitmap { index, .. } => index.prime_count() as usize,
+            _ => self.as_slice().unwrap().len(),
+        }
+    }
+
+    // never true for a table that was constructed successfully
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn last(&self) -> u32 {
+        self.nth(self.len() - 1).unwrap()
+    }
+
+    // primes as an array, None if table is a bitmap
+    pub fn as_slice(&self) -> Option<&[u32]> {
+        match &self.storage {
+            PrimeStorage::Owned(prms) => Some(prms.as_slice()),
+            PrimeStorage::Mapped { map, prime_count } => {
+                let payload = &map[PRIME_FILE_HEADER_LEN..PRIME_FILE_HEADER_LEN + prime_count * 4];
+                // SAFETY: every 4-byte pattern is a valid u32
+                let (prefix, prms, suffix) = unsafe { payload.align_to::<u32>() };
+                assert!(prefix.is_empty() && suffix.is_empty());
+                Some(prms)
+            }
+            PrimeStorage::Bitmap { .. } => None,
+        }
+    }
+
+    // every prime <= min(upper_bound, TRIAL_DIVISION_LIMIT), in increasing order
+    // these are the first primes in the table, so position in this array is the prime's index
+    pub fn trial_primes(&self) -> &[u32] {
+        match &self.storage {
+            PrimeStorage::Bitmap { trial_primes, .. } => trial_primes.as_slice(),
+            _ => {
+                let prms = self.as_slice().unwrap();
+                &prms[..prms.partition_point(|p| *p <= TRIAL_DIVISION_LIMIT)]
+            }
+        }
+    }
+
+    // the i'th prime, zero-based, so nth(0) is 2
+    pub fn nth(&self, i: usize) -> Option<u32> {
+        match &self.storage {
+            PrimeStorage::Bitmap { index, .. } => u32::try_from(i).ok().and_then(|i| index.nth(i)),
+            _ => self.as_slice().unwrap().get(i).copied(),
+        }
+    }
+
+    // zero-based index of prime p in the table
+    pub fn index_of(&self, p: u32) -> Result<u32, PrimeIndexError> {
+        let found = match &self.storage {
+            PrimeStorage::Bitmap { index, .. } => index.index_of(p),
+            _ => self.as_slice().unwrap().binary_search(&p).ok().map(|ix| ix as u32),
+        };
+        found.ok_or(PrimeIndexError::NotInList)
+    }
+
+    // number of primes <= x, x must be <= upper_bound
+    pub fn pi(&self, x: u32) -> Result<u32, PrimeIndexError> {
+        if x > self.upper_bound {
+            return Err(PrimeIndexError::OutOfRange);
+        }
+        Ok(match &self.storage {
+            PrimeStorage::Bitmap { index, .. } => index.pi(x),
+            _ => self.as_slice().unwrap().partition_point(|p| *p <= x) as u32,
+        })
+    }
+
+    // primes with indexes in range, without copying them, None if table is a bitmap
+    pub fn slice(&self, ixs: Range<usize>) -> Option<&[u32]> {
+        self.as_slice().map(|prms| &prms[ixs])
+    }
+
+    // all primes in the table in increasing order
+    pub fn iter(&self) -> PrimeTableIter<'_> {
+        match &self.storage {
+            PrimeStorage::Bitmap { index, .. } => PrimeTableIter::Bitmap(index.iter()),
+            _ => PrimeTableIter::Array(self.as_slice().unwrap().iter()),
+        }
+    }
+}
+
+pub enum PrimeTableIter<'a> {
+    Array(std::slice::Iter<'a, u32>),
+    Bitmap(PrimeBitmapIter<'a>),
+}
+
+impl Iterator for PrimeTableIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            PrimeTableIter::Array(it) => it.next().copied(),
+            PrimeTableIter::Bitmap(it) => it.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::primes::{factor, write_primes_with_layout};
+
+    #[test]
+    pub fn test_from_vec() {
+        assert_eq!(PrimeTable::from_vec([3, 5].to_vec(), 5).err(), Some(PrimeTableErrcode::DoesNotStartAt2));
+        assert_eq!(PrimeTable::from_vec(vec![], 5).err(), Some(PrimeTableErrcode::DoesNotStartAt2));
+        assert_eq!(PrimeTable::from_vec([2, 5, 3].to_vec(), 5).err(), Some(PrimeTableErrcode::NotIncreasing));
+        assert_eq!(PrimeTable::from_vec([2, 3, 5, 7].to_vec(), 6).err(), Some(PrimeTableErrcode::PastUpperBound));
+        let table = PrimeTable::from_vec([2, 3, 5, 7].to_vec(), 10).unwrap();
+        assert_eq!(table.upper_bound(), 10);
+        assert_eq!(table.factorable_up_to(), 100);
+        assert_eq!(table.last(), 7);
+    }
+
+    #[test]
+    pub fn test_constructors() {
+        let table = PrimeTable::up_to(3);
+        assert_eq!(table.as_slice(), Some(&[2, 3, 5][..]));
+        assert_eq!(table.upper_bound(), 5);
+        let prms = gen_primes_up_to(1000000);
+        assert_eq!(PrimeTable::up_to(1000000).as_slice(), Some(prms.as_slice()));
+        assert_eq!(PrimeTable::parallel_up_to(2, 1000000).as_slice(), Some(prms.as_slice()));
+        let bitmap_table = PrimeTable::from_bitmap_index(PrimeBitmapIndex::from_primes(&prms, 1000000));
+        assert!(bitmap_table.is_bitmap() && !bitmap_table.is_mapped());
+        assert_eq!(bitmap_table.iter().collect::<Vec<u32>>(), prms);
+        assert_eq!(bitmap_table.trial_primes(), PrimeTable::up_to(1000000).trial_primes());
+        assert_eq!(*bitmap_table.trial_primes().last().unwrap(), 65521);
+    }
+
+    #[test]
+    pub fn test_open_mapped() {
+        let prms = gen_primes_up_to(10007);
+        write_primes_with_layout(&prms, 10007, PrimeFileLayout::U32Array).unwrap();
+        let table = PrimeTable::open(10007).unwrap();
+        assert!(table.is_mapped());
+        table.verify().unwrap();
+        assert_eq!(table.as_slice(), Some(prms.as_slice()));
+        assert_eq!(table.nth(0), Some(2));
+        assert_eq!(table.nth(prms.len()), None);
+        assert_eq!(table.index_of(10007), Ok(prms.len() as u32 - 1));
+        assert_eq!(table.index_of(10006), Err(PrimeIndexError::NotInList));
+        assert_eq!(table.slice(1..4), Some(&[3, 5, 7][..]));
+        assert_eq!(table.pi(10006), Ok(prms.len() as u32 - 1));
+        assert_eq!(table.pi(10008), Err(PrimeIndexError::OutOfRange));
+        assert_eq!(table.upper_bound(), 10007);
+
+        let owned = PrimeTable::read(10007).unwrap();
+        assert!(!owned.is_mapped());
+        assert_eq!(owned.as_slice(), Some(prms.as_slice()));
+        assert_eq!(factor(10007 * 3, &table), factor(10007 * 3, &owned));
+    }
+
+    #[test]
+    pub fn test_open_bitmap() {
+        let prms = gen_primes_up_to(10009);
+        write_primes_with_layout(&prms, 10009, PrimeFileLayout::Wheel30Bitmap).unwrap();
+        let table = PrimeTable::open(10009).unwrap();
+        assert!(table.is_mapped() && table.is_bitmap());
+        table.verify().unwrap();
+        assert_eq!(table.as_slice(), None);
+        assert_eq!(table.len(), prms.len());
+        assert_eq!(table.last(), 10009);
+        assert_eq!(table.iter().collect::<Vec<u32>>(), prms);
+        for (i, p) in prms.iter().enumerate() {
+            assert_eq!(table.nth(i), Some(*p));
+            assert_eq!(table.index_of(*p), Ok(i as u32));
+            assert_eq!(table.pi(*p), Ok(i as u32 + 1));
+        }
+        assert_eq!(table.nth(prms.len()), None);
+        assert_eq!(table.index_of(10001), Err(PrimeIndexError::NotInList));
+        assert_eq!(table.pi(10010), Err(PrimeIndexError::OutOfRange));
+
+        let owned = PrimeTable::from_vec(prms.clone(), 10009).unwrap();
+        for n in [2, 97, 10009, 10009 * 13, 99991 * 1009, 10007 * 10009] {
+            assert_eq!(factor(n, &table), factor(n, &owned));
+        }
+    }
+
+    #[test]
+    pub fn test_open_bad_file() {
+        let prms = gen_primes_up_to(10037);
+        write_primes_with_layout(&prms, 10037, PrimeFileLayout::U32Array).unwrap();
+        let pathname = prime_data_pathname(10037);
+        let bytes = std::fs::read(&pathname).unwrap();
+        std::fs::write(&pathname, &bytes[..bytes.len() - 4]).unwrap();
+        let e = PrimeTable::open(10037).err().unwrap();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+        assert!(PrimeTable::open(10039).is_err());
+
+        // a stored rank index is trusted when the file is opened, verify() catches it being wrong
+        let prms = gen_primes_up_to(130001);
+        write_primes_with_layout(&prms, 130001, PrimeFileLayout::Wheel30Bitmap).unwrap();
+        let pathname = prime_data_pathname(130001);
+        let mut bytes = std::fs::read(&pathname).unwrap();
+        // three blocks, so the middle rank does not feed into the prime count
+        let middle_rank_start = bytes.len() - 8;
+        bytes[middle_rank_start] += 1;
+        std::fs::write(&pathname, &bytes).unwrap();
+        let table = PrimeTable::open(130001).unwrap();
+        assert!(table.verify().is_err());
+        // but one that does not start at 0 is refused
+        bytes[middle_rank_start - 4] = 1;
+        std::fs::write(&pathname, &bytes).unwrap();
+        assert!(PrimeTable::open(130001).is_err());
+    }
+}
@@ -1,5 +1,12 @@
-use byteorder::ReadBytesExt;
 use std::time::SystemTime;
+use crate::segmented_sieve::sieve_primes_in_range;
+use crate::wheel::Wheel;
+use crate::prime_table::PrimeTable;
+use crate::uint_width::UintWidth;
+use crate::encode_prime::Factorization;
+use crate::prime_file::{read_prime_file, write_prime_file, PrimeFileLayout, DEFAULT_PRIME_FILE_LAYOUT};
+pub use crate::miller_rabin::{is_prime_u32, is_prime_u64};
+pub use crate::pollard_rho::factor_u64_primes;
 
 const STARTER_PRIMES: [u32; 3] = [2, 3, 5];
 
@@ -23,8 +30,10 @@ pub enum GenPrimesErrcode {
 #[derive(Debug)]
 #[derive(PartialEq)]
 pub enum FactorPrimesErrcode {
-    NotEnoughPrimesToFactorIt,  // we don't have large enough prime number array to prove it is prime
+    NotEnoughPrimesToFactorIt,  // N is not prime, but prime number array is too small to factor it
     NIsBigPrime,  // we proved N is prime but we cannot return its index in prime array
+    BigPrimeFactor,  // N has a prime factor that is too big to have an index in prime array
+    TooWideToFactor,  // N does not fit in a u64
     AlgorithmFailed, // should never get here
 }
 
@@ -50,52 +59,110 @@ pub struct PerThreadRanges {
 #[derive(Debug)]
 #[derive(PartialEq)]
 pub enum PrimeIndexError {
-    NotInList
+    NotInList,
+    OutOfRange,   // number is bigger than upper bound of prime table
 }
 
 // return index of matching prime in primes list
-pub fn index_in_prime_list(k: u32, prms: &[u32]) -> Result<u32, PrimeIndexError> {
-    assert!(!prms.is_empty());
-    match prms[..].binary_search(&k) {
-        Ok(ix) => Ok(ix as u32),
-        Err(_) => Err(PrimeIndexError::NotInList)
-    }
+pub fn index_in_prime_list(k: u32, prms: &PrimeTable) -> Result<u32, PrimeIndexError> {
+    prms.index_of(k)
+}
+
+// number of primes <= x
+// x must be <= prms.upper_bound()
+pub fn pi(x: u32, prms: &PrimeTable) -> Result<u32, PrimeIndexError> {
+    prms.pi(x)
 }
 
-// return true if n is in array of primes
-pub fn is_prime(n: u32, prms: &[u32]) -> bool {
-    index_in_prime_list(n, prms).is_ok()
+// the i'th prime, zero-based, so nth_prime(0) is 2
+pub fn nth_prime(i: u32, prms: &PrimeTable) -> Result<u32, PrimeIndexError> {
+    prms.nth(i as usize).ok_or(PrimeIndexError::OutOfRange)
+}
+
+// return true if n is prime
+// looked up in prms if it is small enough, otherwise use Miller-Rabin
+pub fn is_prime(n: u32, prms: &PrimeTable) -> bool {
+    if n <= prms.upper_bound() {
+        return index_in_prime_list(n, prms).is_ok();
+    }
+    is_prime_u32(n)
 }
 
 // factor any positive integer > 1 into a list of non-decreasing prime indexes
-// prms is an increasing array of primes, cannot be empty
+// prms must cover sqrt(n) to factor every n, and must contain every prime factor of n
+// to return its index
 
-pub fn factor(n: u32, prms: &[u32]) -> Result<Vec<u32>, FactorPrimesErrcode> {
-    let last_prime = *prms.last().unwrap() as u64;
-    if last_prime * last_prime < n as u64 {
+pub fn factor(n: u32, prms: &PrimeTable) -> Result<Vec<u32>, FactorPrimesErrcode> {
+    factor_with_wheel(n, prms, &Wheel::default())
+}
+
+// same as factor() but the wheel primes are divided out first.
+// prms starts with the wheel primes, so their indexes are just their positions
+// in the wheel, and trial division by the rest of prms.trial_primes() starts after them.
+// the index of a prime found by trial division is its position in trial_primes(),
+// the index of a prime cofactor comes from the table's rank index.
+// since smaller primes have already been divided out, we never have to go back
+// to the start of prms after finding a factor, and we can stop at sqrt of
+// whatever is left to factor.
+// if n is too big for prms, we still divide out every prime in prms, and then
+// Miller-Rabin tells us whether what is left is a big prime or a composite that
+// prms cannot factor.
+
+pub fn factor_with_wheel(n: u32, prms: &PrimeTable, wheel: &Wheel) -> Result<Vec<u32>, FactorPrimesErrcode> {
+    let (factors, num_to_factor) = factor_partial_with_wheel(n, prms, wheel);
+    if num_to_factor as u64 > prms.factorable_up_to() && !is_prime_u32(num_to_factor) {
         return Err(FactorPrimesErrcode::NotEnoughPrimesToFactorIt);
     }
+    if factors.is_empty() {
+        // n is 0, 1, or a prime that is bigger than prms.upper_bound()
+        return Err(FactorPrimesErrcode::NIsBigPrime);
+    }
+    if num_to_factor > 1 {
+        // n is composite, but what is left after dividing out prms
+        // is a prime that is bigger than prms.upper_bound()
+        return Err(FactorPrimesErrcode::BigPrimeFactor);
+    }
+    Ok(factors)
+}
+
+// divide every prime in prms out of n, the way factor_with_wheel() does
+// returns the indexes of the primes that divide n, and the cofactor that is left,
+// which is 1 if every prime factor of n is in prms.
+// if prms can factor n (see PrimeTable::factorable_up_to()) the cofactor is 1 or a prime
+
+pub fn factor_partial_with_wheel(n: u32, prms: &PrimeTable, wheel: &Wheel) -> (Vec<u32>, u32) {
     let mut next_prime_index_to_try: usize = 0;
     let mut num_to_factor = n;
     let mut factors: Vec<u32> = vec![];
-    let prms_len = prms.len();
+    let trial_prms = prms.trial_primes();
+    let prms_len = trial_prms.len();
+    for p in wheel.primes() {
+        if next_prime_index_to_try == prms_len {
+            break;
+        }
+        assert_eq!(trial_prms[next_prime_index_to_try], *p);
+        while num_to_factor > 1 && num_to_factor.is_multiple_of(*p) {
+            factors.push(next_prime_index_to_try as u32);
+            num_to_factor /= p;
+        }
+        next_prime_index_to_try += 1;
+    }
     while num_to_factor > 1 {
         if let Ok(i) = index_in_prime_list(num_to_factor, prms) {
             factors.push(i);
-            return Ok(factors);
+            num_to_factor = 1;
+            break;
         }
         let mut next_factor_found = false;
         while next_prime_index_to_try < prms_len {
-            next_factor_found = false;
-            let p: u32 = prms[next_prime_index_to_try];
-            if num_to_factor % p == 0 {
+            let p: u32 = trial_prms[next_prime_index_to_try];
+            if p as u64 * p as u64 > num_to_factor as u64 {
+                break;
+            }
+            if num_to_factor.is_multiple_of(p) {
                 factors.push(next_prime_index_to_try as u32);
                 num_to_factor /= p;
                 next_factor_found = true;
-                next_prime_index_to_try = 0;
-                break;
-            }
-            if p * p > n {
                 break;
             }
             next_prime_index_to_try += 1;
@@ -104,23 +171,110 @@ pub fn factor(n: u32, prms: &[u32]) -> Result<Vec<u32>, FactorPrimesErrcode> {
             break;
         }
     }
-    if (n as f64).sqrt() as u32 > *prms.last().unwrap() {
-        return Err(FactorPrimesErrcode::NotEnoughPrimesToFactorIt);
-    } else if factors.is_empty() {
-        return Err(FactorPrimesErrcode::NIsBigPrime);
-    } else if num_to_factor > 1 {
-        return Err(FactorPrimesErrcode::AlgorithmFailed);
+    (factors, num_to_factor)
+}
+
+// factor a u64 into a list of non-decreasing prime indexes, same as factor() returns,
+// so the result can be passed to encode_prime::encode_factors().
+// values that fit in a u32 use factor(), bigger ones are factored with
+// trial division, Pollard's rho and Miller-Rabin, and then each prime factor
+// is looked up in prms.
+
+pub fn factor_u64(n: u64, prms: &PrimeTable) -> Result<Vec<u32>, FactorPrimesErrcode> {
+    if let Ok(n32) = u32::try_from(n) {
+        return factor(n32, prms);
+    }
+    let prime_factors = factor_u64_primes(n);
+    let mut factors: Vec<u32> = Vec::with_capacity(prime_factors.len());
+    for p in prime_factors.iter() {
+        match u32::try_from(*p).ok().map(|p32| prms.index_of(p32)) {
+            Some(Ok(i)) => factors.push(i),
+            _ if prime_factors.len() == 1 => return Err(FactorPrimesErrcode::NIsBigPrime),
+            _ => return Err(FactorPrimesErrcode::BigPrimeFactor),
+        }
     }
     Ok(factors)
 }
 
+// factor an unsigned integer of any type that the encoding supports (see uint_width.rs)
+// into a list of non-decreasing prime indexes of the same type,
+// which encode_prime::encode_factors() encodes with a width that matches the type.
+// a u128 can only be factored if it fits in a u64
+
+pub fn factor_uint<T: UintWidth>(n: T, prms: &PrimeTable) -> Result<Vec<T>, FactorPrimesErrcode> {
+    let n64 = match u64::try_from(n.to_u128()) {
+        Ok(n64) => n64,
+        Err(_) => return Err(FactorPrimesErrcode::TooWideToFactor),
+    };
+    // index of a prime factor is always less than n, so it fits in T
+    let ixs = factor_u64(n64, prms)?;
+    Ok(ixs.into_iter().map(|ix| T::from_u128(ix as u128)).collect())
+}
+
+// same as factor_partial_with_wheel() for any unsigned integer type,
+// the cofactor is the product of every prime factor of n that is not in prms.
+// a u128 that does not fit in a u64 is not factored at all
+
+pub fn factor_uint_partial<T: UintWidth>(n: T, prms: &PrimeTable) -> (Vec<T>, T) {
+    let n_u128 = n.to_u128();
+    if let Ok(n32) = u32::try_from(n_u128) {
+        let (ixs, cofactor) = factor_partial_with_wheel(n32, prms, &Wheel::default());
+        return (ixs.into_iter().map(|ix| T::from_u128(ix as u128)).collect(), T::from_u128(cofactor as u128));
+    }
+    let n64 = match u64::try_from(n_u128) {
+        Ok(n64) => n64,
+        Err(_) => return (vec![], n),
+    };
+    let mut ixs: Vec<T> = vec![];
+    let mut cofactor: u128 = 1;
+    for p in factor_u64_primes(n64) {
+        match u32::try_from(p).ok().map(|p32| prms.index_of(p32)) {
+            Some(Ok(i)) => ixs.push(T::from_u128(i as u128)),
+            _ => cofactor *= p as u128,
+        }
+    }
+    (ixs, T::from_u128(cofactor))
+}
+
+// any unsigned integer as a Factorization, which encode_prime::encode_factorization()
+// can encode even when n is 0 or 1 or has prime factors that prms does not contain
+
+pub fn factorization_of<T: UintWidth>(n: T, prms: &PrimeTable) -> Factorization<T> {
+    match n.to_u128() {
+        0 => Factorization::Zero,
+        1 => Factorization::One,
+        _ => {
+            let (ixs, cofactor) = factor_uint_partial(n, prms);
+            if cofactor.to_u128() == 1 {
+                Factorization::Indices(ixs)
+            } else {
+                Factorization::BigCofactor { ixs, cofactor }
+            }
+        }
+    }
+}
+
+// inverse of factorization_of(), prms must contain every prime whose index is in f
+
+pub fn factorization_to_uint<T: UintWidth>(f: &Factorization<T>, prms: &PrimeTable) -> T {
+    let product_of = |ixs: &[T]| -> u128 {
+        ixs.iter().map(|ix| prms.nth(ix.to_u128() as usize).unwrap() as u128).product()
+    };
+    match f {
+        Factorization::Zero => T::from_u128(0),
+        Factorization::One => T::from_u128(1),
+        Factorization::Indices(ixs) => T::from_u128(product_of(ixs)),
+        Factorization::BigCofactor { ixs, cofactor } => T::from_u128(product_of(ixs) * cofactor.to_u128()),
+    }
+}
+
 // convert indexes of prime numbers in prime number array into the primes
 // represented by those indexes
 
-pub fn indices_to_prime_factors(ixs: &Vec<u32>, prms: &[u32]) -> Vec<u32> {
+pub fn indices_to_prime_factors(ixs: &[u32], prms: &PrimeTable) -> Vec<u32> {
     let mut f: Vec<u32> = Vec::with_capacity(ixs.len());
     for ix in ixs {
-        f.push(prms[*ix as usize]);
+        f.push(prms.nth(*ix as usize).unwrap());
     }
     f
 }
@@ -133,27 +287,42 @@ pub fn indices_to_prime_factors(ixs: &Vec<u32>, prms: &[u32]) -> Vec<u32> {
  * lower_bound  - bottom of range in which we compute prime numbers
  * upper_bound  - top of range in which we compute prime numbers
  */
-pub fn gen_primes_in_range(old_prms: &Vec<u32>, primes_up_to: u32, lower_bound: u32, upper_bound: u32) -> Result<Vec<u32>, GenPrimesErrcode>
+pub fn gen_primes_in_range(old_prms: &[u32], primes_up_to: u32, lower_bound: u32, upper_bound: u32) -> Result<Vec<u32>, GenPrimesErrcode>
+{
+    gen_primes_in_range_with_wheel(old_prms, primes_up_to, lower_bound, upper_bound, &Wheel::default())
+}
+
+// same as gen_primes_in_range() but only candidates generated by the wheel are tested
+// see segmented_sieve::sieve_primes_in_range() for a much faster way
+
+pub fn gen_primes_in_range_with_wheel(old_prms: &[u32], primes_up_to: u32, lower_bound: u32, upper_bound: u32, wheel: &Wheel)
+    -> Result<Vec<u32>, GenPrimesErrcode>
 {
     let primes_up_to_u64 = primes_up_to as u64;
     if primes_up_to_u64 * primes_up_to_u64 < upper_bound as u64 {
         Err(GenPrimesErrcode::PrimesNotEnoughForRange)
     } else {
-        let mut candidate = lower_bound;
-        if candidate % 2 == 0 { candidate += 1 };
         let mut new_prms: Vec<u32> = vec![];
-        // since we will never test an even number, we can exclude 2 (prime index 0) in old_prms
-        let old_prms_slice: &[u32] =
-            if old_prms[0] == 2 {
-                &old_prms[1..]
-            } else {
-                &old_prms[0..]
-            };
+        // the wheel primes are the only primes that the wheel never generates
+        for p in wheel.primes() {
+            if lower_bound <= *p && *p <= upper_bound {
+                new_prms.push(*p);
+            }
+        }
+        // a wheel candidate is never divisible by a wheel prime, so we can exclude them in old_prms
+        let old_prms_slice: &[u32] = &old_prms[old_prms.partition_point(|p| wheel.modulus().is_multiple_of(*p))..];
 
-        while candidate <= upper_bound {
+        for candidate in wheel.candidates(lower_bound, upper_bound) {
+            if candidate == 1 {
+                continue;
+            }
             let mut factor_found = false;
             for prime_ref in old_prms_slice {
-                if candidate % *prime_ref == 0 {
+                let p = *prime_ref as u64;
+                if p * p > candidate as u64 {
+                    break;
+                }
+                if candidate.is_multiple_of(*prime_ref) {
                     factor_found = true;
                     break;
                 }
@@ -161,12 +330,6 @@ pub fn gen_primes_in_range(old_prms: &Vec<u32>, primes_up_to: u32, lower_bound:
             if !factor_found {
                 new_prms.push(candidate);
             }
-            // FIXME replace with a sieve algorithm for speed
-            if candidate < u32::MAX - 1 {
-                candidate += 2;  // excludes even numbers
-            } else {
-                break;  // before integer overflow occurs
-            }
         }
         Ok(new_prms)
     }
@@ -189,34 +352,37 @@ pub fn gen_primes_up_to(n: u32) -> Vec<u32> {
     prms
 }
 
-fn prime_data_pathname(last_prime: u32) -> String {
+pub(crate) fn prime_data_pathname(last_prime: u32) -> String {
     use std::env;
     let tmpdir = env::var("PRIME_DIR").unwrap();
     tmpdir + "/primes_up_to_" + last_prime.to_string().as_str()
 }
 
 // write out array of primes to file, returning size of array in u32 words
-pub fn write_primes(prms: &Vec<u32>, upper_bound: u32) -> Result<usize, std::io::Error> {
+// see prime_file.rs for the file format
+pub fn write_primes(prms: &[u32], upper_bound: u32) -> Result<usize, std::io::Error> {
+    write_primes_with_layout(prms, upper_bound, DEFAULT_PRIME_FILE_LAYOUT)
+}
+
+pub fn write_primes_with_layout(prms: &[u32], upper_bound: u32, layout: PrimeFileLayout) -> Result<usize, std::io::Error> {
     use std::fs::File;
     use std::io::Write;
 
     let last_prime = *prms.last().unwrap();
     let fnstr = prime_data_pathname(upper_bound);
-    println!("creating prime array file {} containing {} primes with last prime {}", &fnstr, prms.len(), last_prime);
+    println!("creating prime array file {} layout {:?} containing {} primes with last prime {}",
+             &fnstr, layout, prms.len(), last_prime);
     use std::io::BufWriter;
     match File::create(fnstr) {
         Ok(file_handle) => {
             let mut stream = BufWriter::new(file_handle);
-            match prms.iter().try_for_each(|&x| stream.write_all(&x.to_be_bytes())) {
+            match write_prime_file(&mut stream, prms, upper_bound, layout) {
                 Ok(_) => {
-                    stream.flush().unwrap();
+                    stream.flush()?;
                     Ok(prms.len())
                 }
                 Err(e) => Err(e)
             }
-            /* for p in prms {
-                stream.write_all(&p.to_be_bytes()).unwrap();
-            } */
         }
         Err(e) => {
             panic!("could not create prime file : {:?}", e);
@@ -224,28 +390,22 @@ pub fn write_primes(prms: &Vec<u32>, upper_bound: u32) -> Result<usize, std::io:
     }
 }
 
-// FIXME: fast way to load a u32 array into memory from a file
+// read array of primes written by write_primes()
+// fails with ErrorKind::InvalidData wrapping a prime_file::PrimeFileErrcode
+// if the file is truncated, corrupt, or does not cover upper_bound
 pub fn read_primes(upper_bound: u32) -> Result<Vec<u32>, std::io::Error> {
     use std::fs::File;
     use std::io::BufReader;
-    use byteorder::BigEndian;
 
     let fnstr = prime_data_pathname(upper_bound);
     match File::open(fnstr.clone()) {
         Ok(file_handle) => {
-            const BYTES_PER_U32: u32 = 4;
-            let fsz = file_handle.metadata().unwrap().len();
             let mut stream = BufReader::new(file_handle);
-            let prime_count: usize = (fsz as u32 / BYTES_PER_U32) as usize;
-            let mut prms: Vec<u32> = Vec::with_capacity(prime_count);
-            prms.resize(prime_count, 0);
-            match stream.read_u32_into::<BigEndian>(prms.as_mut_slice()) {
-                Ok(_) => Ok(prms),
-                Err(e) => Err(e)
-            }
+            let (_hdr, prms) = read_prime_file(&mut stream, Some(upper_bound))?;
+            Ok(prms)
         }
         Err(e) => {
-            return Err(e);
+            Err(e)
         }
     }
 }
@@ -280,6 +440,7 @@ pub fn shard_prime_calc(chunks: usize, prime_upper_bound: u32, prime_lower_bound
 
 
 // use multithreading to calculate prime numbers up to 2^32 much faster
+// each thread runs a segmented sieve over its share of the ranges
 
 pub fn parallel_calc_primes(nthreads: usize, highest_candidate: u32) -> Vec<u32> {
     use std::sync::mpsc;
@@ -352,13 +513,13 @@ pub fn parallel_calc_primes(nthreads: usize, highest_candidate: u32) -> Vec<u32>
                     let lower = chunk.lower;
                     let upper = chunk.upper;
                     let before_gen_chunk = SystemTime::now();
-                    println!("thread {} time since start {:?} gen_primes_in_range base {} lower {} upper {}",
+                    println!("thread {} time since start {:?} sieve_primes_in_range base {} lower {} upper {}",
                             i, SystemTime::now().duration_since(before).unwrap(),
                              prime_base_range, lower, upper);
-                    let gen_result = gen_primes_in_range(&prms_clone, prime_base_range, lower, upper);
+                    let gen_result = sieve_primes_in_range(&prms_clone, prime_base_range, lower, upper);
                     let after = SystemTime::now();
                     let duration = after.duration_since(before_gen_chunk).unwrap();
-                    println!("for thread {}, duration of gen_primes_in_range = {:?}", i, duration);
+                    println!("for thread {}, duration of sieve_primes_in_range = {:?}", i, duration);
                     match gen_result {
                         Ok(chunk_prms) => {
                             match tx1.send(chunk_prms) {
@@ -382,8 +543,7 @@ pub fn parallel_calc_primes(nthreads: usize, highest_candidate: u32) -> Vec<u32>
         // collect vectors of primes from all threads
 
         for k in 0..per_thread_range_count {
-            for t in 0..nthreads {
-                let next_receiver = &receive_ends[t];
+            for (t, next_receiver) in receive_ends.iter().enumerate() {
                 let rcv_max_duration = std::time::Duration::from_secs(100);
                 let chunk_rcv_result = next_receiver.recv_timeout(rcv_max_duration);
                 if let Err(e) = chunk_rcv_result {
@@ -408,7 +568,7 @@ pub fn parallel_calc_primes(nthreads: usize, highest_candidate: u32) -> Vec<u32>
 
 // verify that factoring algorithm works for every number in an interval
 
-fn test_factors_in_range(thread_id: String, lo: u32, hi: u32, prms: &[u32]) -> u32 {
+fn test_factors_in_range(thread_id: String, lo: u32, hi: u32, prms: &PrimeTable) -> u32 {
     for k in lo..hi {
         if k % 10000000 == 0 {
             let pct_left = 100.0 * (hi - k) as f32 / (hi - lo) as f32;
@@ -422,7 +582,7 @@ fn test_factors_in_range(thread_id: String, lo: u32, hi: u32, prms: &[u32]) -> u
                 for p in f {
                     prod *= p;
                     match index_in_prime_list(p, prms) {
-                        Ok(ix) => { assert_eq!(prms[ix as usize], p) }
+                        Ok(ix) => { assert_eq!(prms.nth(ix as usize), Some(p)) }
                         Err(e) => {
                             assert_eq!(e, PrimeIndexError::NotInList);
                             println!("prime {} in factor array not found in prms", p);
@@ -442,7 +602,7 @@ fn test_factors_in_range(thread_id: String, lo: u32, hi: u32, prms: &[u32]) -> u
 }
 
 // use multithreading to speed up testing of factoring algorithm
-pub fn parallel_factor_all(biggest_number: u32, nthreads: usize, prms: &[u32]) {
+pub fn parallel_factor_all(biggest_number: u32, nthreads: usize, prms: &PrimeTable) {
     use std::thread;
 
     let factor_threads = nthreads;
@@ -475,11 +635,11 @@ pub fn parallel_factor_all(biggest_number: u32, nthreads: usize, prms: &[u32]) {
 // to do this, we can shard the range of prime numbers and multi-thread the calculation
 // if necessary
 
-pub fn prime_index_ratio_hist(prm_idx_lo: usize, prm_idx_hi: usize, prms: &[u32], hist: &mut Vec<f64>) {
+pub fn prime_index_ratio_hist(prm_idx_lo: usize, prm_idx_hi: usize, prms: &PrimeTable, hist: &mut Vec<f64>) {
     hist.resize(32, 0.0);
     let mut pcount: Vec<u32> = vec![0; 32];
     for (k, prm) in prms.iter().enumerate().take(prm_idx_hi).skip(prm_idx_lo) {
-        let p = *prm as f64;
+        let p = prm as f64;
         let r = k as f64 / p;
         let idx = p.log2() as usize;
         hist[idx] += r;
@@ -506,22 +666,44 @@ pub mod tests {
 
     #[test]
     pub fn test_index_in_prime_list() {
-        let test_prms: Vec<u32> = STARTER_PRIMES.to_vec();
+        let test_prms = PrimeTable::from_vec(STARTER_PRIMES.to_vec(), 6).unwrap();
         assert_eq!(index_in_prime_list(5, &test_prms), Ok(2));
         assert_eq!(index_in_prime_list(4, &test_prms), Err(PrimeIndexError::NotInList));
         assert_eq!(index_in_prime_list(1, &test_prms), Err(PrimeIndexError::NotInList));
     }
 
+    #[test]
+    pub fn test_pi_and_nth_prime() {
+        use crate::prime_index::PrimeBitmapIndex;
+
+        let array_prms = PrimeTable::from_vec(PRIMES_UP_TO_271.to_vec(), 272).unwrap();
+        let bitmap_prms = PrimeTable::from_bitmap_index(PrimeBitmapIndex::from_primes(&PRIMES_UP_TO_271, 272));
+        for test_prms in [&array_prms, &bitmap_prms] {
+            assert_eq!(pi(0, test_prms), Ok(0));
+            assert_eq!(pi(2, test_prms), Ok(1));
+            assert_eq!(pi(100, test_prms), Ok(25));
+            assert_eq!(pi(272, test_prms), Ok(58));
+            assert_eq!(pi(273, test_prms), Err(PrimeIndexError::OutOfRange));
+            assert_eq!(nth_prime(0, test_prms), Ok(2));
+            assert_eq!(nth_prime(24, test_prms), Ok(97));
+            assert_eq!(nth_prime(57, test_prms), Ok(271));
+            assert_eq!(nth_prime(58, test_prms), Err(PrimeIndexError::OutOfRange));
+        }
+    }
+
     #[test]
     pub fn test_is_prime() {
-        let test_prms: Vec<u32> = STARTER_PRIMES.to_vec();
+        let test_prms = PrimeTable::from_vec(STARTER_PRIMES.to_vec(), 6).unwrap();
         assert!(is_prime(2, &test_prms));
         assert!(!is_prime(4, &test_prms));
+        // bigger than the table but small enough to test with it
+        assert!(is_prime(31, &test_prms));
+        assert!(!is_prime(35, &test_prms));
     }
 
     #[test]
     pub fn test_indices_to_prime_factors() {
-        let test_prms: Vec<u32> = PRIMES_UP_TO_271.to_vec();
+        let test_prms = PrimeTable::from_vec(PRIMES_UP_TO_271.to_vec(), 271).unwrap();
         let f = [0, 1, 2].to_vec();
         let fct = indices_to_prime_factors(&f, &test_prms);
         assert_eq!(fct.len(), 3);
@@ -532,26 +714,32 @@ pub mod tests {
     pub fn test_factors() {
         use crate::primes::FactorPrimesErrcode::*;
 
-        let prms: Vec<u32> = PRIMES_UP_TO_271.to_vec();
+        let prms = PrimeTable::from_vec(PRIMES_UP_TO_271.to_vec(), 271).unwrap();
 
-        // must have enough primes so that sqrt(n) <= largest prime
+        // must have enough primes so that sqrt(n) <= largest prime,
+        // unless n is prime or only needs primes we have
 
-        let too_big_to_factor = 271 * 271 + 1;
+        let too_big_to_factor = 277 * 281;
         let rslt = factor(too_big_to_factor, &prms);
         match rslt {
-            Ok(_) => { assert!(false); }
+            Ok(_) => { panic!("expected an error"); }
             Err(e) => { assert_eq!(e, NotEnoughPrimesToFactorIt); }
         };
+        assert_eq!(factor(271 * 271 * 2, &prms), Ok([0, 57, 57].to_vec()));
 
         // n could be prime but larger than any in prms
         let big_prime = 23;
-        let small_prime_list = STARTER_PRIMES.to_vec();
+        let small_prime_list = PrimeTable::from_vec(STARTER_PRIMES.to_vec(), 5).unwrap();
         let rslt2 = factor(big_prime, &small_prime_list);
         match rslt2 {
-            Ok(_) => { assert!(false); }
+            Ok(_) => { panic!("expected an error"); }
             Err(e) => { assert_eq!(e, NIsBigPrime); }
         };
 
+        // composite with a prime cofactor past prms is not a big prime
+        assert_eq!(factor(277, &prms), Err(NIsBigPrime));
+        assert_eq!(factor(2 * 277, &prms), Err(BigPrimeFactor));
+
         // check it for primes that we know of already
 
         for i in 2..272 {
@@ -562,7 +750,7 @@ pub mod tests {
             // test that elements are non-decreasing and prime
             // test that product of these elements is the number being factored, i
             for k in f {
-                let next_prime = prms[k as usize];
+                let next_prime = prms.nth(k as usize).unwrap();
 
                 prod *= next_prime;
 
@@ -575,6 +763,61 @@ pub mod tests {
             }
             assert_eq!(prod, i);
         }
+
+        // table knows there are no primes between 7 and its upper bound,
+        // so it can factor numbers bigger than 7*7
+        let prms_up_to_10 = PrimeTable::from_vec([2, 3, 5, 7].to_vec(), 10).unwrap();
+        assert_eq!(factor(98, &prms_up_to_10), Ok([0, 3, 3].to_vec()));
+        assert_eq!(factor(97, &prms_up_to_10), Err(NIsBigPrime));
+        // too big for the table, but Miller-Rabin can tell primes from composites
+        assert_eq!(factor(101, &prms_up_to_10), Err(NIsBigPrime));
+        assert_eq!(factor(121, &prms_up_to_10), Err(NotEnoughPrimesToFactorIt));
+        assert_eq!(factor(4294967291, &prms_up_to_10), Err(NIsBigPrime));
+        assert_eq!(factor(4294967295, &prms_up_to_10), Err(NotEnoughPrimesToFactorIt));
+
+        // composite with a prime cofactor past the table is not a big prime
+        let prms_up_to_65536 = PrimeTable::up_to(1 << 16);
+        assert_eq!(factor(65537, &prms_up_to_65536), Err(NIsBigPrime));
+        assert_eq!(factor(2 * 65537, &prms_up_to_65536), Err(BigPrimeFactor));
+        assert_eq!(factor(101 * 2, &prms_up_to_10), Err(BigPrimeFactor));
+    }
+
+    #[test]
+    pub fn test_factor_u64() {
+        use crate::primes::FactorPrimesErrcode::*;
+        use crate::encode_prime::{decode_factors, encode_factors};
+
+        let prms = PrimeTable::up_to(1 << 16);
+        assert_eq!(factor_u64(30, &prms), factor(30, &prms));
+        for n in [(1u64 << 31) * 65521, 65521 * 65521 * 65519, 2 * 3 * 5 * 7 * 11 * 13 * 17 * 19 * 23 * 29 * 31 * 37 * 41 * 43 * 47] {
+            let ixs = factor_u64(n, &prms).unwrap();
+            let prime_factors: Vec<u64> = ixs.iter().map(|ix| prms.nth(*ix as usize).unwrap() as u64).collect();
+            assert_eq!(prime_factors, factor_u64_primes(n));
+            assert_eq!(decode_factors::<u32>(&encode_factors(&ixs)), ixs);
+        }
+        assert_eq!(factor_u64(18446744073709551557, &prms), Err(NIsBigPrime));
+        assert_eq!(factor_u64(2 * 4294967291, &prms), Err(BigPrimeFactor));
+    }
+
+    #[test]
+    pub fn test_factor_uint() {
+        use crate::primes::FactorPrimesErrcode::*;
+        use crate::encode_prime::{decode_factors, encode_factors};
+
+        let prms = PrimeTable::up_to(1 << 16);
+        for n in 2..=u16::MAX {
+            let ixs = factor_uint(n, &prms).unwrap();
+            let decoded: Vec<u16> = decode_factors(&encode_factors(&ixs));
+            assert_eq!(decoded, ixs);
+            let p: u32 = indices_to_prime_factors(&decoded.iter().map(|ix| *ix as u32).collect::<Vec<u32>>(), &prms).iter().product();
+            assert_eq!(p, n as u32);
+        }
+        // 2^63 needs an exponent bigger than a u32 encoding allows
+        let ixs = factor_uint(1u64 << 63, &prms).unwrap();
+        assert_eq!(ixs, [0; 63]);
+        assert_eq!(decode_factors::<u64>(&encode_factors(&ixs)), ixs);
+        assert_eq!(factor_uint(6u128, &prms), Ok([0, 1].to_vec()));
+        assert_eq!(factor_uint(1u128 << 64, &prms), Err(TooWideToFactor));
     }
 
     #[test]
@@ -583,7 +826,7 @@ pub mod tests {
         let gen_primes_result = gen_primes_in_range(&old_prms, 271, 1000000, 2000000);
         match gen_primes_result {
             Err(GenPrimesErrcode::PrimesNotEnoughForRange) => {}
-            Ok(_) => { assert!(false); }
+            Ok(_) => { panic!("expected an error"); }
         }
         let lower_bound: u32 = 273;
         let upper_bound: u32 = 1000;
@@ -592,10 +835,10 @@ pub mod tests {
         old_and_new_prms.append(&mut old_prms);
         //println!("primes in range [{}, {}] = {:?}", lower_bound, upper_bound, new_prms);
         old_and_new_prms.append(&mut new_prms);
-        let nonmut_old_and_new = &old_and_new_prms;
+        let nonmut_old_and_new = &PrimeTable::from_vec(old_and_new_prms.clone(), upper_bound).unwrap();
 
-        for j in 0..old_and_new_prms.len() {
-            let next_primes_factors = factor(old_and_new_prms[j], nonmut_old_and_new).unwrap();
+        for (j, next_prime) in old_and_new_prms.iter().enumerate() {
+            let next_primes_factors = factor(*next_prime, nonmut_old_and_new).unwrap();
             assert!(next_primes_factors.len() == 1 && next_primes_factors[0] == j as u32);
         }
     }
@@ -603,20 +846,46 @@ pub mod tests {
     pub fn test_gen_primes_up_to() {
         let prms_up_to_271 = gen_primes_up_to(271);
         assert_eq!(prms_up_to_271, PRIMES_UP_TO_271);
-        let prms_up_to_10000 = gen_primes_up_to(10000);
+        let prms_up_to_10000 = PrimeTable::up_to(10000);
         let mut last_k: u32 = 0;
-        for k in &prms_up_to_10000 {
-            assert!(*k > last_k);
-            last_k = *k;
-            assert!(is_prime(*k, &prms_up_to_10000));
+        for k in prms_up_to_10000.iter() {
+            assert!(k > last_k);
+            last_k = k;
+            assert!(is_prime(k, &prms_up_to_10000));
         }
         assert_eq!(last_k, 9971);
     }
 
+    #[test]
+    pub fn test_with_wheel() {
+        use crate::wheel::WHEEL_MODULI;
+
+        let prms = PrimeTable::up_to(10000);
+        for m in WHEEL_MODULI {
+            let w = Wheel::new(m).unwrap();
+            let from_wheel = gen_primes_in_range_with_wheel(&PRIMES_UP_TO_271, 271, 0, 10000, &w).unwrap();
+            assert_eq!(Some(from_wheel.as_slice()), prms.as_slice());
+            for n in 2..10001 {
+                let ixs = factor_with_wheel(n, &prms, &w).unwrap();
+                assert!(ixs.windows(2).all(|pair| pair[0] <= pair[1]));
+                assert_eq!(indices_to_prime_factors(&ixs, &prms).iter().product::<u32>(), n);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_parallel_calc_primes() {
+        let prms = parallel_calc_primes(4, 1000000);
+        assert_eq!(prms, gen_primes_up_to(1000000));
+    }
+
     #[test]
     pub fn test_read_primes() {
-        write_primes(&PRIMES_UP_TO_271.to_vec(), 271).unwrap();
+        write_primes(&PRIMES_UP_TO_271, 271).unwrap();
         let primes_we_read = read_primes(271).unwrap();
         assert_eq!(primes_we_read, PRIMES_UP_TO_271.to_vec());
+
+        write_primes_with_layout(&PRIMES_UP_TO_271[..57], 270, PrimeFileLayout::U32Array).unwrap();
+        assert_eq!(read_primes(270).unwrap(), PRIMES_UP_TO_271[..57].to_vec());
     }
 }
@@ -0,0 +1,319 @@
+// adaptive binary range coder, the same design as the one in LZMA
+//
+// every bit is coded with a probability that it is 0, kept in a BitModel that moves
+// towards the bits it has seen, so a skewed bit costs much less than 1 bit.
+// the encoder keeps the interval [low, low + range) and narrows it for each bit,
+// shifting out a byte whenever range falls below 2^24. a carry out of low can still
+// change bytes already shifted out, so the last byte and any 0xFF bytes after it
+// are held back (cache, cache_size) until we know whether the carry happens.
+//
+// integers are coded by UintModel as their bit length in a bit tree, then the
+// bits below the leading 1, the most significant few of them modeled by bit length
+// and the rest coded with probability 1/2.
+
+use crate::prime_codec::PrimeStreamErrcode;
+
+const PROB_BITS: u32 = 11;
+const PROB_ONE: u16 = 1 << PROB_BITS;
+const PROB_MOVE_BITS: u32 = 5;
+const RANGE_TOP: u32 = 1 << 24;
+
+// bytes that finishing the encoder pushes out of low, and that the decoder starts with
+const INITIAL_BYTES: usize = 5;
+
+// enough for the bit length of a u32, 0 through 32
+const LENGTH_TREE_BITS: u32 = 6;
+
+// bits below the leading 1 that are modeled, the rest are coded with probability 1/2
+const MODELED_MANTISSA_BITS: u32 = 3;
+
+// probability that the next bit is 0, out of PROB_ONE
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub struct BitModel {
+    prob : u16,
+}
+
+impl Default for BitModel {
+    fn default() -> Self {
+        BitModel { prob: PROB_ONE / 2 }
+    }
+}
+
+pub struct RangeEncoder {
+    low : u64,
+    range : u32,
+    cache : u8,
+    cache_size : u64,
+    out : Vec<u8>,
+}
+
+pub struct RangeDecoder<'a> {
+    range : u32,
+    code : u32,
+    input : &'a [u8],
+    pos : usize,
+    past_end : bool,    // needed a byte after the end of input
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeEncoder {
+    pub fn new() -> Self {
+        RangeEncoder { low: 0, range: u32::MAX, cache: 0, cache_size: 1, out: vec![] }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xFF00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut held_byte = self.cache;
+            loop {
+                self.out.push(held_byte.wrapping_add(carry));
+                held_byte = 0xFF;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00FF_FFFF) << 8;
+    }
+
+    fn normalize(&mut self) {
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    pub fn encode_bit(&mut self, model : &mut BitModel, bit : bool) {
+        let bound = (self.range >> PROB_BITS) * model.prob as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+            model.prob -= model.prob >> PROB_MOVE_BITS;
+        } else {
+            self.range = bound;
+            model.prob += (PROB_ONE - model.prob) >> PROB_MOVE_BITS;
+        }
+        self.normalize();
+    }
+
+    // low n bits of v, most significant first, each with probability 1/2
+    pub fn encode_direct_bits(&mut self, v : u32, n : u32) {
+        for k in (0..n).rev() {
+            self.range >>= 1;
+            if v & (1 << k) != 0 {
+                self.low += self.range as u64;
+            }
+            self.normalize();
+        }
+    }
+
+    // low n bits of v, most significant first, using a tree of 2^n models
+    pub fn encode_bit_tree(&mut self, models : &mut [BitModel], v : u32, n : u32) {
+        assert!(models.len() >= 1 << n);
+        let mut m: usize = 1;
+        for k in (0..n).rev() {
+            let bit = v & (1 << k) != 0;
+            self.encode_bit(&mut models[m], bit);
+            m = (m << 1) | bit as usize;
+        }
+    }
+
+    // push out everything still held in low and return the encoded bytes
+    pub fn finish(mut self) -> Vec<u8> {
+        for _k in 0..INITIAL_BYTES {
+            self.shift_low();
+        }
+        self.out
+    }
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(input : &'a [u8]) -> Self {
+        let mut decoder = RangeDecoder { range: u32::MAX, code: 0, input, pos: 0, past_end: false };
+        for _k in 0..INITIAL_BYTES {
+            decoder.code = (decoder.code << 8) | decoder.next_byte() as u32;
+        }
+        decoder
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        match self.input.get(self.pos) {
+            Some(b) => {
+                self.pos += 1;
+                *b
+            }
+            None => {
+                self.past_end = true;
+                0
+            }
+        }
+    }
+
+    fn normalize(&mut self) {
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte() as u32;
+        }
+    }
+
+    pub fn decode_bit(&mut self, model : &mut BitModel) -> bool {
+        let bound = (self.range >> PROB_BITS) * model.prob as u32;
+        let bit = if self.code < bound {
+            self.range = bound;
+            model.prob += (PROB_ONE - model.prob) >> PROB_MOVE_BITS;
+            false
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            model.prob -= model.prob >> PROB_MOVE_BITS;
+            true
+        };
+        self.normalize();
+        bit
+    }
+
+    pub fn decode_direct_bits(&mut self, n : u32) -> u32 {
+        let mut v: u32 = 0;
+        for _k in 0..n {
+            self.range >>= 1;
+            let bit = self.code >= self.range;
+            if bit {
+                self.code -= self.range;
+            }
+            v = (v << 1) | bit as u32;
+            self.normalize();
+        }
+        v
+    }
+
+    pub fn decode_bit_tree(&mut self, models : &mut [BitModel], n : u32) -> u32 {
+        assert!(models.len() >= 1 << n);
+        let mut m: usize = 1;
+        for _k in 0..n {
+            m = (m << 1) | self.decode_bit(&mut models[m]) as usize;
+        }
+        (m - (1 << n)) as u32
+    }
+
+    // the encoder's bytes ran out before the bits we decoded did, so the input was truncated
+    pub fn check_not_past_end(&self) -> Result<(), PrimeStreamErrcode> {
+        if self.past_end { Err(PrimeStreamErrcode::Truncated) } else { Ok(()) }
+    }
+}
+
+// adaptive model for u32 values of one kind, see top of file
+#[derive(Clone)]
+pub struct UintModel {
+    length_tree : Vec<BitModel>,
+    mantissa_trees : Vec<Vec<BitModel>>,   // one per bit length
+}
+
+impl Default for UintModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UintModel {
+    pub fn new() -> Self {
+        UintModel {
+            length_tree: vec![BitModel::default(); 1 << LENGTH_TREE_BITS],
+            mantissa_trees: vec![vec![BitModel::default(); 1 << MODELED_MANTISSA_BITS]; u32::BITS as usize + 1],
+        }
+    }
+
+    pub fn encode(&mut self, enc : &mut RangeEncoder, v : u32) {
+        let bit_len = u32::BITS - v.leading_zeros();
+        enc.encode_bit_tree(&mut self.length_tree, bit_len, LENGTH_TREE_BITS);
+        if bit_len < 2 {
+            return;   // 0 and 1 are their bit length
+        }
+        let mantissa_bits = bit_len - 1;
+        let modeled_bits = mantissa_bits.min(MODELED_MANTISSA_BITS);
+        let direct_bits = mantissa_bits - modeled_bits;
+        enc.encode_bit_tree(&mut self.mantissa_trees[bit_len as usize], v >> direct_bits, modeled_bits);
+        enc.encode_direct_bits(v, direct_bits);
+    }
+
+    pub fn decode(&mut self, dec : &mut RangeDecoder) -> Result<u32, PrimeStreamErrcode> {
+        let bit_len = dec.decode_bit_tree(&mut self.length_tree, LENGTH_TREE_BITS);
+        if bit_len > u32::BITS {
+            return Err(PrimeStreamErrcode::BadEncoding);
+        }
+        if bit_len < 2 {
+            return Ok(bit_len);
+        }
+        let mantissa_bits = bit_len - 1;
+        let modeled_bits = mantissa_bits.min(MODELED_MANTISSA_BITS);
+        let direct_bits = mantissa_bits - modeled_bits;
+        let top = (1 << modeled_bits) | dec.decode_bit_tree(&mut self.mantissa_trees[bit_len as usize], modeled_bits);
+        let low = dec.decode_direct_bits(direct_bits);
+        Ok((top << direct_bits) | low)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    pub fn test_skewed_bits() {
+        // 1 bit in 64 is a 1, which should cost well under 1 bit each
+        let mut enc = RangeEncoder::new();
+        let mut model = BitModel::default();
+        let bits: Vec<bool> = (0..100000).map(|k| k % 64 == 63).collect();
+        for b in bits.iter() {
+            enc.encode_bit(&mut model, *b);
+        }
+        let encoded = enc.finish();
+        assert!(encoded.len() < 100000 / 8 / 4, "{} bytes", encoded.len());
+        let mut dec = RangeDecoder::new(&encoded);
+        let mut model = BitModel::default();
+        for b in bits.iter() {
+            assert_eq!(dec.decode_bit(&mut model), *b);
+        }
+        assert_eq!(dec.check_not_past_end(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_uint_model() {
+        let mut rng = rand::rng();
+        let mut values: Vec<u32> = (0..3000).collect();
+        values.extend((0..3000).map(|_| rng.random::<u32>()));
+        values.extend([u32::MAX, 1 << 31, 0xFF00_0000, 0x00FF_FFFF]);
+        let mut enc = RangeEncoder::new();
+        let mut model = UintModel::new();
+        for v in values.iter() {
+            model.encode(&mut enc, *v);
+            enc.encode_direct_bits(*v, 32);
+        }
+        let encoded = enc.finish();
+        let mut dec = RangeDecoder::new(&encoded);
+        let mut model = UintModel::new();
+        for v in values.iter() {
+            assert_eq!(model.decode(&mut dec), Ok(*v));
+            assert_eq!(dec.decode_direct_bits(32), *v);
+        }
+        assert_eq!(dec.check_not_past_end(), Ok(()));
+        assert_eq!(dec.pos, encoded.len());
+
+        // a decoder that runs out of input says so
+        let mut dec = RangeDecoder::new(&encoded[..encoded.len() / 2]);
+        let mut model = UintModel::new();
+        for _v in values.iter() {
+            let _ = model.decode(&mut dec);
+            dec.decode_direct_bits(32);
+        }
+        assert_eq!(dec.check_not_past_end(), Err(PrimeStreamErrcode::Truncated));
+    }
+}
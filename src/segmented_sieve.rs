@@ -0,0 +1,129 @@
+// segmented Sieve of Eratosthenes
+// instead of testing every candidate against every base prime,
+// cross off the multiples of each base prime within a segment of the range.
+// the segment is small enough to stay in the CPU cache, so for large ranges
+// this is much faster than trial division in primes::gen_primes_in_range().
+// only odd numbers are represented in a segment, one byte per odd number.
+
+use crate::primes::GenPrimesErrcode;
+
+// number of odd candidates per segment, 32 KB of sieve flags fits in L1 cache
+
+pub const SIEVE_SEGMENT_SIZE: usize = 1 << 15;
+
+/*
+ * sieve_primes_in_range - same contract and output as primes::gen_primes_in_range()
+ * base_prms    - prime numbers from 2 through primes_up_to
+ * primes_up_to - base_prms contains all primes up to this value
+ * lower_bound  - bottom of range in which we compute prime numbers
+ * upper_bound  - top of range in which we compute prime numbers
+ */
+pub fn sieve_primes_in_range(base_prms: &[u32], primes_up_to: u32, lower_bound: u32, upper_bound: u32) -> Result<Vec<u32>, GenPrimesErrcode>
+{
+    sieve_primes_in_range_segmented(base_prms, primes_up_to, lower_bound, upper_bound, SIEVE_SEGMENT_SIZE)
+}
+
+// same as sieve_primes_in_range() but caller chooses the number of odd candidates per segment
+
+pub fn sieve_primes_in_range_segmented(base_prms: &[u32], primes_up_to: u32, lower_bound: u32, upper_bound: u32, segment_size: usize)
+    -> Result<Vec<u32>, GenPrimesErrcode>
+{
+    assert!(segment_size > 0);
+    let primes_up_to_u64 = primes_up_to as u64;
+    if primes_up_to_u64 * primes_up_to_u64 < upper_bound as u64 {
+        return Err(GenPrimesErrcode::PrimesNotEnoughForRange);
+    }
+    let mut new_prms: Vec<u32> = vec![];
+    if lower_bound > upper_bound {
+        return Ok(new_prms);
+    }
+    if lower_bound <= 2 && upper_bound >= 2 {
+        new_prms.push(2);
+    }
+
+    // do all arithmetic in u64 so segments ending at u32::MAX cannot overflow
+
+    let upper = upper_bound as u64;
+    let mut seg_lower = (lower_bound as u64).max(3) | 1;  // first odd candidate
+    let mut sieve: Vec<bool> = vec![false; segment_size];  // true means composite
+
+    // since a segment only contains odd numbers, we can exclude 2 (prime index 0) in base_prms
+    let odd_base_prms: &[u32] = if !base_prms.is_empty() && base_prms[0] == 2 { &base_prms[1..] } else { base_prms };
+
+    while seg_lower <= upper {
+        let seg_upper = upper.min(seg_lower + 2 * (segment_size as u64 - 1));
+        let seg_len = ((seg_upper - seg_lower) / 2 + 1) as usize;
+        sieve[..seg_len].fill(false);
+        for prime_ref in odd_base_prms {
+            let p = *prime_ref as u64;
+            if p * p > seg_upper {
+                break;
+            }
+            // start at p*p, smaller multiples were crossed off by smaller primes,
+            // otherwise at the first odd multiple of p inside this segment
+            let mut multiple = (p * p).max(seg_lower.div_ceil(p) * p);
+            if multiple.is_multiple_of(2) {
+                multiple += p;
+            }
+            let mut k = ((multiple - seg_lower) / 2) as usize;
+            while k < seg_len {
+                sieve[k] = true;
+                k += p as usize;
+            }
+        }
+        for (k, is_composite) in sieve.iter().enumerate().take(seg_len) {
+            if !is_composite {
+                new_prms.push((seg_lower + 2 * k as u64) as u32);
+            }
+        }
+        seg_lower = seg_upper + 2;
+    }
+    Ok(new_prms)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::primes::{gen_primes_in_range, gen_primes_up_to};
+    use crate::primes::tests::PRIMES_UP_TO_271;
+
+    #[test]
+    pub fn test_sieve_primes_in_range() {
+        let base_prms: Vec<u32> = PRIMES_UP_TO_271.to_vec();
+        match sieve_primes_in_range(&base_prms, 271, 1000000, 2000000) {
+            Err(GenPrimesErrcode::PrimesNotEnoughForRange) => {}
+            Ok(_) => { panic!("expected an error"); }
+        }
+        let from_sieve = sieve_primes_in_range(&base_prms, 271, 273, 70000).unwrap();
+        let from_trial_division = gen_primes_in_range(&base_prms, 271, 273, 70000).unwrap();
+        assert_eq!(from_sieve, from_trial_division);
+    }
+
+    #[test]
+    pub fn test_sieve_from_2() {
+        let base_prms: Vec<u32> = [2, 3, 5, 7, 11, 13, 17].to_vec();
+        let from_sieve = sieve_primes_in_range(&base_prms, 17, 0, 271).unwrap();
+        assert_eq!(from_sieve, PRIMES_UP_TO_271.to_vec());
+    }
+
+    #[test]
+    pub fn test_small_segments() {
+        // segment boundaries must not lose or duplicate primes
+        let base_prms: Vec<u32> = PRIMES_UP_TO_271.to_vec();
+        let expected = sieve_primes_in_range(&base_prms, 271, 1001, 50000).unwrap();
+        for segment_size in [1, 2, 7, 64, 1000] {
+            let from_sieve = sieve_primes_in_range_segmented(&base_prms, 271, 1001, 50000, segment_size).unwrap();
+            assert_eq!(from_sieve, expected);
+        }
+    }
+
+    #[test]
+    pub fn test_sieve_top_of_u32() {
+        let base_prms = gen_primes_up_to(1 << 16);
+        let lower = u32::MAX - 2000;
+        let from_sieve = sieve_primes_in_range(&base_prms, 1 << 16, lower, u32::MAX).unwrap();
+        let from_trial_division = gen_primes_in_range(&base_prms, 1 << 16, lower, u32::MAX).unwrap();
+        assert_eq!(from_sieve, from_trial_division);
+        assert_eq!(*from_sieve.last().unwrap(), 4294967291);
+    }
+}
@@ -0,0 +1,272 @@
+// choose an unsigned integer encoding at run time
+// every EncodingUint implementation can append to and read from a DynBitString directly,
+// so encode_prime can pick a different one for each field of the factor encoding.
+// names used by FromStr and Display, K is the Rice or exponential Golomb parameter:
+//   u32 smallint gamma delta omega fibonacci rice:K expgolomb:K
+// u32 and smallint can be followed by their continue offsets, as in u32:1,3 or smallint:none,
+// and are written without them when they are the default ones.
+
+use std::fmt;
+use std::str::FromStr;
+use bitstring::BitString;
+use crate::dyn_bit_string::DynBitString;
+use crate::encoding_elias::*;
+use crate::encoding_fibonacci::{append_fibonacci, read_fibonacci, try_read_fibonacci};
+use crate::encoding_golomb::*;
+use crate::encoding_small_int::*;
+use crate::encoding_u32::*;
+use crate::encoding_uint_trait::{ContinueOffsets, DecodeError};
+use crate::uint_width::UintWidth;
+
+// largest Rice and exponential Golomb parameter that candidate_codes() tries
+pub const MAX_CANDIDATE_PARAMETER: u32 = 8;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone, Copy)]
+pub enum UintCode {
+    U32(ContinueOffsets),        // U32Encoding
+    SmallInt(ContinueOffsets),   // SmallIntEncoding, values must be < 2^T::LEN_BITS
+    EliasGamma,
+    EliasDelta,
+    EliasOmega,
+    Fibonacci,
+    Rice(u32),
+    ExpGolomb(u32),
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum ParseUintCodeErrcode {
+    UnknownCode,
+    BadParameter,    // parameter missing, not a number, or >= 128, or bad continue offsets
+}
+
+impl fmt::Display for ParseUintCodeErrcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid integer code name: {:?}", self)
+    }
+}
+
+impl std::error::Error for ParseUintCodeErrcode {}
+
+impl fmt::Display for UintCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UintCode::U32(offsets) if *offsets == U32_DEFAULT_CONTINUE_OFFSETS => write!(f, "u32"),
+            UintCode::U32(offsets) => write!(f, "u32:{}", offsets),
+            UintCode::SmallInt(offsets) if *offsets == SMALL_INT_DEFAULT_CONTINUE_OFFSETS => write!(f, "smallint"),
+            UintCode::SmallInt(offsets) => write!(f, "smallint:{}", offsets),
+            UintCode::EliasGamma => write!(f, "gamma"),
+            UintCode::EliasDelta => write!(f, "delta"),
+            UintCode::EliasOmega => write!(f, "omega"),
+            UintCode::Fibonacci => write!(f, "fibonacci"),
+            UintCode::Rice(k) => write!(f, "rice:{}", k),
+            UintCode::ExpGolomb(k) => write!(f, "expgolomb:{}", k),
+        }
+    }
+}
+
+impl FromStr for UintCode {
+    type Err = ParseUintCodeErrcode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("u32", offsets)) => return ContinueOffsets::from_str(offsets)
+                .map(UintCode::U32)
+                .map_err(|_| ParseUintCodeErrcode::BadParameter),
+            Some(("smallint", offsets)) => return ContinueOffsets::from_str(offsets)
+                .map(UintCode::SmallInt)
+                .map_err(|_| ParseUintCodeErrcode::BadParameter),
+            _ => {}
+        }
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, k)) => {
+                match u32::from_str(k) {
+                    Ok(k) if k < u128::BITS => (name, Some(k)),
+                    _ => return Err(ParseUintCodeErrcode::BadParameter),
+                }
+            }
+            None => (s, None),
+        };
+        match (name, parameter) {
+            ("u32", None) => Ok(UintCode::U32(U32_DEFAULT_CONTINUE_OFFSETS)),
+            ("smallint", None) => Ok(UintCode::SmallInt(SMALL_INT_DEFAULT_CONTINUE_OFFSETS)),
+            ("gamma", None) => Ok(UintCode::EliasGamma),
+            ("delta", None) => Ok(UintCode::EliasDelta),
+            ("omega", None) => Ok(UintCode::EliasOmega),
+            ("fibonacci", None) => Ok(UintCode::Fibonacci),
+            ("rice", Some(k)) => Ok(UintCode::Rice(k)),
+            ("expgolomb", Some(k)) => Ok(UintCode::ExpGolomb(k)),
+            ("gamma" | "delta" | "omega" | "fibonacci" | "rice" | "expgolomb", _) =>
+                Err(ParseUintCodeErrcode::BadParameter),
+            _ => Err(ParseUintCodeErrcode::UnknownCode),
+        }
+    }
+}
+
+// append v to bs with this code
+pub fn append_code<T: UintWidth>(code: UintCode, bs: &mut DynBitString, v: T) {
+    match code {
+        UintCode::U32(offsets) => append_u32_encoding_with(bs, v, offsets),
+        UintCode::SmallInt(offsets) => append_small_int_encoding_with(bs, v, offsets),
+        UintCode::EliasGamma => append_elias_gamma(bs, v),
+        UintCode::EliasDelta => append_elias_delta(bs, v),
+        UintCode::EliasOmega => append_elias_omega(bs, v),
+        UintCode::Fibonacci => append_fibonacci(bs, v),
+        UintCode::Rice(k) => append_rice(bs, v, k),
+        UintCode::ExpGolomb(k) => append_exp_golomb(bs, v, k),
+    }
+}
+
+// inverse of append_code(), code and T must be the same ones that were appended with
+pub fn read_code<T: UintWidth>(code: UintCode, bs: &DynBitString, bitstring_cursor: &mut usize) -> T {
+    match code {
+        UintCode::U32(offsets) => read_u32_encoding_with(bs, bitstring_cursor, offsets),
+        UintCode::SmallInt(offsets) => read_small_int_encoding_with(bs, bitstring_cursor, offsets),
+        UintCode::EliasGamma => read_elias_gamma(bs, bitstring_cursor),
+        UintCode::EliasDelta => read_elias_delta(bs, bitstring_cursor),
+        UintCode::EliasOmega => read_elias_omega(bs, bitstring_cursor),
+        UintCode::Fibonacci => read_fibonacci(bs, bitstring_cursor),
+        UintCode::Rice(k) => read_rice(bs, bitstring_cursor, k),
+        UintCode::ExpGolomb(k) => read_exp_golomb(bs, bitstring_cursor, k),
+    }
+}
+
+// same as read_code() but safe for untrusted bits, see DecodeError
+pub fn try_read_code<T: UintWidth>(code: UintCode, bs: &DynBitString, bitstring_cursor: &mut usize) -> Result<T, DecodeError> {
+    match code {
+        UintCode::U32(offsets) => try_read_u32_encoding_with(bs, bitstring_cursor, offsets),
+        UintCode::SmallInt(offsets) => try_read_small_int_encoding_with(bs, bitstring_cursor, offsets),
+        UintCode::EliasGamma => try_read_elias_gamma(bs, bitstring_cursor),
+        UintCode::EliasDelta => try_read_elias_delta(bs, bitstring_cursor),
+        UintCode::EliasOmega => try_read_elias_omega(bs, bitstring_cursor),
+        UintCode::Fibonacci => try_read_fibonacci(bs, bitstring_cursor),
+        UintCode::Rice(k) => try_read_rice(bs, bitstring_cursor, k),
+        UintCode::ExpGolomb(k) => try_read_exp_golomb(bs, bitstring_cursor, k),
+    }
+}
+
+// number of bits append_code() would append, or None if the code cannot encode v
+// Rice codes are measured without building them, since they can be very long
+pub fn code_len<T: UintWidth>(code: UintCode, v: T) -> Option<u128> {
+    match code {
+        UintCode::SmallInt(_) if v.to_u128() >= 1 << T::LEN_BITS => None,
+        UintCode::SmallInt(offsets) => Some(small_int_encoding_len(v, offsets) as u128),
+        UintCode::U32(offsets) => Some(u32_encoding_len(v, offsets) as u128),
+        UintCode::Rice(k) => Some(rice_len(v, k)),
+        _ => {
+            let mut bs = DynBitString::null();
+            append_code(code, &mut bs, v);
+            Some(bs.len() as u128)
+        }
+    }
+}
+
+// every code, with Rice and exponential Golomb parameters up to MAX_CANDIDATE_PARAMETER
+// and the default continue offsets, see encoding_optimizer for choosing other ones
+pub fn candidate_codes() -> Vec<UintCode> {
+    let mut codes = vec![UintCode::U32(U32_DEFAULT_CONTINUE_OFFSETS),
+                         UintCode::SmallInt(SMALL_INT_DEFAULT_CONTINUE_OFFSETS), UintCode::EliasGamma,
+                         UintCode::EliasDelta, UintCode::EliasOmega, UintCode::Fibonacci];
+    codes.extend((0..=MAX_CANDIDATE_PARAMETER).map(UintCode::Rice));
+    codes.extend((0..=MAX_CANDIDATE_PARAMETER).map(UintCode::ExpGolomb));
+    codes
+}
+
+// true if code is usable for prime index differences, which can be in the millions.
+// rice:0 is plain unary, so it would take as many bits as the difference
+pub fn is_index_delta_code(code: UintCode) -> bool {
+    code != UintCode::Rice(0)
+}
+
+// candidate_codes() that are usable for prime index differences
+pub fn index_delta_candidate_codes() -> Vec<UintCode> {
+    candidate_codes().into_iter().filter(|code| is_index_delta_code(*code)).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::encoding_uint_trait::ParseContinueOffsetsErrcode;
+
+    #[test]
+    pub fn test_parse_uint_code() {
+        for code in candidate_codes() {
+            assert_eq!(UintCode::from_str(code.to_string().as_str()), Ok(code));
+        }
+        assert_eq!(UintCode::from_str("rice"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(UintCode::from_str("rice:128"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(UintCode::from_str("gamma:1"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(UintCode::from_str("huffman"), Err(ParseUintCodeErrcode::UnknownCode));
+        let u32_code = UintCode::U32(ContinueOffsets::from_offsets(&[0, 3]));
+        assert_eq!(u32_code.to_string(), "u32:0,3");
+        assert_eq!(UintCode::from_str("u32:0,3"), Ok(u32_code));
+        assert_eq!(UintCode::from_str("smallint:none"), Ok(UintCode::SmallInt(ContinueOffsets { mask: 0 })));
+        assert_eq!(UintCode::from_str("smallint:x"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(UintCode::from_str("u32:31"), Ok(UintCode::U32(ContinueOffsets::from_offsets(&[31]))));
+        assert_eq!(UintCode::from_str("u32:40"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(UintCode::from_str("u32:0,32"), Err(ParseUintCodeErrcode::BadParameter));
+        assert_eq!(ContinueOffsets::from_str("1,300"), Err(ParseContinueOffsetsErrcode::OffsetTooBig));
+    }
+
+    #[test]
+    pub fn test_append_read_code() {
+        let values: Vec<u32> = (0..32).chain([1000, 65535]).collect();
+        let mut bs = DynBitString::null();
+        for code in candidate_codes() {
+            for v in values.iter().filter(|v| !matches!(code, UintCode::SmallInt(_)) || **v < 32) {
+                let len_before = bs.len();
+                append_code(code, &mut bs, *v);
+                assert_eq!(code_len(code, *v), Some((bs.len() - len_before) as u128), "{} {}", code, v);
+            }
+        }
+        let mut cursor: usize = 0;
+        for code in candidate_codes() {
+            for v in values.iter().filter(|v| !matches!(code, UintCode::SmallInt(_)) || **v < 32) {
+                assert_eq!(read_code::<u32>(code, &bs, &mut cursor), *v, "{}", code);
+            }
+        }
+        assert_eq!(cursor, bs.len());
+        assert_eq!(code_len(UintCode::SmallInt(SMALL_INT_DEFAULT_CONTINUE_OFFSETS), 32u32), None);
+        assert_eq!(code_len(UintCode::Rice(0), u32::MAX), Some(1 << 32));
+
+        // everything but plain unary is fit for prime index differences
+        let index_codes = index_delta_candidate_codes();
+        assert!(!index_codes.contains(&UintCode::Rice(0)));
+        assert!(index_codes.contains(&UintCode::Rice(1)));
+        assert_eq!(index_codes.len(), candidate_codes().len() - 1);
+    }
+
+    #[test]
+    pub fn test_try_read_code() {
+        for code in candidate_codes() {
+            // every prefix of a code is truncated, and the cursor stays put
+            let mut bs = DynBitString::null();
+            append_code(code, &mut bs, 11u16);
+            let mut cursor: usize = 0;
+            assert_eq!(try_read_code::<u16>(code, &bs, &mut cursor), Ok(11), "{}", code);
+            assert_eq!(cursor, bs.len());
+            for len in 0..bs.len() {
+                let mut prefix = DynBitString::null();
+                for k in 0..len {
+                    prefix.append(bs.get(k));
+                }
+                let mut cursor: usize = 0;
+                assert_eq!(try_read_code::<u16>(code, &prefix, &mut cursor), Err(DecodeError::Truncated), "{} {}", code, len);
+                assert_eq!(cursor, 0);
+            }
+
+            // too big for the type it is read as, u32 and smallint have a length field
+            // that fits the type, and rice:0 of a big value is very long
+            if matches!(code, UintCode::U32(_) | UintCode::SmallInt(_)) || code == UintCode::Rice(0) {
+                continue;
+            }
+            let mut bs = DynBitString::null();
+            append_code(code, &mut bs, 100000u32);
+            let mut cursor: usize = 0;
+            assert_eq!(try_read_code::<u16>(code, &bs, &mut cursor), Err(DecodeError::Overflow), "{}", code);
+            assert_eq!(cursor, 0);
+        }
+    }
+}
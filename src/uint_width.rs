@@ -0,0 +1,62 @@
+// unsigned integer types that the encoding pipeline can work with
+// the width of the type decides how many bits are needed to encode
+// the bit length of a value, an exponent or a count of prime factors:
+// a value of BITS bits has bit length - 1 < BITS, at most BITS - 1 prime factors,
+// and no exponent bigger than BITS - 1, so all of these fit in LEN_BITS bits.
+//
+//   type   BITS  LEN_BITS
+//   u16     16      4
+//   u32     32      5
+//   u64     64      6
+//   u128   128      7
+
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Sub};
+
+pub trait UintWidth: Copy + Ord + Debug + Display + Add<Output = Self> + Sub<Output = Self> {
+    const BITS: u32;
+    const LEN_BITS: u32 = u32::BITS - (Self::BITS - 1).leading_zeros();
+    const ZERO: Self;
+
+    fn to_u128(self) -> u128;
+
+    // v must fit in this type
+    fn from_u128(v: u128) -> Self;
+}
+
+macro_rules! impl_uint_width {
+    ($t:ty) => {
+        impl UintWidth for $t {
+            const BITS: u32 = <$t>::BITS;
+            const ZERO: Self = 0;
+
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+
+            fn from_u128(v: u128) -> Self {
+                <$t>::try_from(v).unwrap()
+            }
+        }
+    };
+}
+
+impl_uint_width!(u16);
+impl_uint_width!(u32);
+impl_uint_width!(u64);
+impl_uint_width!(u128);
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_len_bits() {
+        assert_eq!(<u16 as UintWidth>::LEN_BITS, 4);
+        assert_eq!(<u32 as UintWidth>::LEN_BITS, 5);
+        assert_eq!(<u64 as UintWidth>::LEN_BITS, 6);
+        assert_eq!(<u128 as UintWidth>::LEN_BITS, 7);
+        assert_eq!(u16::from_u128(65535), u16::MAX);
+        assert_eq!(u64::MAX.to_u128(), u64::MAX as u128);
+    }
+}
@@ -0,0 +1,139 @@
+// wheel factorization
+// a wheel with modulus M = 2*3*5*... only generates the numbers that are
+// coprime to M, so none of them can have one of the wheel primes as a factor.
+// with M = 30 only 8 of every 30 numbers are candidates (vs 15 when just skipping
+// even numbers), with M = 210 only 48 of every 210, with M = 2310 only 480 of every 2310.
+
+pub const WHEEL_MODULI: [u32; 4] = [6, 30, 210, 2310];
+pub const DEFAULT_WHEEL_MODULUS: u32 = 30;
+
+// the primes that multiply together to make each modulus in WHEEL_MODULI
+const WHEEL_PRIMES: [u32; 5] = [2, 3, 5, 7, 11];
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum WheelErrcode {
+    UnsupportedModulus
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Wheel {
+    modulus: u32,
+    prms: Vec<u32>,       // primes whose product is the modulus
+    residues: Vec<u32>,   // increasing list of values in [1, modulus) coprime to modulus
+}
+
+impl Wheel {
+    pub fn new(modulus: u32) -> Result<Self, WheelErrcode> {
+        let mut product: u32 = 1;
+        let mut prms: Vec<u32> = vec![];
+        for p in WHEEL_PRIMES {
+            product *= p;
+            prms.push(p);
+            if product == modulus {
+                let residues: Vec<u32> = (1..modulus)
+                    .filter(|r| prms.iter().all(|p| !r.is_multiple_of(*p)))
+                    .collect();
+                return Ok(Wheel { modulus, prms, residues });
+            }
+        }
+        Err(WheelErrcode::UnsupportedModulus)
+    }
+
+    pub fn modulus(&self) -> u32 { self.modulus }
+
+    // the wheel primes, these are also the first primes in any prime table
+    pub fn primes(&self) -> &[u32] { &self.prms }
+
+    pub fn residues(&self) -> &[u32] { &self.residues }
+
+    // true if no wheel prime divides n
+    pub fn is_candidate(&self, n: u32) -> bool {
+        self.residues.binary_search(&(n % self.modulus)).is_ok()
+    }
+
+    // iterate over every number in [lower, upper] that is coprime to the modulus
+    // note that 1 is always a candidate
+    pub fn candidates(&self, lower: u32, upper: u32) -> WheelCandidates<'_> {
+        let lower_u64 = lower as u64;
+        let modulus = self.modulus as u64;
+        let base = lower_u64 - lower_u64 % modulus;
+        let residue_index = self.residues.partition_point(|r| base + (*r as u64) < lower_u64);
+        WheelCandidates { wheel: self, base, residue_index, upper: upper as u64 }
+    }
+}
+
+impl Default for Wheel {
+    fn default() -> Self {
+        Wheel::new(DEFAULT_WHEEL_MODULUS).unwrap()
+    }
+}
+
+// arithmetic is done in u64 so that iterating up to u32::MAX cannot overflow
+
+pub struct WheelCandidates<'a> {
+    wheel: &'a Wheel,
+    base: u64,             // multiple of the modulus at or below next candidate
+    residue_index: usize,  // position in residue array of next candidate
+    upper: u64,
+}
+
+impl Iterator for WheelCandidates<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.residue_index == self.wheel.residues.len() {
+            self.base += self.wheel.modulus as u64;
+            self.residue_index = 0;
+        }
+        let candidate = self.base + self.wheel.residues[self.residue_index] as u64;
+        if candidate > self.upper {
+            return None;
+        }
+        self.residue_index += 1;
+        Some(candidate as u32)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_new_wheel() {
+        assert_eq!(Wheel::new(31), Err(WheelErrcode::UnsupportedModulus));
+        let expected_residue_counts = [2, 8, 48, 480];
+        for (m, residue_count) in WHEEL_MODULI.iter().zip(expected_residue_counts) {
+            let w = Wheel::new(*m).unwrap();
+            assert_eq!(w.residues().len(), residue_count);
+            assert_eq!(w.primes().iter().product::<u32>(), *m);
+        }
+        assert_eq!(Wheel::default().primes(), &[2, 3, 5]);
+        assert_eq!(Wheel::default().residues(), &[1, 7, 11, 13, 17, 19, 23, 29]);
+    }
+
+    #[test]
+    pub fn test_candidates() {
+        for m in WHEEL_MODULI {
+            let w = Wheel::new(m).unwrap();
+            for (lower, upper) in [(0u32, 5000u32), (1, 1), (4, 6), (2311, 9999), (500, 100)] {
+                let expected: Vec<u32> = (lower..=upper)
+                    .filter(|n| w.primes().iter().all(|p| !n.is_multiple_of(*p)))
+                    .collect();
+                let got: Vec<u32> = w.candidates(lower, upper).collect();
+                assert_eq!(got, expected);
+                assert!(got.iter().all(|c| w.is_candidate(*c)));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_candidates_top_of_u32() {
+        let w = Wheel::new(210).unwrap();
+        let got: Vec<u32> = w.candidates(u32::MAX - 20, u32::MAX).collect();
+        let expected: Vec<u32> = (u32::MAX - 20..=u32::MAX).filter(|n| w.is_candidate(*n)).collect();
+        assert_eq!(got, expected);
+    }
+}